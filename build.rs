@@ -0,0 +1,16 @@
+use vergen_gitcl::{BuildBuilder, CargoBuilder, Emitter, GitclBuilder, RustcBuilder};
+
+fn main() -> anyhow::Result<()> {
+    // Build info shown by `rommer --version --verbose`
+    Emitter::default()
+        .add_instructions(&BuildBuilder::default().build_date(true).build()?)?
+        .add_instructions(&CargoBuilder::default().target_triple(true).build()?)?
+        .add_instructions(
+            &GitclBuilder::default()
+                .sha(false)
+                .commit_date(true)
+                .build()?,
+        )?
+        .add_instructions(&RustcBuilder::default().semver(true).build()?)?
+        .emit()
+}
@@ -0,0 +1,410 @@
+use crate::utils;
+use anyhow::Context;
+use serde_yaml::{Mapping, Value};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+const UTF8_FLAG: u32 = 1 << 8;
+const NO_STRING: u32 = 0xFFFF_FFFF;
+const TYPE_STRING: u8 = 0x03;
+const TYPE_INT_DEC: u8 = 0x10;
+const TYPE_INT_HEX: u8 = 0x11;
+
+/// What `patch from-apk` needs from an APK's `AndroidManifest.xml`
+#[derive(Debug, Default, PartialEq)]
+pub struct ApkInfo {
+    pub package: String,
+    /// `android:label`, unless it refers to a resource
+    pub label: Option<String>,
+    pub min_sdk: Option<u32>,
+}
+
+/// Creates a patch folder that installs an APK as a system app, with a
+/// `patch.yaml` filled in from its manifest
+pub fn patch_from_apk(
+    apk: &str,
+    target: Option<&str>,
+    privileged: bool,
+    patch_name: Option<&str>,
+) -> anyhow::Result<()> {
+    utils::print_section("📱 CREATING PATCH FROM APK");
+    let apk_path = PathBuf::from(shellexpand::tilde(apk).to_string());
+    let info = read_apk_info(&apk_path)?;
+    utils::print_info(&format!(
+        "📦 {} ({})",
+        info.package,
+        info.label.as_deref().unwrap_or("no label")
+    ));
+    let target = target
+        .map(|t| t.trim_matches('/').to_string())
+        .unwrap_or_else(|| {
+            if privileged {
+                "system/priv-app".to_string()
+            } else {
+                "system/app".to_string()
+            }
+        });
+    let patch_path = PathBuf::from(patch_name.unwrap_or(&info.package));
+    if patch_path.exists() {
+        anyhow::bail!("'{}' already exists", patch_path.display());
+    }
+    let app_dir = patch_path.join(&target).join(&info.package);
+    fs::create_dir_all(&app_dir)
+        .with_context(|| format!("Failed to create '{}'", app_dir.display()))?;
+    let apk_target = app_dir.join(format!("{}.apk", info.package));
+    fs::copy(&apk_path, &apk_target).with_context(|| {
+        format!(
+            "Failed to copy '{}' to '{}'",
+            apk_path.display(),
+            apk_target.display()
+        )
+    })?;
+
+    let name = info.label.clone().unwrap_or_else(|| info.package.clone());
+    let mut manifest = Mapping::new();
+    manifest.insert("name".into(), name.clone().into());
+    manifest.insert("version".into(), "1.0.0".into());
+    manifest.insert(
+        "description".into(),
+        format!("Adds {} ({}) to {}", name, info.package, target).into(),
+    );
+    if let Some(android) = info.min_sdk.and_then(android_version) {
+        manifest.insert("requires_android".into(), format!(">={}", android).into());
+    }
+    let manifest_path = patch_path.join("patch.yaml");
+    fs::write(
+        &manifest_path,
+        serde_yaml::to_string(&Value::Mapping(manifest))?,
+    )
+    .with_context(|| format!("Failed to write '{}'", manifest_path.display()))?;
+    utils::print_success(&format!(
+        "📁 Created patch '{}' with {}",
+        patch_path.display(),
+        apk_target.display()
+    ));
+    Ok(())
+}
+
+/// Reads the package name, label and `minSdkVersion` from the binary
+/// `AndroidManifest.xml` of an APK
+pub fn read_apk_info(apk_path: &Path) -> anyhow::Result<ApkInfo> {
+    let file =
+        File::open(apk_path).with_context(|| format!("Failed to open '{}'", apk_path.display()))?;
+    let mut archive = ZipArchive::new(file)
+        .with_context(|| format!("'{}' is not a valid APK", apk_path.display()))?;
+    let mut data = Vec::new();
+    archive
+        .by_name("AndroidManifest.xml")
+        .with_context(|| format!("'{}' has no AndroidManifest.xml", apk_path.display()))?
+        .read_to_end(&mut data)?;
+    parse_manifest(&data).with_context(|| {
+        format!(
+            "Failed to parse AndroidManifest.xml of '{}'",
+            apk_path.display()
+        )
+    })
+}
+
+fn u16_at(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("Unexpected end of file")?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("Unexpected end of file")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Parses a binary XML (AXML) manifest, reading only the string pool and the
+/// attributes of `<manifest>`, `<uses-sdk>` and `<application>`
+fn parse_manifest(data: &[u8]) -> anyhow::Result<ApkInfo> {
+    if u16_at(data, 0)? != RES_XML_TYPE {
+        anyhow::bail!("Not a binary XML file");
+    }
+    let mut strings = Vec::new();
+    let mut info = ApkInfo::default();
+    let mut offset = usize::from(u16_at(data, 2)?);
+    while offset + 8 <= data.len() {
+        let chunk_type = u16_at(data, offset)?;
+        let header_size = usize::from(u16_at(data, offset + 2)?);
+        let chunk_size = u32_at(data, offset + 4)? as usize;
+        if chunk_size < 8 {
+            anyhow::bail!("Invalid chunk size at offset {}", offset);
+        }
+        match chunk_type {
+            RES_STRING_POOL_TYPE => strings = read_string_pool(data, offset)?,
+            RES_XML_START_ELEMENT_TYPE => {
+                let element = string(&strings, u32_at(data, offset + 20)?);
+                let attr_start = usize::from(u16_at(data, offset + 24)?);
+                let attr_size = usize::from(u16_at(data, offset + 26)?);
+                let attr_count = usize::from(u16_at(data, offset + 28)?);
+                for i in 0..attr_count {
+                    let attr = offset + header_size + attr_start + i * attr_size;
+                    let name = string(&strings, u32_at(data, attr + 4)?);
+                    let raw = u32_at(data, attr + 8)?;
+                    let data_type = *data.get(attr + 15).context("Unexpected end of file")?;
+                    let value = u32_at(data, attr + 16)?;
+                    let text = if raw != NO_STRING {
+                        string(&strings, raw)
+                    } else if data_type == TYPE_STRING {
+                        string(&strings, value)
+                    } else {
+                        None
+                    };
+                    match (element.as_deref(), name.as_deref()) {
+                        (Some("manifest"), Some("package")) => {
+                            info.package = text.unwrap_or_default();
+                        }
+                        (Some("application"), Some("label")) => info.label = text,
+                        (Some("uses-sdk"), Some("minSdkVersion")) => {
+                            info.min_sdk = match data_type {
+                                TYPE_INT_DEC | TYPE_INT_HEX => Some(value),
+                                // Preview SDKs are given by codename
+                                _ => text.and_then(|t| t.parse().ok()),
+                            };
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        offset += chunk_size;
+    }
+    if info.package.is_empty() {
+        anyhow::bail!("The manifest has no package name");
+    }
+    // The package name becomes a folder name, so don't trust it
+    if !is_package_name(&info.package) {
+        anyhow::bail!("Invalid package name '{}'", info.package);
+    }
+    Ok(info)
+}
+
+/// Whether `name` is a valid Android package name: dot-separated segments of
+/// ASCII letters, digits and `_`, each starting with a letter
+fn is_package_name(name: &str) -> bool {
+    name.split('.').all(|segment| {
+        segment.starts_with(|c: char| c.is_ascii_alphabetic())
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+fn string(strings: &[String], index: u32) -> Option<String> {
+    strings.get(index as usize).cloned()
+}
+
+fn read_string_pool(data: &[u8], chunk: usize) -> anyhow::Result<Vec<String>> {
+    let count = u32_at(data, chunk + 8)? as usize;
+    let utf8 = u32_at(data, chunk + 16)? & UTF8_FLAG != 0;
+    let strings_start = chunk + u32_at(data, chunk + 20)? as usize;
+    let header_size = usize::from(u16_at(data, chunk + 2)?);
+    // Each string has a 4-byte offset in the chunk, which bounds the count
+    let chunk_size = u32_at(data, chunk + 4)? as usize;
+    if count > chunk_size.saturating_sub(header_size) / 4 {
+        anyhow::bail!("String pool at offset {} has too many strings", chunk);
+    }
+    let mut strings = Vec::with_capacity(count);
+    for i in 0..count {
+        let start = strings_start + u32_at(data, chunk + header_size + i * 4)? as usize;
+        strings.push(if utf8 {
+            read_utf8(data, start)?
+        } else {
+            read_utf16(data, start)?
+        });
+    }
+    Ok(strings)
+}
+
+/// A UTF-8 pool string: its length in characters and in bytes, each one or
+/// two bytes, then the bytes
+fn read_utf8(data: &[u8], mut offset: usize) -> anyhow::Result<String> {
+    let mut length = || -> anyhow::Result<usize> {
+        let first = usize::from(*data.get(offset).context("Unexpected end of file")?);
+        offset += 1;
+        if first & 0x80 == 0 {
+            return Ok(first);
+        }
+        let second = usize::from(*data.get(offset).context("Unexpected end of file")?);
+        offset += 1;
+        Ok(((first & 0x7F) << 8) | second)
+    };
+    length()?;
+    let len = length()?;
+    let bytes = data
+        .get(offset..offset + len)
+        .context("Unexpected end of file")?;
+    Ok(String::from_utf8_lossy(bytes).to_string())
+}
+
+/// A UTF-16 pool string: its length in code units, one or two `u16`s, then
+/// the code units
+fn read_utf16(data: &[u8], offset: usize) -> anyhow::Result<String> {
+    let first = usize::from(u16_at(data, offset)?);
+    let (len, start) = if first & 0x8000 == 0 {
+        (first, offset + 2)
+    } else {
+        (
+            ((first & 0x7FFF) << 16) | usize::from(u16_at(data, offset + 2)?),
+            offset + 4,
+        )
+    };
+    let units = (0..len)
+        .map(|i| u16_at(data, start + i * 2))
+        .collect::<anyhow::Result<Vec<u16>>>()?;
+    Ok(String::from_utf16_lossy(&units))
+}
+
+/// Android version that introduced an API level
+fn android_version(sdk: u32) -> Option<u32> {
+    Some(match sdk {
+        0..=13 => return None,
+        14..=20 => 4,
+        21..=22 => 5,
+        23 => 6,
+        24..=25 => 7,
+        26..=27 => 8,
+        28 => 9,
+        29 => 10,
+        30 => 11,
+        31..=32 => 12,
+        _ => sdk - 20,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a binary XML with a UTF-16 string pool and one element per
+    /// entry of `elements`: (element name, [(attribute name, value)])
+    fn axml(elements: &[(&str, &[(&str, AttrValue)])]) -> Vec<u8> {
+        let mut strings: Vec<String> = Vec::new();
+        let mut index = |s: &str| -> u32 {
+            match strings.iter().position(|x| x == s) {
+                Some(i) => i as u32,
+                None => {
+                    strings.push(s.to_string());
+                    (strings.len() - 1) as u32
+                }
+            }
+        };
+        let mut body = Vec::new();
+        for (element, attrs) in elements {
+            let name = index(element);
+            let mut chunk = Vec::new();
+            chunk.extend(RES_XML_START_ELEMENT_TYPE.to_le_bytes());
+            chunk.extend(16u16.to_le_bytes());
+            chunk.extend(((36 + attrs.len() * 20) as u32).to_le_bytes());
+            chunk.extend(1u32.to_le_bytes());
+            chunk.extend(NO_STRING.to_le_bytes());
+            chunk.extend(NO_STRING.to_le_bytes());
+            chunk.extend(name.to_le_bytes());
+            chunk.extend(20u16.to_le_bytes());
+            chunk.extend(20u16.to_le_bytes());
+            chunk.extend((attrs.len() as u16).to_le_bytes());
+            chunk.extend([0u8; 6]);
+            for (attr, value) in attrs.iter() {
+                chunk.extend(NO_STRING.to_le_bytes());
+                chunk.extend(index(attr).to_le_bytes());
+                let (raw, data_type, data) = match value {
+                    AttrValue::Str(s) => {
+                        let i = index(s);
+                        (i, TYPE_STRING, i)
+                    }
+                    AttrValue::Int(n) => (NO_STRING, TYPE_INT_DEC, *n),
+                };
+                chunk.extend(raw.to_le_bytes());
+                chunk.extend(8u16.to_le_bytes());
+                chunk.push(0);
+                chunk.push(data_type);
+                chunk.extend(data.to_le_bytes());
+            }
+            body.extend(chunk);
+        }
+        let mut pool_data = Vec::new();
+        let mut offsets = Vec::new();
+        for s in &strings {
+            offsets.push(pool_data.len() as u32);
+            let units: Vec<u16> = s.encode_utf16().collect();
+            pool_data.extend((units.len() as u16).to_le_bytes());
+            for unit in units {
+                pool_data.extend(unit.to_le_bytes());
+            }
+            pool_data.extend([0, 0]);
+        }
+        let strings_start = 28 + offsets.len() * 4;
+        let mut pool = Vec::new();
+        pool.extend(RES_STRING_POOL_TYPE.to_le_bytes());
+        pool.extend(28u16.to_le_bytes());
+        pool.extend(((strings_start + pool_data.len()) as u32).to_le_bytes());
+        pool.extend((strings.len() as u32).to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        pool.extend((strings_start as u32).to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        for offset in offsets {
+            pool.extend(offset.to_le_bytes());
+        }
+        pool.extend(pool_data);
+
+        let mut xml = Vec::new();
+        xml.extend(RES_XML_TYPE.to_le_bytes());
+        xml.extend(8u16.to_le_bytes());
+        xml.extend(((8 + pool.len() + body.len()) as u32).to_le_bytes());
+        xml.extend(pool);
+        xml.extend(body);
+        xml
+    }
+
+    enum AttrValue {
+        Str(&'static str),
+        Int(u32),
+    }
+
+    #[test]
+    fn test_parse_manifest() -> anyhow::Result<()> {
+        let data = axml(&[
+            (
+                "manifest",
+                &[("package", AttrValue::Str("org.fdroid.fdroid"))],
+            ),
+            ("uses-sdk", &[("minSdkVersion", AttrValue::Int(24))]),
+            ("application", &[("label", AttrValue::Str("F-Droid"))]),
+        ]);
+        let info = parse_manifest(&data)?;
+        assert_eq!(
+            info,
+            ApkInfo {
+                package: "org.fdroid.fdroid".to_string(),
+                label: Some("F-Droid".to_string()),
+                min_sdk: Some(24),
+            }
+        );
+        assert_eq!(info.min_sdk.and_then(android_version), Some(7));
+        assert_eq!(android_version(34), Some(14));
+        assert!(parse_manifest(b"not xml").is_err());
+
+        let traversal = axml(&[("manifest", &[("package", AttrValue::Str("../../evil"))])]);
+        assert!(parse_manifest(&traversal).is_err());
+        assert!(!is_package_name("org..fdroid"));
+        assert!(!is_package_name("1org.fdroid"));
+
+        // A string count the pool can't hold is rejected before allocating
+        let mut huge = data.clone();
+        huge[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_manifest(&huge).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use crate::config::Config;
+use crate::utils;
+use anyhow::Context;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tar::{Archive, Builder};
+use walkdir::WalkDir;
+
+/// Writes patch folders and ROMMER.yaml to a `.tar.gz` backup, then reads it
+/// back to check that every patch made it in. Patches are stored under their
+/// configured folder, so extracting the backup into a project restores it.
+pub fn archive_patches(
+    config_path: &str,
+    config: &Config,
+    patches: &[String],
+    all: bool,
+    output: Option<&str>,
+) -> anyhow::Result<()> {
+    utils::print_section("🗄️  ARCHIVING PATCHES");
+    let mut folders = Vec::new();
+    let selected = if all { &config.patches } else { patches };
+    for patch in selected {
+        let path = if all {
+            config.resolve_patch_path(patch)
+        } else {
+            crate::bundle::resolve_patch(config, patch)?
+        };
+        if !path.is_dir() {
+            anyhow::bail!("Patch folder '{}' does not exist", path.display());
+        }
+        folders.push((archive_folder(patch, &path), path));
+    }
+
+    let output_path = PathBuf::from(match output {
+        Some(output) => shellexpand::tilde(output).to_string(),
+        None => format!("rommer-patches-backup-{}.tar.gz", utils::today()),
+    });
+    let files = write_archive(&output_path, Path::new(config_path), &folders)?;
+
+    let names = read_names(&output_path)?;
+    for (folder, _) in &folders {
+        if !names.iter().any(|name| name == &format!("{}/", folder)) {
+            anyhow::bail!(
+                "Archive '{}' is missing patch '{}'",
+                output_path.display(),
+                folder
+            );
+        }
+    }
+    utils::print_success(&format!(
+        "🗄️  Archived {} patches ({} files) to {}",
+        folders.len(),
+        files,
+        output_path.display()
+    ));
+    Ok(())
+}
+
+/// Writes the config as `ROMMER.yaml` and each patch folder under its name
+/// in the archive to a `.tar.gz`, keeping modification times, modes and
+/// symlinks. Returns the number of files and symlinks written.
+fn write_archive(
+    output_path: &Path,
+    config_path: &Path,
+    folders: &[(String, PathBuf)],
+) -> anyhow::Result<usize> {
+    let file = File::create(output_path)
+        .with_context(|| format!("Failed to create archive '{}'", output_path.display()))?;
+    let mut tar = Builder::new(GzEncoder::new(file, Compression::default()));
+    tar.follow_symlinks(false);
+    let mut config = File::open(config_path)
+        .with_context(|| format!("Failed to open '{}'", config_path.display()))?;
+    tar.append_file("ROMMER.yaml", &mut config)?;
+    let mut files = 1;
+    for (folder, path) in folders {
+        for entry in WalkDir::new(path) {
+            let entry = entry?;
+            let rel = crate::rezip::zip_entry_name(entry.path().strip_prefix(path)?);
+            let name = if rel.is_empty() {
+                folder.clone()
+            } else {
+                format!("{}/{}", folder, rel)
+            };
+            let file_type = entry.file_type();
+            if file_type.is_dir() {
+                tar.append_dir(&name, entry.path())?;
+            } else if file_type.is_file() || file_type.is_symlink() {
+                tar.append_path_with_name(entry.path(), &name)
+                    .with_context(|| format!("Failed to archive '{}'", entry.path().display()))?;
+                files += 1;
+            } else {
+                utils::print_warning(&format!(
+                    "Skipping '{}', which is not a file, directory or symlink",
+                    entry.path().display()
+                ));
+            }
+        }
+        utils::print_info(&format!("📁 {}", folder));
+    }
+    tar.into_inner()?.finish()?.flush()?;
+    Ok(files)
+}
+
+/// Path of a patch inside the archive: its configured folder, or just its
+/// name when it is configured outside the project
+fn archive_folder(patch: &str, path: &Path) -> String {
+    let folder = patch.trim_end_matches(['/', '\\']).replace('\\', "/");
+    if Path::new(&folder).is_absolute() || folder.starts_with("..") || folder.starts_with('~') {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or(folder)
+    } else {
+        folder.trim_start_matches("./").to_string()
+    }
+}
+
+/// Entry names of a `.tar.gz`, with a trailing `/` for directories
+fn read_names(path: &Path) -> anyhow::Result<Vec<String>> {
+    let file = File::open(path).with_context(|| format!("Failed to open '{}'", path.display()))?;
+    let mut archive = Archive::new(GzDecoder::new(file));
+    let mut names = Vec::new();
+    for entry in archive
+        .entries()
+        .with_context(|| format!("Failed to read '{}'", path.display()))?
+    {
+        let entry = entry.with_context(|| format!("'{}' is corrupted", path.display()))?;
+        let name = crate::rezip::zip_entry_name(&entry.path()?);
+        let name = name.trim_end_matches('/');
+        names.push(if entry.header().entry_type().is_dir() {
+            format!("{}/", name)
+        } else {
+            name.to_string()
+        });
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_round_trip() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let config = dir.path().join("ROMMER.yaml");
+        fs::write(&config, "device: x\n")?;
+        let patch = dir.path().join("hosts");
+        let long_dir = "x".repeat(120);
+        fs::create_dir_all(patch.join("system/etc").join(&long_dir))?;
+        let hosts = patch.join("system/etc/hosts");
+        fs::write(&hosts, "127.0.0.1 localhost\n")?;
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        File::options()
+            .write(true)
+            .open(&hosts)?
+            .set_modified(mtime)?;
+        fs::write(
+            patch.join("system/etc").join(&long_dir).join("font.ttf"),
+            "ttf",
+        )?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("hosts", patch.join("system/etc/hosts.link"))?;
+
+        let output = dir.path().join("backup.tar.gz");
+        let folders = [("patches/hosts".to_string(), patch)];
+        write_archive(&output, &config, &folders)?;
+
+        let mut names = read_names(&output)?;
+        names.sort();
+        let mut expected = vec![
+            "ROMMER.yaml".to_string(),
+            "patches/hosts/".to_string(),
+            "patches/hosts/system/".to_string(),
+            "patches/hosts/system/etc/".to_string(),
+            "patches/hosts/system/etc/hosts".to_string(),
+            format!("patches/hosts/system/etc/{}/", long_dir),
+            format!("patches/hosts/system/etc/{}/font.ttf", long_dir),
+        ];
+        #[cfg(unix)]
+        expected.push("patches/hosts/system/etc/hosts.link".to_string());
+        expected.sort();
+        assert_eq!(names, expected);
+
+        let restored = dir.path().join("restored");
+        Archive::new(GzDecoder::new(File::open(&output)?)).unpack(&restored)?;
+        let restored_hosts = restored.join("patches/hosts/system/etc/hosts");
+        assert_eq!(fs::metadata(&restored_hosts)?.modified()?, mtime);
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(restored.join("patches/hosts/system/etc/hosts.link"))?,
+            Path::new("hosts")
+        );
+        Ok(())
+    }
+}
@@ -1,26 +1,176 @@
-use clap::{Parser, Subcommand};
+use crate::config::PatchSetMode;
+use crate::steps::Step;
+use crate::utils::LogLevel;
+use clap::{ArgGroup, Parser, Subcommand};
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
 pub struct Args {
     #[command(subcommand)]
-    pub(crate) command: Option<Commands>,
+    pub command: Option<Commands>,
+
+    #[arg(
+        short = 'V',
+        long = "version",
+        help = "Print version (with --verbose, also build info)"
+    )]
+    pub show_version: bool,
 
     #[arg(short, long, default_value = "ROMMER.yaml")]
     pub config: String,
 
+    #[arg(long, help = "Build profile from ROMMER.yaml to apply")]
+    pub profile: Option<String>,
+
     #[arg(short, long, default_value = ".download")]
     pub romzip: String,
 
+    #[arg(
+        long,
+        help = "sha256sum file with the expected hash of the ROM (overrides expected_checksum)"
+    )]
+    pub checksum_file: Option<String>,
+
+    #[arg(long, help = "Directory to create the working directory in")]
+    pub temp_dir: Option<String>,
+
+    #[arg(
+        long,
+        help = "Build in this fixed directory and keep it afterwards (overrides keep_dir)"
+    )]
+    pub keep_dir: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Resume a previous build from this step (requires --work-dir)"
+    )]
+    pub from_step: Option<Step>,
+
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with_all = ["from_step", "resume", "resume_from_patch"],
+        help = "Run only this step in --keep-dir or --work-dir and stop (the earlier steps must have completed there)"
+    )]
+    pub only_step: Option<Step>,
+
+    #[arg(
+        long,
+        conflicts_with = "from_step",
+        help = "Resume patching a kept directory at this patch, skipping the ones before it (requires --keep-dir or --work-dir)"
+    )]
+    pub resume_from_patch: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "from_step",
+        help = "Resume patching a kept directory, skipping the patches it records as applied (requires --keep-dir or --work-dir)"
+    )]
+    pub resume: bool,
+
+    #[arg(
+        long,
+        help = "Work directory to build in, or to resume with --from-step"
+    )]
+    pub work_dir: Option<String>,
+
     #[arg(short, long, help = "Override cleanup setting from config")]
     pub no_cleanup: bool,
 
-    #[arg(short, long, help = "Skip signing the final ROM")]
+    #[arg(
+        short,
+        long,
+        visible_alias = "no-sign",
+        help = "Skip signing the final ROM"
+    )]
     pub skip_signing: bool,
 
+    #[arg(long, help = "Skip uploading the final ROM")]
+    pub no_upload: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Make no network calls: use --romzip, skip uploads and update checks"
+    )]
+    pub offline: bool,
+
+    #[arg(
+        long,
+        help = "Skip rezip and signing, keep the patched directory (implies --no-cleanup)"
+    )]
+    pub no_rezip: bool,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Print detailed debug output (same as --log-level debug)"
+    )]
+    pub verbose: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Minimum level of messages to print and log (default: info)"
+    )]
+    pub log_level: Option<LogLevel>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Also write messages to this file as JSON lines"
+    )]
+    pub log_file: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Only print warnings, errors and the final ROM"
+    )]
+    pub quiet: bool,
+
+    #[arg(long, global = true, help = "Only print errors and the final ROM")]
+    pub silent: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "CI mode: no progress bars or colors, exit code by failure type"
+    )]
+    pub ci: bool,
+
     #[arg(short, long, help = "Running in dry-run mode")]
     pub dry_run: bool,
 
+    #[arg(long, help = "Override the max_patch_count safety limit")]
+    pub max_patches: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "MB",
+        help = "Override the max_patch_size_mb safety limit"
+    )]
+    pub max_patch_size: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Always rezip, even if the output ZIP matches the patched ROM"
+    )]
+    pub force_rezip: bool,
+
+    #[arg(
+        long,
+        help = "Write a JSON build plan to this file instead of building"
+    )]
+    pub plan: Option<String>,
+
+    #[arg(long, help = "Remove a stale build lock left by a dead process")]
+    pub break_lock: bool,
+
     #[arg(
         short,
         long,
@@ -28,6 +178,52 @@ pub struct Args {
         help = "Only apply patches with these tags (comma-separated)"
     )]
     pub tags: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        help = "Abort instead of skipping patches with a bad patch_checksum"
+    )]
+    pub strict_patch_checksums: bool,
+
+    #[arg(
+        long,
+        help = "Abort instead of warning when a pinned patch changed since its pinned_commit"
+    )]
+    pub strict_pins: bool,
+
+    #[arg(
+        long,
+        help = "Abort instead of skipping unsigned patches or patches with an invalid signature"
+    )]
+    pub strict_patch_signatures: bool,
+
+    #[arg(
+        long,
+        help = "Skip patches whose files are all already in the ROM with the same content"
+    )]
+    pub apply_only_new: bool,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Apply these patches first, in this order (comma-separated)"
+    )]
+    pub patch_order: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        help = "YAML file with a list of patch folders, or a patches: key, to use with the configured patches"
+    )]
+    pub patch_set: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "replace",
+        requires = "patch_set",
+        help = "How --patch-set combines with the configured patches"
+    )]
+    pub patch_set_mode: PatchSetMode,
 }
 
 #[derive(Subcommand)]
@@ -42,5 +238,400 @@ pub enum Commands {
             default_value = "my-rom"
         )]
         name: Option<String>,
+        #[arg(long, help = "Pre-fill ROMMER.yaml by inspecting an existing ROM ZIP")]
+        from_existing_rom: Option<String>,
+        #[arg(
+            long,
+            value_name = "USER/REPO",
+            conflicts_with = "from_existing_rom",
+            help = "Start from a GitHub repository with a ROMMER.yaml or a patches/ directory"
+        )]
+        from_github: Option<String>,
+    },
+    /// Check patches for common mistakes
+    Lint {
+        /// ROM ZIP to check deletions against
+        #[arg(
+            long,
+            help = "ROM ZIP to check deletions against (defaults to the downloaded ROM)"
+        )]
+        rom_zip: Option<String>,
+    },
+    /// Print the installed version
+    Version {
+        #[arg(long, help = "Check GitHub for a newer release")]
+        check: bool,
+    },
+    /// Check tool dependencies and environment health
+    Doctor,
+    /// Print the ROMMER_* variables hook scripts get during a build
+    Env {
+        #[arg(long, help = "Print export statements to eval in a shell")]
+        export: bool,
+        #[arg(
+            long,
+            requires = "export",
+            value_parser = ["sh", "fish"],
+            help = "Shell syntax of the export statements (default: sh)"
+        )]
+        format: Option<String>,
+    },
+    /// Check patches for insecure permissions and removed security components
+    Audit,
+    /// Print the file listing the output ZIP would have, without building
+    Simulate,
+    /// Print step timings of the last build
+    Metrics {
+        #[arg(long, help = "Metrics file of another build to compare against")]
+        compare: Option<String>,
+    },
+    /// Inspect and debug ROMMER.yaml files
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Manage and inspect patches
+    Patch {
+        #[command(subcommand)]
+        command: PatchCommands,
+    },
+    /// Check signatures of built ROMs
+    Sign {
+        #[command(subcommand)]
+        command: SignCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SignCommands {
+    /// Verify the signature of a signed ROM ZIP
+    Verify {
+        /// Signed ROM ZIP
+        zip: String,
+        #[arg(
+            long,
+            value_parser = ["apksigner", "jarsigner"],
+            help = "Tool to verify with (default: jarsigner for jarsigner and pkcs11 signing, else apksigner)"
+        )]
+        method: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print a JSON Schema for ROMMER.yaml
+    GenerateSchema,
+    /// Print or write the effective config of an override merged over a base config
+    Merge {
+        /// Base config file
+        base: String,
+        /// Config file merged over the base
+        #[arg(value_name = "OVERRIDE")]
+        override_: String,
+        /// Write the merged config here instead of printing it
+        output: Option<String>,
+        #[arg(long, help = "Check that the merged config is a valid ROMMER.yaml")]
+        validate: bool,
+    },
+    /// Show the semantic differences between two config files
+    Diff {
+        /// Old config file
+        a: String,
+        /// New config file
+        b: String,
+    },
+    /// Encrypt the signing passwords in ROMMER.yaml with AES-256-GCM
+    EncryptSecrets {
+        #[arg(
+            long,
+            default_value = "ROMMER_SECRET_KEY",
+            help = "Environment variable holding the encryption key"
+        )]
+        key_env: String,
+    },
+    /// Set the script run for a hook in ROMMER.yaml
+    AddHook {
+        /// Hook name, e.g. pre-zip
+        event: String,
+        /// Script to run with sh
+        script: String,
+    },
+    /// Add a patch folder to the patches list in ROMMER.yaml
+    AddPatch {
+        /// Patch folder to add
+        patch: String,
+        #[arg(
+            long,
+            value_parser = ["first", "last"],
+            help = "Add the patch at the start or end of the list (default last)"
+        )]
+        position: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "position",
+            help = "Add the patch after this one"
+        )]
+        after: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PatchCommands {
+    /// Show which patches touch the same files
+    CreateConflictMatrix,
+    /// Rename a patch folder and update every reference to it
+    Rename {
+        /// Current patch folder
+        from: String,
+        /// New patch folder
+        to: String,
+    },
+    /// Remove a patch from ROMMER.yaml and drop every reference to it
+    Remove {
+        /// Patch folder to remove
+        patch: String,
+        #[arg(long, help = "Also delete the patch folder")]
+        delete_directory: bool,
+        #[arg(short, long, help = "Preview the changes without writing")]
+        dry_run: bool,
+    },
+    /// Sign a patch folder with an RSA private key
+    Sign {
+        /// Patch folder or name
+        patch: String,
+        #[arg(long, help = "Private key (default: signing.patch_key_path)")]
+        key: Option<String>,
+    },
+    /// Generate a patch.yaml from the files of a patch
+    Manifest {
+        /// Patch folder or name
+        patch: String,
+        #[arg(
+            long,
+            help = "Ask for every field, with the generated value as default"
+        )]
+        interactive: bool,
+    },
+    /// Create a patch that installs an APK as a system app
+    FromApk {
+        /// APK to install
+        apk: String,
+        #[arg(
+            long,
+            conflicts_with = "privileged",
+            help = "Directory to install the app to (default: system/app)"
+        )]
+        target: Option<String>,
+        #[arg(long = "priv", help = "Install to system/priv-app")]
+        privileged: bool,
+        #[arg(long, help = "Patch folder to create (default: the package name)")]
+        patch_name: Option<String>,
+    },
+    /// Create a patch that sets the properties of a key=value file in system/build.prop
+    FromProp {
+        /// File with one key=value property per line
+        prop_file: String,
+        #[arg(long, help = "Patch folder to create (default: the file name)")]
+        patch_name: Option<String>,
+    },
+    /// Deploy a patch file under another path in the ROM, via .rommercp
+    Mv {
+        /// File to move, relative to the patch folder
+        source: String,
+        /// New path in the ROM, relative to the ROM root
+        dest: String,
+        #[arg(long, help = "Patch folder or name containing the file")]
+        patch: String,
+    },
+    /// Re-enable a disabled patch
+    Enable {
+        /// Patch folder to enable
+        patch: String,
+    },
+    /// Disable a patch without removing it from ROMMER.yaml
+    Disable {
+        /// Patch folder to disable
+        patch: String,
+    },
+    /// Expand the glob patterns in a patch's deletion files into the paths they match in a ROM
+    ConvertDeletions {
+        /// Patch folder or name
+        patch: String,
+        /// ROM ZIP to match the patterns against
+        rom_zip: String,
+        #[arg(short, long, help = "Preview the changes without writing")]
+        dry_run: bool,
+    },
+    /// Remove deletion entries that name nothing in a ROM
+    PruneStaleDeletions {
+        /// Patch folder or name
+        patch: String,
+        /// ROM ZIP to check the entries against
+        rom_zip: String,
+        #[arg(long, help = "Write the pruned deletion files (default: only report)")]
+        apply: bool,
+        #[arg(short, long, help = "Only report, even with --apply")]
+        dry_run: bool,
+    },
+    /// Compare the configured patches against the files of a ROM
+    FindConflicts {
+        /// ROM ZIP to compare against
+        rom_zip: String,
+    },
+    /// Apply a single patch to a ROM and run structural checks on the result
+    Test {
+        /// Patch folder to test
+        patch: String,
+        /// ROM ZIP to apply the patch to
+        rom_zip: String,
+    },
+    /// Run only a patch's deletions on a ROM and report what they delete
+    TestDeletion {
+        /// Patch folder whose deletions to test
+        patch: String,
+        /// ROM ZIP to run the deletions on
+        rom_zip: String,
+    },
+    /// Time applying a single patch to a ROM over several runs
+    Benchmark {
+        /// Patch folder to benchmark
+        patch: String,
+        /// ROM ZIP to apply the patch to
+        rom_zip: String,
+        #[arg(long, help = "Number of runs (default: 5)")]
+        runs: Option<u32>,
+    },
+    /// Apply a single patch to an already extracted ROM directory
+    Apply {
+        /// Patch folder to apply
+        patch: String,
+        #[arg(long, help = "Extracted ROM directory to apply the patch to")]
+        to: String,
+        #[arg(short, long, help = "Preview the changes without writing")]
+        dry_run: bool,
+    },
+    /// Package several patches into a single distributable ZIP
+    ExportBundle {
+        /// Patch folders or names to include
+        #[arg(required = true)]
+        patches: Vec<String>,
+        #[arg(short, long, help = "Bundle ZIP to create")]
+        output: String,
+        #[arg(long, help = "Include a ROMMER.yaml listing only the bundled patches")]
+        include_config: bool,
+    },
+    /// Back up patches and ROMMER.yaml to a .tar.gz
+    Archive {
+        /// Patch folders or names to archive
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        patches: Vec<String>,
+        #[arg(long, help = "Archive every configured patch")]
+        all: bool,
+        #[arg(
+            short,
+            long,
+            help = "Archive to create (default: rommer-patches-backup-<date>.tar.gz)"
+        )]
+        output: Option<String>,
+    },
+    /// Search the name, description, tags and author of every patch
+    Search {
+        /// Text to search for (case-insensitive)
+        query: String,
+        #[arg(
+            long,
+            value_parser = ["name", "description", "tags", "author"],
+            help = "Only search this patch.yaml field"
+        )]
+        field: Option<String>,
+    },
+    /// Check patch files against the file_hashes in patch.yaml
+    Integrity {
+        /// Patch folder or name to check (default: every configured patch)
+        patch: Option<String>,
+    },
+    /// Record the current git commit as pinned_commit in a patch's patch.yaml
+    Pin {
+        /// Patch folder or name
+        patch: String,
+    },
+    /// Print the Merkle tree hash of a patch (with --verbose, of every file too)
+    HashTree {
+        /// Patch folder or name
+        patch: String,
+    },
+    /// Recompute the patch_checksum in a patch's patch.yaml
+    UpdateChecksum {
+        /// Patch folder or name
+        patch: String,
+    },
+    /// Print a Markdown changelog from the changelog in patch.yaml
+    Changelog {
+        /// Patch folder or name (default: every configured patch)
+        patch: Option<String>,
+        #[arg(long, help = "Only show versions newer than this one")]
+        since: Option<String>,
+    },
+    /// Bump a patch's version and add a changelog entry to its patch.yaml
+    NewVersion {
+        /// Patch folder or name
+        patch: String,
+        #[arg(long, help = "New version, greater than the current one (semver)")]
+        version: String,
+        #[arg(long, required = true, help = "Change in this version (repeatable)")]
+        changes: Vec<String>,
+        #[arg(short, long, help = "Print the updated patch.yaml without writing")]
+        dry_run: bool,
+    },
+    /// Write a .rommerdel listing the app folders of the given packages in a ROM
+    ExtractDeletions {
+        /// ROM ZIP to scan
+        rom_zip: String,
+        #[arg(
+            long,
+            required = true,
+            value_delimiter = ',',
+            help = "App package names, or files with one package per line (comma-separated)"
+        )]
+        apps: Vec<String>,
+        #[arg(short, long, help = "File to write (default: print to stdout)")]
+        output: Option<String>,
+    },
+    /// Convert a Magisk module ZIP to a ROMMER patch
+    ImportFromMagisk {
+        /// Magisk module ZIP
+        module_zip: String,
+        #[arg(short, long, help = "Patch folder to create (default: the module id)")]
+        output: Option<String>,
+    },
+    /// Print the depends_on/conflicts_with graph of the configured patches
+    DepsGraph {
+        #[arg(
+            long,
+            value_parser = ["dot", "mermaid"],
+            help = "Graph format (default: dot)"
+        )]
+        format: Option<String>,
+    },
+    /// Move a patch within the patches list in ROMMER.yaml
+    #[command(group(
+        ArgGroup::new("target")
+            .required(true)
+            .args(["before", "after", "position"])
+    ))]
+    Reorder {
+        /// Patch folder to move
+        patch: String,
+        #[arg(long, help = "Move the patch just before this one")]
+        before: Option<String>,
+        #[arg(long, help = "Move the patch just after this one")]
+        after: Option<String>,
+        #[arg(long, help = "Move the patch to this index (0-based)")]
+        position: Option<usize>,
+    },
+    /// Reorder patches in ROMMER.yaml by dependencies and priority
+    Sort {
+        #[arg(short, long, help = "Preview the new order without writing")]
+        dry_run: bool,
     },
 }
@@ -0,0 +1,213 @@
+use crate::config::Config;
+use crate::utils;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Path fragments of components whose removal weakens device security
+const SECURITY_COMPONENTS: [&str; 6] =
+    ["selinux", "sepolicy", "verity", "vbmeta", "avb", "keystore"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warn,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "INFO",
+            Severity::Warn => "WARN",
+            Severity::Critical => "CRITICAL",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Checks every configured patch for permissions and deletions that weaken
+/// the security of the ROM, and fails when a critical finding is reported
+pub fn audit_patches(config: &Config) -> anyhow::Result<()> {
+    utils::print_section("🛡️  AUDITING PATCHES");
+    let mut findings = Vec::new();
+    for patch_folder in &config.patches {
+        let patch_path = config.resolve_patch_path(patch_folder);
+        if !patch_path.is_dir() {
+            utils::print_warning(&format!("Patch folder '{}' does not exist", patch_folder));
+            continue;
+        }
+        for finding in audit_patch(&patch_path, config.android_version)? {
+            findings.push(Finding {
+                message: format!("{}: {}", patch_folder, finding.message),
+                ..finding
+            });
+        }
+    }
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    for finding in &findings {
+        println!("[{}] {}", finding.severity, finding.message);
+    }
+    let critical = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Critical)
+        .count();
+    if critical > 0 {
+        anyhow::bail!("Audit found {} critical issue(s)", critical);
+    }
+    utils::print_success(&format!(
+        "No critical issues in {} patches ({} finding(s))",
+        config.patches.len(),
+        findings.len()
+    ));
+    Ok(())
+}
+
+fn audit_patch(patch_path: &Path, android_version: u32) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut modes = read_modes(patch_path)?;
+    for rel in utils::patch_files(patch_path) {
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        let mode = modes
+            .remove(&rel)
+            .or_else(|| disk_mode(&patch_path.join(&rel)));
+        if let Some(mode) = mode {
+            findings.extend(check_mode(&rel, mode, true));
+        }
+    }
+    // Entries left over name directories or files the patch doesn't add
+    for (path, mode) in modes {
+        if patch_path.join(&path).is_dir() {
+            findings.extend(check_mode(&path, mode, false));
+        } else {
+            findings.push(Finding {
+                severity: Severity::Info,
+                message: format!(".rommerchmod entry '{}' is not a file in the patch", path),
+            });
+        }
+    }
+    for del_file in [".rommerdel", ".rommerfdel"] {
+        let del_path = patch_path.join(del_file);
+        if !del_path.exists() {
+            continue;
+        }
+        for item in utils::read_paths(&del_path, android_version)? {
+            let item = item.to_string_lossy().replace('\\', "/");
+            let lower = item.to_lowercase();
+            if let Some(component) = SECURITY_COMPONENTS.iter().find(|c| lower.contains(*c)) {
+                findings.push(Finding {
+                    severity: Severity::Critical,
+                    message: format!("{} removes '{}' ({})", del_file, item, component),
+                });
+            }
+        }
+    }
+    Ok(findings)
+}
+
+/// Modes from the patch's `.rommerchmod`, one `<octal mode> <path>` per line
+pub fn read_modes(patch_path: &Path) -> anyhow::Result<BTreeMap<String, u32>> {
+    let chmod_path = patch_path.join(".rommerchmod");
+    if !chmod_path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = fs::read_to_string(&chmod_path)
+        .with_context(|| format!("Failed to read '{}'", chmod_path.display()))?;
+    parse_modes(&content).with_context(|| format!("Failed to parse '{}'", chmod_path.display()))
+}
+
+fn parse_modes(content: &str) -> anyhow::Result<BTreeMap<String, u32>> {
+    let mut modes = BTreeMap::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (mode, path) = line
+            .split_once(char::is_whitespace)
+            .with_context(|| format!("Expected '<mode> <path>', got '{}'", line))?;
+        let mode = u32::from_str_radix(mode, 8)
+            .with_context(|| format!("Invalid octal mode '{}'", mode))?;
+        modes.insert(path.trim().trim_end_matches('/').to_string(), mode);
+    }
+    Ok(modes)
+}
+
+/// Mode of a patch file on disk, used when `.rommerchmod` doesn't list it
+#[cfg(unix)]
+fn disk_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .ok()
+        .map(|m| m.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn disk_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+fn check_mode(path: &str, mode: u32, is_file: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut push = |severity, message: String| findings.push(Finding { severity, message });
+    if mode & 0o6000 != 0 {
+        push(
+            Severity::Critical,
+            format!("'{}' has the setuid/setgid bit set ({:04o})", path, mode),
+        );
+    }
+    if mode & 0o002 != 0 {
+        let kind = if is_file { "file" } else { "directory" };
+        push(
+            Severity::Warn,
+            format!("'{}' is a world-writable {} ({:04o})", path, kind, mode),
+        );
+    }
+    if is_file && path.starts_with("system/bin/") && mode & 0o111 == 0 {
+        push(
+            Severity::Warn,
+            format!(
+                "'{}' is in system/bin but not executable ({:04o})",
+                path, mode
+            ),
+        );
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_mode() -> anyhow::Result<()> {
+        let modes = parse_modes(
+            "# modes\n4755 system/bin/su\n0666 system/etc/hosts\n0644 system/bin/tool\n0755 system/bin/ok\n",
+        )?;
+        let severities: Vec<Vec<Severity>> = modes
+            .iter()
+            .map(|(path, mode)| {
+                check_mode(path, *mode, true)
+                    .into_iter()
+                    .map(|f| f.severity)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            severities,
+            [
+                vec![],
+                vec![Severity::Critical],
+                vec![Severity::Warn],
+                vec![Severity::Warn],
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::config::Config;
+use crate::utils;
+use std::path::PathBuf;
+use std::time::Instant;
+use tempfile::tempdir;
+
+/// Runs `patch benchmark` does when `--runs` is not given
+pub const DEFAULT_RUNS: u32 = 5;
+
+/// Summary of the durations of several runs, in seconds
+#[derive(Debug, PartialEq)]
+struct Stats {
+    mean: f64,
+    median: f64,
+    min: f64,
+    max: f64,
+    std_dev: f64,
+}
+
+/// Extracts `rom_zip` once, then applies a single patch to `runs` fresh
+/// copies of it and prints how long applying took
+pub fn benchmark_patch(
+    config: &Config,
+    patch: &str,
+    rom_zip: &str,
+    runs: u32,
+) -> anyhow::Result<()> {
+    utils::print_section("⏱️  BENCHMARKING PATCH");
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+    if !patch_path.is_dir() {
+        anyhow::bail!("Patch folder '{}' does not exist", patch_path.display());
+    }
+    if !rom_path.exists() {
+        anyhow::bail!("ROM '{}' does not exist", rom_path.display());
+    }
+    if runs == 0 {
+        anyhow::bail!("--runs must be at least 1");
+    }
+
+    let tmp_dir = tempdir()?;
+    let extracted = tmp_dir.path().join("rom");
+    crate::unzip::unzip_rom(&rom_path, &extracted, config.validate_extraction, false)?;
+    let strategy =
+        crate::patchmeta::load_patch_meta(&patch_path).and_then(|meta| meta.file_conflict_strategy);
+    let mut times = Vec::new();
+    for run in 1..=runs {
+        // Copying the ROM is not part of the measured time
+        let work_dir = tmp_dir.path().join(format!("run-{}", run));
+        utils::copy_dir_all(&extracted, &work_dir, None, &[], None, false)?;
+        let start = Instant::now();
+        utils::apply_patch(
+            &patch_path,
+            &work_dir,
+            strategy.as_deref(),
+            config,
+            &utils::CopiedFiles::default(),
+            false,
+        )?;
+        let elapsed = start.elapsed().as_secs_f64();
+        std::fs::remove_dir_all(&work_dir)?;
+        utils::print_info(&format!("Run {}/{}: {:.3}s", run, runs, elapsed));
+        times.push(elapsed);
+    }
+    tmp_dir.close()?;
+
+    let stats = stats(&times);
+    utils::print_section("📊 RESULTS");
+    println!("runs:    {}", runs);
+    println!("mean:    {:.3}s", stats.mean);
+    println!("median:  {:.3}s", stats.median);
+    println!("min:     {:.3}s", stats.min);
+    println!("max:     {:.3}s", stats.max);
+    println!("std dev: {:.3}s", stats.std_dev);
+    Ok(())
+}
+
+/// Statistics of a non-empty list of durations. The standard deviation is
+/// that of the population, as every run is measured.
+fn stats(times: &[f64]) -> Stats {
+    let mut sorted = times.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len();
+    let mean = sorted.iter().sum::<f64>() / n as f64;
+    let median = if n.is_multiple_of(2) {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    } else {
+        sorted[n / 2]
+    };
+    let variance = sorted.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / n as f64;
+    Stats {
+        mean,
+        median,
+        min: sorted[0],
+        max: sorted[n - 1],
+        std_dev: variance.sqrt(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            stats(&[4.0, 2.0, 9.0, 5.0, 4.0, 4.0, 7.0, 5.0]),
+            Stats {
+                mean: 5.0,
+                median: 4.5,
+                min: 2.0,
+                max: 9.0,
+                std_dev: 2.0,
+            }
+        );
+        assert_eq!(stats(&[3.0, 1.0, 2.0]).median, 2.0);
+    }
+}
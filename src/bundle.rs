@@ -0,0 +1,163 @@
+use crate::config::Config;
+use crate::patchmeta;
+use crate::utils;
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+pub const MANIFEST_NAME: &str = "bundle-manifest.yaml";
+
+/// Root manifest of a patch bundle
+#[derive(Debug, Serialize)]
+struct BundleManifest {
+    patches: Vec<BundledPatch>,
+}
+
+#[derive(Debug, Serialize)]
+struct BundledPatch {
+    folder: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    /// `file_hashes` of a signed patch, whose `patch.yaml` is bundled as is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_hashes: Option<BTreeMap<String, String>>,
+}
+
+/// Packages patch folders into a single ZIP, one subdirectory per patch,
+/// with a `bundle-manifest.yaml` and optionally a stripped ROMMER.yaml
+pub fn export_bundle(
+    config: &Config,
+    patches: &[String],
+    output: &str,
+    include_config: bool,
+) -> anyhow::Result<()> {
+    utils::print_section("📦 EXPORTING PATCH BUNDLE");
+    let mut folders = Vec::new();
+    for patch in patches {
+        let path = resolve_patch(config, patch)?;
+        let folder = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .with_context(|| format!("Patch '{}' has no folder name", patch))?;
+        if folders.iter().any(|(existing, _)| existing == &folder) {
+            anyhow::bail!("Two patches share the folder name '{}'", folder);
+        }
+        folders.push((folder, path));
+    }
+
+    let output_path = PathBuf::from(shellexpand::tilde(output).to_string());
+    let file = File::create(&output_path)
+        .with_context(|| format!("Failed to create bundle '{}'", output_path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::<()>::default().compression_method(CompressionMethod::Deflated);
+    let mut manifest = BundleManifest {
+        patches: Vec::new(),
+    };
+    for (folder, path) in &folders {
+        utils::print_info(&format!("➕ Adding patch: {}", folder));
+        // A signature covers patch.yaml, so a signed one must not be rewritten
+        let signed = path.join(crate::patchsig::SIGNATURE_FILE).exists();
+        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+            let rel = entry.path().strip_prefix(path)?;
+            let name = crate::rezip::zip_entry_name(&Path::new(folder).join(rel));
+            if !signed && entry.path() == path.join("patch.yaml") {
+                continue;
+            }
+            if entry.file_type().is_file() {
+                zip.start_file(name, options)?;
+                let mut f = File::open(entry.path())?;
+                std::io::copy(&mut f, &mut zip)?;
+            } else if entry.file_type().is_dir() {
+                zip.add_directory(name, options)?;
+            }
+        }
+        let file_hashes = if signed {
+            Some(crate::integrity::file_hashes(path)?.into_iter().collect())
+        } else {
+            zip.start_file(
+                crate::rezip::zip_entry_name(&Path::new(folder).join("patch.yaml")),
+                options,
+            )?;
+            serde_yaml::to_writer(&mut zip, &with_file_hashes(path)?)?;
+            None
+        };
+        let meta = patchmeta::load_patch_meta(path).unwrap_or_default();
+        manifest.patches.push(BundledPatch {
+            folder: folder.clone(),
+            name: meta.name,
+            version: meta.version,
+            author: meta.author,
+            description: meta.description,
+            tags: meta.tags,
+            file_hashes,
+        });
+    }
+    zip.start_file(MANIFEST_NAME, options)?;
+    serde_yaml::to_writer(&mut zip, &manifest)?;
+    if include_config {
+        // Only the patch list, so no credentials or signing settings leave the project
+        zip.start_file("ROMMER.yaml", options)?;
+        let names: Vec<&String> = folders.iter().map(|(folder, _)| folder).collect();
+        serde_yaml::to_writer(
+            &mut zip,
+            &serde_yaml::Mapping::from_iter([("patches".into(), serde_yaml::to_value(names)?)]),
+        )?;
+    }
+    zip.finish()?;
+    utils::print_success(&format!(
+        "📦 Exported {} patch(es) to {}",
+        folders.len(),
+        output_path.display()
+    ));
+    Ok(())
+}
+
+/// The patch's `patch.yaml` (or an empty one) with `file_hashes` filled in,
+/// so consumers can run `rommer patch integrity` on the bundled patch
+fn with_file_hashes(path: &Path) -> anyhow::Result<serde_yaml::Value> {
+    let manifest_path = path.join("patch.yaml");
+    let mut manifest = if manifest_path.exists() {
+        let content = std::fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read '{}'", manifest_path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", manifest_path.display()))?
+    } else {
+        serde_yaml::Value::Mapping(Default::default())
+    };
+    let hashes: BTreeMap<String, String> =
+        crate::integrity::file_hashes(path)?.into_iter().collect();
+    let serde_yaml::Value::Mapping(mapping) = &mut manifest else {
+        anyhow::bail!("'{}' is not a YAML mapping", manifest_path.display());
+    };
+    mapping.insert("file_hashes".into(), serde_yaml::to_value(hashes)?);
+    Ok(manifest)
+}
+
+/// A patch given by path, or by folder or `patch.yaml` name from ROMMER.yaml
+pub fn resolve_patch(config: &Config, patch: &str) -> anyhow::Result<PathBuf> {
+    let path = PathBuf::from(shellexpand::tilde(patch).to_string());
+    if path.is_dir() {
+        return Ok(path);
+    }
+    let metas: Vec<_> = config
+        .patches
+        .iter()
+        .map(|p| patchmeta::load_patch_meta(config.resolve_patch_path(p)))
+        .collect();
+    crate::deps::find_patch(&config.patches, &metas, patch)
+        .map(|i| config.resolve_patch_path(config.patches[i].trim_end_matches('/')))
+        .with_context(|| format!("Patch '{}' not found", patch))
+}
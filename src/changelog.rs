@@ -0,0 +1,150 @@
+use crate::config::{self, Config};
+use crate::patchmeta::{self, ChangelogEntry};
+use anyhow::Context;
+use semver::Version;
+use serde_yaml::{Mapping, Value};
+use std::path::PathBuf;
+
+fn parse_version(version: &str) -> Option<Version> {
+    Version::parse(version.trim_start_matches('v')).ok()
+}
+
+/// Renders changelog entries as Markdown, leaving out versions that are not
+/// newer than `since`. Entries whose version is not semver are always kept.
+pub fn render_changelog(entries: &[ChangelogEntry], since: Option<&Version>) -> String {
+    let mut markdown = String::new();
+    for entry in entries {
+        if let (Some(since), Some(version)) = (since, parse_version(&entry.version))
+            && version <= *since
+        {
+            continue;
+        }
+        markdown.push_str(&format!("## v{}", entry.version.trim_start_matches('v')));
+        if let Some(date) = &entry.date {
+            markdown.push_str(&format!(" ({})", date));
+        }
+        markdown.push('\n');
+        for change in &entry.changes {
+            markdown.push_str(&format!("- {}\n", change));
+        }
+        markdown.push('\n');
+    }
+    markdown
+}
+
+/// Prints the changelog of one patch, or of every configured patch in config
+/// order, as Markdown
+pub fn print_changelog(
+    config: &Config,
+    patch: Option<&str>,
+    since: Option<&str>,
+) -> anyhow::Result<()> {
+    let since = since
+        .map(|v| parse_version(v).with_context(|| format!("Invalid version '{}'", v)))
+        .transpose()?;
+    let patches: Vec<PathBuf> = match patch {
+        Some(patch) => vec![crate::bundle::resolve_patch(config, patch)?],
+        None => config
+            .patches
+            .iter()
+            .map(|p| config.resolve_patch_path(p.trim_end_matches('/')))
+            .collect(),
+    };
+    for patch_path in &patches {
+        let Some(meta) = patchmeta::load_patch_meta(patch_path) else {
+            continue;
+        };
+        let markdown = render_changelog(&meta.changelog.unwrap_or_default(), since.as_ref());
+        if markdown.is_empty() {
+            continue;
+        }
+        let name = meta
+            .name
+            .unwrap_or_else(|| patch_path.to_string_lossy().to_string());
+        print!("# {}\n\n{}", name, markdown);
+    }
+    Ok(())
+}
+
+/// Sets the version in a patch's `patch.yaml` and adds a changelog entry for
+/// it dated today, editing the file in place so its comments are kept. The
+/// new version must be newer than the current one.
+pub fn new_version(
+    config: &Config,
+    patch: &str,
+    version: &str,
+    changes: &[String],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let manifest_path = patch_path.join("patch.yaml");
+    let (content, manifest) = patchmeta::read_manifest(&patch_path)?;
+    let new = parse_version(version).with_context(|| format!("Invalid version '{}'", version))?;
+    let current = manifest.get("version").and_then(|v| match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    });
+    if let Some(current) = &current {
+        let current = parse_version(current)
+            .with_context(|| format!("Current version '{}' is not semver", current))?;
+        if new <= current {
+            anyhow::bail!("Version {} is not newer than the current {}", new, current);
+        }
+    }
+    let mut entry = Mapping::new();
+    entry.insert("version".into(), new.to_string().into());
+    entry.insert("date".into(), crate::utils::today().into());
+    entry.insert("changes".into(), serde_yaml::to_value(changes)?);
+    let updated = config::set_scalar(&content, "version", &new.to_string())?;
+    // Newest first, as `patch changelog` prints them
+    let updated = config::prepend_list_item(&updated, "changelog", &Value::Mapping(entry))
+        .with_context(|| format!("Failed to edit '{}'", manifest_path.display()))?;
+    if dry_run {
+        crate::utils::print_info(&format!(
+            "🔍 DRY RUN: Would write {}:",
+            manifest_path.display()
+        ));
+        print!("{}", updated);
+        return Ok(());
+    }
+    std::fs::write(&manifest_path, updated)
+        .with_context(|| format!("Failed to write '{}'", manifest_path.display()))?;
+    crate::utils::print_success(&format!(
+        "🏷️  {} is now v{} ({} change(s))",
+        patch_path.display(),
+        new,
+        changes.len()
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_changelog_since() {
+        let entries = vec![
+            ChangelogEntry {
+                version: "1.2.0".to_string(),
+                date: Some("2026-03-01".to_string()),
+                changes: vec!["Add dark boot animation".to_string()],
+            },
+            ChangelogEntry {
+                version: "1.1.0".to_string(),
+                date: None,
+                changes: vec!["Fix typo".to_string(), "Faster".to_string()],
+            },
+        ];
+        assert_eq!(
+            render_changelog(&entries, None),
+            "## v1.2.0 (2026-03-01)\n- Add dark boot animation\n\n## v1.1.0\n- Fix typo\n- Faster\n\n"
+        );
+        let since = Version::parse("1.1.0").unwrap();
+        assert_eq!(
+            render_changelog(&entries, Some(&since)),
+            "## v1.2.0 (2026-03-01)\n- Add dark boot animation\n\n"
+        );
+    }
+}
@@ -1,9 +1,20 @@
 use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
 use std::{fs::File, io::Read, path::Path};
+use tokio::io::AsyncReadExt;
+use walkdir::WalkDir;
+
+/// Read size used by `calculate_file_checksum` and the async variant
+const DEFAULT_BUFFER_SIZE: usize = 1024 * 64;
 
 /// Calculates the SHA-256 checksum of a file
 pub fn calculate_file_checksum(path: &Path) -> Result<String> {
+    calculate_file_checksum_streaming(path, DEFAULT_BUFFER_SIZE)
+}
+
+/// Calculates the SHA-256 checksum of a file, reading `buffer_size` bytes at
+/// a time. Larger buffers mean fewer reads for multi-GB ROMs.
+pub fn calculate_file_checksum_streaming(path: &Path, buffer_size: usize) -> Result<String> {
     let mut file = File::open(path).with_context(|| {
         format!(
             "Failed to open file for checksum calculation: {}",
@@ -11,7 +22,7 @@ pub fn calculate_file_checksum(path: &Path) -> Result<String> {
         )
     })?;
     let mut hasher = Sha256::new();
-    let mut buffer = [0; 1024 * 64]; // 64KiB buffer
+    let mut buffer = vec![0; buffer_size.max(1)];
     loop {
         let bytes_read = file
             .read(&mut buffer)
@@ -27,10 +38,128 @@ pub fn calculate_file_checksum(path: &Path) -> Result<String> {
     Ok(format!("{:x}", result))
 }
 
-/// Verify a file's checksum against an expected value
-pub fn verify_checksum(path: &Path, expected: &str) -> Result<bool> {
-    let calculated = calculate_file_checksum(path)?;
-    Ok(calculated.to_lowercase() == expected.to_lowercase())
+/// Calculates the SHA-256 checksum of a file without blocking the async
+/// runtime: chunks are read with Tokio and hashed on a blocking thread
+pub async fn calculate_file_checksum_async(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path).await.with_context(|| {
+        format!(
+            "Failed to open file for checksum calculation: {}",
+            path.display()
+        )
+    })?;
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(4);
+    let hashing = tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = rx.blocking_recv() {
+            hasher.update(&chunk);
+        }
+        format!("{:x}", hasher.finalize())
+    });
+    loop {
+        let mut buffer = vec![0; DEFAULT_BUFFER_SIZE];
+        let bytes_read = file
+            .read(&mut buffer)
+            .await
+            .with_context(|| "Failed to read file during checksum calculation")?;
+        if bytes_read == 0 {
+            break;
+        }
+        buffer.truncate(bytes_read);
+        if tx.send(buffer).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+    hashing.await.context("Checksum calculation task failed")
+}
+
+/// Calculates the SHA-256 checksum of the concatenated contents of every file
+/// in a directory, in sorted path order, excluding `patch.yaml`
+pub fn calculate_directory_checksum(path: &Path) -> Result<String> {
+    let mut files: Vec<_> = WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.path() != path.join("patch.yaml"))
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 1024 * 64];
+    for file_path in files {
+        let mut file = File::open(&file_path).with_context(|| {
+            format!(
+                "Failed to open file for checksum calculation: {}",
+                file_path.display()
+            )
+        })?;
+        loop {
+            let bytes_read = file
+                .read(&mut buffer)
+                .with_context(|| "Failed to read file during checksum calculation")?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Calculates the SHA-256 checksum of a directory tree, covering the relative
+/// path and contents of every file in sorted path order. Top-level entries
+/// named in `exclude` are skipped.
+pub fn calculate_tree_checksum(path: &Path, exclude: &[&str]) -> Result<String> {
+    let mut files: Vec<_> = WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|e| e.depth() != 1 || !exclude.iter().any(|name| e.file_name() == *name))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 1024 * 64];
+    for file_path in files {
+        let rel = file_path.strip_prefix(path).unwrap_or(&file_path);
+        hasher.update(rel.to_string_lossy().replace('\\', "/").as_bytes());
+        hasher.update([0]);
+        let mut file = File::open(&file_path).with_context(|| {
+            format!(
+                "Failed to open file for checksum calculation: {}",
+                file_path.display()
+            )
+        })?;
+        loop {
+            let bytes_read = file
+                .read(&mut buffer)
+                .with_context(|| "Failed to read file during checksum calculation")?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Verify a file's checksum against an expected value, without blocking the
+/// async runtime
+pub async fn verify_checksum(path: &Path, expected: &str) -> Result<bool> {
+    let calculated = calculate_file_checksum_async(path).await?;
+    Ok(calculated.eq_ignore_ascii_case(expected))
+}
+
+/// Finds the hash of `filename` in the contents of a `sha256sum` file, where
+/// each line is `<hash>  <name>`, or `<hash> *<name>` for binary mode. Names
+/// are compared by their last path component.
+pub fn parse_checksum_file(content: &str, filename: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        (name == filename && hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| hash.to_lowercase())
+    })
 }
 
 #[cfg(test)]
@@ -39,15 +168,51 @@ mod tests {
     use std::io::Write;
     use tempfile::NamedTempFile;
 
-    #[test]
-    fn test_checksum_calculation() -> Result<()> {
+    #[tokio::test]
+    async fn test_checksum_calculation() -> Result<()> {
         let mut temp_file = NamedTempFile::new()?;
         temp_file.write_all(b"Hello, ROMMER!")?;
         let expected = "bc25e260ac6d31552dd72a17dc1ef1f38117f136cd6b9ace6ade20a79bdf4790";
         let calculated = calculate_file_checksum(temp_file.path())?;
         assert_eq!(calculated, expected);
-        let verified = verify_checksum(temp_file.path(), expected)?;
+        let verified = verify_checksum(temp_file.path(), expected).await?;
         assert!(verified);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_streaming_and_async_checksums_match() -> Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(&vec![7u8; 200 * 1024])?;
+        let expected = calculate_file_checksum(temp_file.path())?;
+        assert_eq!(
+            calculate_file_checksum_streaming(temp_file.path(), 3)?,
+            expected
+        );
+        assert_eq!(
+            calculate_file_checksum_async(temp_file.path()).await?,
+            expected
+        );
+        assert!(verify_checksum(temp_file.path(), &expected.to_uppercase()).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_checksum_file() {
+        let a = "a".repeat(64);
+        let b = "B".repeat(64);
+        let content = format!(
+            "{}  lineage-22.1-bluejay-signed.zip\n{} *downloads/bluejay_lineageos_22.1.zip\nnot a checksum line\n",
+            a, b
+        );
+        assert_eq!(
+            parse_checksum_file(&content, "lineage-22.1-bluejay-signed.zip"),
+            Some(a)
+        );
+        assert_eq!(
+            parse_checksum_file(&content, "bluejay_lineageos_22.1.zip"),
+            Some("b".repeat(64))
+        );
+        assert_eq!(parse_checksum_file(&content, "other.zip"), None);
+    }
 }
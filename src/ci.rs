@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// Build stage an error is attached to with `.context(...)`, mapped to an
+/// exit code in `--ci` mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Config,
+    Download,
+    Signing,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::Config => "Invalid configuration",
+            Failure::Download => "Download failed",
+            Failure::Signing => "Signing failed",
+        })
+    }
+}
+
+/// Exit code for a failed build in `--ci` mode: 2 for config errors, 3 for
+/// download failures, 4 for signing failures and 1 for anything else
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    match error.downcast_ref::<Failure>() {
+        Some(Failure::Config) => 2,
+        Some(Failure::Download) => 3,
+        Some(Failure::Signing) => 4,
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_exit_code() {
+        let download: anyhow::Result<()> = Err(anyhow::anyhow!("status 404"));
+        let error = download
+            .context(Failure::Download)
+            .context("Failed to build")
+            .unwrap_err();
+        assert_eq!(exit_code(&error), 3);
+        assert_eq!(exit_code(&anyhow::anyhow!("patch failed")), 1);
+    }
+}
@@ -1,46 +1,1041 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::Deserialize;
+use serde_yaml::Value;
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct Config {
+    #[schemars(description = "Device codename, e.g. bluejay")]
     pub device: String,
+    #[schemars(description = "ROM name (lineageos, pixelos, evolutionx) or a direct download URL")]
     pub rom: String,
+    #[schemars(description = "Download retry attempts")]
     pub max_retries: u32,
+    #[schemars(
+        description = "ROM version to download",
+        schema_with = "string_or_number"
+    )]
     pub version: String,
+    #[schemars(description = "Android version of the ROM")]
     pub android_version: u32,
+    #[schemars(
+        description = "Build date of the ROM to download, e.g. 20250614",
+        schema_with = "string_or_number"
+    )]
     pub timestamp: String,
+    #[schemars(description = "ROM variant, e.g. nightly")]
     pub variant: String,
+    #[schemars(description = "Patch folders to apply, in order")]
     pub patches: Vec<String>,
+    #[serde(default)]
+    #[schemars(description = "Patches to skip, by folder path or name")]
+    pub disabled_patches: Vec<String>,
+    #[schemars(description = "Signing configuration; a test signature is used when omitted")]
     pub signing: Option<SigningConfig>,
+    #[schemars(description = "Output configuration")]
     pub output: OutputConfig,
+    #[schemars(description = "SHA-256 checksum of the downloaded ROM")]
     pub expected_checksum: Option<String>,
     #[serde(default = "default_cleanup")]
+    #[schemars(description = "Remove the working directory after the build")]
     pub cleanup: bool,
     #[serde(default)]
+    #[schemars(description = "Delete the downloaded ROM ZIP after a successful build")]
+    pub cleanup_downloads: bool,
+    #[serde(default)]
+    #[schemars(description = "Scripts to run at build stages, keyed by hook name")]
     pub hooks: Hooks,
+    #[schemars(description = "Upload the final ROM to object storage")]
+    pub upload: Option<UploadConfig>,
+    #[serde(default)]
+    #[schemars(description = "Where to send a message when a build finishes or fails")]
+    pub notifications: NotificationsConfig,
+    #[schemars(description = "Directory to create the working directory in (supports ~)")]
+    pub temp_dir: Option<String>,
+    #[schemars(
+        description = "Fixed working directory that is kept after the build, for inspection (supports ~)"
+    )]
+    pub keep_dir: Option<String>,
+    #[schemars(description = "Maximum number of configured patches (default 100)")]
+    pub max_patch_count: Option<usize>,
+    #[schemars(
+        description = "Maximum size in MB of the files of a single patch (default: no limit)"
+    )]
+    pub max_patch_size_mb: Option<u64>,
+    #[serde(default)]
+    #[schemars(description = "Build the output ZIP without extracting the ROM")]
+    pub streaming_build: bool,
+    #[serde(default)]
+    #[schemars(description = "Don't revalidate downloaded ROMs with ETag/Last-Modified")]
+    pub no_cache: bool,
+    #[schemars(
+        description = "User-Agent sent when downloading the ROM (default ROMMER/<version>)"
+    )]
+    pub download_user_agent: Option<String>,
+    #[schemars(description = "Timeout in seconds for each download attempt")]
+    pub download_timeout_secs: Option<u64>,
+    #[serde(default)]
+    #[schemars(
+        description = "Check that the extracted ROM is complete and has an updater-script and system partition"
+    )]
+    pub validate_extraction: bool,
+    #[serde(default = "default_verify_output")]
+    #[schemars(description = "Check that the output ZIP still has an updater-script")]
+    pub verify_output: bool,
+    #[schemars(
+        description = "Abort the build when a patch takes longer than this many seconds to apply"
+    )]
+    pub patch_timeout_secs: Option<u64>,
+    #[serde(default)]
+    #[schemars(
+        description = "Record each applied patch in rommer-provenance.jsonl inside the output ZIP"
+    )]
+    pub generate_provenance: bool,
+    #[serde(default)]
+    #[schemars(
+        description = "Check every patch's patch-signature.bin against patch_public_key, skipping unsigned or invalid patches"
+    )]
+    pub verify_patch_signatures: bool,
+    #[schemars(description = "RSA public key (PEM or DER) that patch signatures are checked with")]
+    pub patch_public_key: Option<String>,
+    #[serde(default = "default_already_compressed_extensions")]
+    #[schemars(
+        description = "File extensions stored without compression when rezipping, because they are already compressed"
+    )]
+    pub already_compressed_extensions: Vec<String>,
+    #[schemars(
+        description = "Named partial configs merged over this one with --profile",
+        with = "Option<HashMap<String, serde_json::Value>>"
+    )]
+    pub profiles: Option<HashMap<String, ProfileOverride>>,
+    #[schemars(description = "Profile used when --profile is not given")]
+    pub default_profile: Option<String>,
+    #[schemars(
+        description = "Base directory for relative patch paths (supports ~, default: the config file's directory)"
+    )]
+    pub patch_dir: Option<String>,
+    /// Directory containing the config file, set by `load_config`
+    #[serde(skip)]
+    pub config_dir: PathBuf,
+    /// `output.filename` expanded once by `load_config`, so every step of a
+    /// build uses the same `{date}` and `{datetime}`
+    #[serde(skip)]
+    pub expanded_filename: Option<String>,
+}
+
+impl Config {
+    /// Resolves a configured patch folder against `patch_dir`, or the
+    /// directory of the config file when it is not set. Absolute paths are
+    /// kept as they are.
+    pub fn resolve_patch_path(&self, patch_folder: &str) -> PathBuf {
+        let patch = PathBuf::from(shellexpand::tilde(patch_folder).to_string());
+        if patch.is_absolute() {
+            return patch;
+        }
+        let base = match &self.patch_dir {
+            Some(dir) => self.config_dir.join(shellexpand::tilde(dir).to_string()),
+            None => self.config_dir.clone(),
+        };
+        base.join(patch)
+    }
+
+    /// `output.filename` with its template variables expanded
+    pub fn output_filename(&self) -> String {
+        self.expanded_filename
+            .clone()
+            .unwrap_or_else(|| self.expand_filename(crate::utils::unix_now()))
+    }
+
+    /// Expands `{device}`, `{version}`, `{timestamp}`, `{variant}` and
+    /// `{android_version}` from the config, and `{date}` (`YYYYMMDD`) and
+    /// `{datetime}` (`YYYYMMDDTHHMMSSZ`, UTC) from `now`. Unknown variables
+    /// are kept.
+    fn expand_filename(&self, now: u64) -> String {
+        let (year, month, day) = crate::utils::civil_date(now / 86_400);
+        [
+            ("{device}", self.device.clone()),
+            ("{version}", self.version.clone()),
+            ("{timestamp}", self.timestamp.clone()),
+            ("{variant}", self.variant.clone()),
+            ("{android_version}", self.android_version.to_string()),
+            ("{date}", format!("{:04}{:02}{:02}", year, month, day)),
+            (
+                "{datetime}",
+                crate::utils::rfc3339(now).replace(['-', ':'], ""),
+            ),
+        ]
+        .iter()
+        .fold(self.output.filename.clone(), |name, (var, value)| {
+            name.replace(var, value)
+        })
+    }
+}
+
+/// Partial config merged over the base config when its profile is selected
+pub type ProfileOverride = Value;
+
+pub fn load_config(path: &str, profile: Option<&str>) -> Result<Config> {
+    with_paths(load_profile(path, profile)?, path)
+}
+
+/// The config as written in ROMMER.yaml, without `--profile` or
+/// `default_profile` applied, for commands that edit its lists
+pub fn load_base_config(path: &str) -> Result<Config> {
+    let config_content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file '{}'", path))?;
+    let config =
+        serde_yaml::from_str(&config_content).with_context(|| "Failed to parse ROMMER.yaml")?;
+    with_paths(config, path)
+}
+
+/// `config` with the fields `load_config` derives from its path filled in
+fn with_paths(mut config: Config, path: &str) -> Result<Config> {
+    config.config_dir = Path::new(path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    config.expanded_filename = Some(config.expand_filename(crate::utils::unix_now()));
+    Ok(config)
+}
+
+fn load_profile(path: &str, profile: Option<&str>) -> Result<Config> {
+    let config_content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file '{}'", path))?;
+    let mut value: Value =
+        serde_yaml::from_str(&config_content).with_context(|| "Failed to parse ROMMER.yaml")?;
+    let base: Config =
+        serde_yaml::from_str(&config_content).with_context(|| "Failed to parse ROMMER.yaml")?;
+    let Some(name) = profile.map(str::to_string).or(base.default_profile.clone()) else {
+        return Ok(base);
+    };
+    let overrides = base
+        .profiles
+        .and_then(|mut profiles| profiles.remove(&name))
+        .with_context(|| format!("Profile '{}' is not defined in ROMMER.yaml", name))?;
+    merge_values(&mut value, overrides);
+    // On stderr, so `eval $(rommer env --export)` still works with a profile
+    crate::utils::eprint_info(&format!("Using profile: {}", name));
+    // Round-trip through text so scalars like `version: 22.2` still parse as strings
+    let merged = serde_yaml::to_string(&value)?;
+    serde_yaml::from_str(&merged).with_context(|| format!("Failed to apply profile '{}'", name))
+}
+
+/// Merges the config file `overrides` over `base` the same way profiles are
+/// applied, returning the merged YAML
+pub fn merge_config_files(base: &str, overrides: &str) -> Result<String> {
+    let read = |path: &str| -> Result<Value> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file '{}'", path))?;
+        serde_yaml::from_str(&content).with_context(|| format!("Failed to parse '{}'", path))
+    };
+    let mut value = read(base)?;
+    merge_values(&mut value, read(overrides)?);
+    Ok(serde_yaml::to_string(&value)?)
+}
+
+/// Semantic differences between two config files, one line per change: `+`
+/// and `-` for added and removed keys and list items, `~` for changed values
+/// and reordered lists. Both files must be valid configs.
+pub fn diff_config_files(a: &str, b: &str) -> Result<Vec<String>> {
+    let read = |path: &str| -> Result<Value> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file '{}'", path))?;
+        serde_yaml::from_str::<Config>(&content)
+            .with_context(|| format!("'{}' is not a valid ROMMER.yaml", path))?;
+        serde_yaml::from_str(&content).with_context(|| format!("Failed to parse '{}'", path))
+    };
+    let mut changes = Vec::new();
+    diff_values("", &read(a)?, &read(b)?, &mut changes);
+    Ok(changes)
+}
+
+fn diff_values(path: &str, a: &Value, b: &Value, changes: &mut Vec<String>) {
+    if a == b {
+        return;
+    }
+    match (a, b) {
+        (Value::Mapping(a), Value::Mapping(b)) => {
+            let key_path = |key: &Value| {
+                let key = diff_display("", key);
+                if path.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", path, key)
+                }
+            };
+            for (key, a_value) in a {
+                let key_path = key_path(key);
+                match b.get(key) {
+                    Some(b_value) => diff_values(&key_path, a_value, b_value, changes),
+                    None => changes.push(format!(
+                        "- {}: {}",
+                        key_path,
+                        diff_display(&key_path, a_value)
+                    )),
+                }
+            }
+            for (key, b_value) in b {
+                if !a.contains_key(key) {
+                    let key_path = key_path(key);
+                    changes.push(format!(
+                        "+ {}: {}",
+                        key_path,
+                        diff_display(&key_path, b_value)
+                    ));
+                }
+            }
+        }
+        (Value::Sequence(a), Value::Sequence(b)) => {
+            // `patches` items read as `patch: ...`, other lists keep their key
+            let item = match path.strip_suffix("patches") {
+                Some(prefix) => format!("{}patch", prefix),
+                None => path.to_string(),
+            };
+            for value in a.iter().filter(|v| !b.contains(v)) {
+                changes.push(format!("- {}: {}", item, diff_display(path, value)));
+            }
+            for value in b.iter().filter(|v| !a.contains(v)) {
+                changes.push(format!("+ {}: {}", item, diff_display(path, value)));
+            }
+            let kept_a: Vec<&Value> = a.iter().filter(|v| b.contains(v)).collect();
+            let kept_b: Vec<&Value> = b.iter().filter(|v| a.contains(v)).collect();
+            if kept_a != kept_b {
+                let list = |values: &[&Value]| {
+                    values
+                        .iter()
+                        .map(|v| diff_display(path, v))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                changes.push(format!(
+                    "~ {} reordered: {} -> {}",
+                    path,
+                    list(&kept_a),
+                    list(&kept_b)
+                ));
+            }
+        }
+        (a, b) => changes.push(format!(
+            "~ {}: {} -> {}",
+            path,
+            diff_display(path, a),
+            diff_display(path, b)
+        )),
+    }
+}
+
+/// A value on one line, with passwords masked
+fn diff_display(path: &str, value: &Value) -> String {
+    if path
+        .rsplit('.')
+        .next()
+        .is_some_and(|key| key.contains("password"))
+    {
+        return "********".to_string();
+    }
+    match value {
+        Value::String(s) => s.clone(),
+        value => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+/// Deep-merges `overrides` into `base`: mappings are merged key by key,
+/// everything else (scalars and lists) is replaced wholesale
+fn merge_values(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Mapping(base), Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Rewrites the `patches` list of a config file in place, keeping comments
+/// and the formatting of entries that are kept
+pub fn write_patches(path: &str, patches: &[String]) -> Result<()> {
+    write_list(path, "patches", patches, false)
+}
+
+/// Rewrites a top-level list of strings in a config file in place. A missing
+/// key is appended to the end of the file when `create` is set.
+pub fn write_list(path: &str, key: &str, values: &[String], create: bool) -> Result<()> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file '{}'", path))?;
+    let updated = match replace_list_block(&content, key, values) {
+        Some(updated) => updated,
+        None if create => {
+            let mut updated = content.clone();
+            if !updated.is_empty() && !updated.ends_with('\n') {
+                updated.push('\n');
+            }
+            updated.push_str(&format!("{}:\n", key));
+            for value in values {
+                updated.push_str(&format!("  - {}\n", value));
+            }
+            updated
+        }
+        None => anyhow::bail!("No top-level '{}' key in '{}'", key, path),
+    };
+    fs::write(path, updated).with_context(|| format!("Failed to write config file '{}'", path))
+}
+
+/// `content` with the top-level list `key` set to `values`, keeping comments
+/// and the lines of values that are kept, or `None` when there is no `key`
+pub fn replace_list_block(content: &str, key: &str, values: &[String]) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let header_prefix = format!("{}:", key);
+    let start = lines.iter().position(|l| l.starts_with(&header_prefix))?;
+    let mut end = start + 1;
+    let mut existing: Vec<(String, Vec<&str>)> = Vec::new();
+    let mut pending: Vec<&str> = Vec::new();
+    let mut indent = "  ".to_string();
+    let mut i = start + 1;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+        if let Some(item) = trimmed.strip_prefix('-') {
+            if existing.is_empty() {
+                indent = line[..line.len() - trimmed.len()].to_string();
+            }
+            pending.push(line);
+            existing.push((yaml_item_value(item), std::mem::take(&mut pending)));
+            end = i + 1;
+        } else if trimmed.is_empty() || trimmed.starts_with('#') {
+            pending.push(line);
+        } else if !line.starts_with(char::is_whitespace) {
+            break;
+        }
+        i += 1;
+    }
+
+    let mut out: Vec<String> = lines[..start].iter().map(|l| l.to_string()).collect();
+    let header = lines[start];
+    let header_comment = header.find(" #").map(|i| &header[i..]).unwrap_or("");
+    if values.is_empty() {
+        out.push(format!("{}: []{}", key, header_comment));
+    } else {
+        out.push(format!("{}:{}", key, header_comment));
+    }
+    for value in values {
+        match existing.iter().position(|(existing, _)| existing == value) {
+            Some(pos) => {
+                let (_, block) = existing.remove(pos);
+                out.extend(block.iter().map(|l| l.to_string()));
+            }
+            None => out.push(format!("{}- {}", indent, value)),
+        }
+    }
+    out.extend(lines[end..].iter().map(|l| l.to_string()));
+    let mut updated = out.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+/// `content` with the top-level scalar `key` set to `value`, keeping the rest
+/// of the file and a trailing comment on the key's line. A missing key is
+/// appended to the end.
+pub fn set_scalar(content: &str, key: &str, value: &str) -> Result<String> {
+    let value = serde_yaml::to_string(value)?.trim_end().to_string();
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let header_prefix = format!("{}:", key);
+    match lines.iter().position(|l| l.starts_with(&header_prefix)) {
+        Some(start) => {
+            let header = &lines[start];
+            let comment = header.find(" #").map(|i| header[i..].to_string());
+            lines[start] = format!("{}: {}{}", key, value, comment.unwrap_or_default());
+            // Drop the continuation lines of a multi-line value
+            let end = lines[start + 1..]
+                .iter()
+                .position(|l| !l.starts_with(char::is_whitespace) || l.trim().is_empty())
+                .map_or(lines.len(), |i| start + 1 + i);
+            lines.drain(start + 1..end);
+        }
+        None => lines.push(format!("{}: {}", key, value)),
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+/// `content` with `item` added as the first entry of the top-level list
+/// `key`, which is created at the end when missing
+pub fn prepend_list_item(content: &str, key: &str, item: &Value) -> Result<String> {
+    let rendered = serde_yaml::to_string(item)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let header_prefix = format!("{}:", key);
+    let (at, indent) = match lines.iter().position(|l| l.starts_with(&header_prefix)) {
+        Some(start) => {
+            let rest = lines[start][header_prefix.len()..].trim();
+            if rest == "[]" {
+                lines[start] = header_prefix.clone();
+            } else if !rest.is_empty() && !rest.starts_with('#') {
+                anyhow::bail!("Cannot edit '{}', write it as a block sequence", key);
+            }
+            // Before the first entry, with its indentation
+            let first = lines[start + 1..].iter().position(|l| {
+                let trimmed = l.trim_start();
+                !trimmed.is_empty() && !trimmed.starts_with('#')
+            });
+            match first.map(|i| (start + 1 + i, &lines[start + 1 + i])) {
+                Some((i, line)) if line.trim_start().starts_with('-') => {
+                    (i, line[..line.len() - line.trim_start().len()].to_string())
+                }
+                _ => (start + 1, "  ".to_string()),
+            }
+        }
+        None => {
+            lines.push(header_prefix);
+            (lines.len(), "  ".to_string())
+        }
+    };
+    let entry = rendered.lines().enumerate().map(|(i, line)| {
+        let marker = if i == 0 { "- " } else { "  " };
+        format!("{}{}{}", indent, marker, line)
+    });
+    lines.splice(at..at, entry);
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Value of a block sequence item, without quotes or trailing comment
+fn yaml_item_value(item: &str) -> String {
+    let item = item.trim();
+    let item = match item.find(" #") {
+        Some(i) => item[..i].trim_end(),
+        None => item,
+    };
+    item.trim_matches(|c| c == '"' || c == '\'').to_string()
+}
+
+/// How `--patch-set` combines its patches with the configured ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PatchSetMode {
+    Prepend,
+    Append,
+    Replace,
+}
+
+/// Reads a patch set file: a YAML list of patch folders, or a document with
+/// a `patches` list. Relative folders are resolved against the directory of
+/// the patch set, so a shared set works from any config.
+pub fn load_patch_set(path: &str) -> Result<Vec<String>> {
+    let path = shellexpand::tilde(path).to_string();
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read patch set '{}'", path))?;
+    let set = parse_patch_set(&content).with_context(|| format!("Invalid patch set '{}'", path))?;
+    let set_dir = std::path::absolute(&path)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    Ok(set
+        .iter()
+        .map(|patch| {
+            set_dir
+                .join(shellexpand::tilde(patch).to_string())
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect())
+}
+
+fn parse_patch_set(content: &str) -> Result<Vec<String>> {
+    let value: Value = serde_yaml::from_str(content)?;
+    let list = match value {
+        Value::Mapping(mut map) => map
+            .remove("patches")
+            .context("Expected a list of patches or a 'patches' key")?,
+        value => value,
+    };
+    Ok(serde_yaml::from_value(list)?)
+}
+
+/// Combines the configured patches with a patch set. Patches already in the
+/// list are not added twice, comparing the folders `resolve` gives.
+pub fn apply_patch_set(
+    patches: &[String],
+    set: &[String],
+    mode: PatchSetMode,
+    resolve: impl Fn(&str) -> PathBuf,
+) -> Vec<String> {
+    let mut combined: Vec<String> = match mode {
+        PatchSetMode::Replace => Vec::new(),
+        PatchSetMode::Append => patches.to_vec(),
+        PatchSetMode::Prepend => Vec::new(),
+    };
+    let mut add = |patch: &String| {
+        let folder = resolve(patch.trim_end_matches('/'));
+        let same = |p: &String| resolve(p.trim_end_matches('/')) == folder;
+        if !combined.iter().any(same) {
+            combined.push(patch.clone());
+        }
+    };
+    set.iter().for_each(&mut add);
+    if mode == PatchSetMode::Prepend {
+        patches.iter().for_each(&mut add);
+    }
+    combined
 }
 
+/// Hook names that builds run, in the order they run
+pub const HOOK_NAMES: [&str; 13] = [
+    "pre-run",
+    "pre-download",
+    "post-download",
+    "pre-unzip",
+    "post-unzip",
+    "pre-patch",
+    "post-patch",
+    "pre-zip",
+    "post-zip",
+    "pre-sign",
+    "post-sign",
+    "pre-cleanup",
+    "post-cleanup",
+];
+
+/// Sets the script of a hook in the `hooks` section of a config file in
+/// place, adding the section if needed. Returns the script it replaced.
+pub fn write_hook(path: &str, event: &str, script: &str) -> Result<Option<String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file '{}'", path))?;
+    let (updated, previous) = set_hook(&content, event, script)?;
+    fs::write(path, updated).with_context(|| format!("Failed to write config file '{}'", path))?;
+    Ok(previous)
+}
+
+fn set_hook(content: &str, event: &str, script: &str) -> Result<(String, Option<String>)> {
+    let value = serde_yaml::to_string(script)?.trim_end().to_string();
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let Some(start) = lines.iter().position(|l| {
+        l.strip_prefix("hooks:")
+            .is_some_and(|rest| rest.trim().is_empty() || rest.trim().starts_with('#'))
+            || l.trim_end() == "hooks: {}"
+    }) else {
+        if lines.iter().any(|l| l.starts_with("hooks:")) {
+            anyhow::bail!("Cannot edit the 'hooks' section, write it as a block mapping");
+        }
+        lines.push("hooks:".to_string());
+        lines.push(format!("  {}: {}", event, value));
+        return Ok((lines.join("\n") + "\n", None));
+    };
+    if lines[start].trim_end() == "hooks: {}" {
+        lines[start] = "hooks:".to_string();
+    }
+    let mut indent = "  ".to_string();
+    let mut insert_at = start + 1;
+    for i in start + 1..lines.len() {
+        let line = &lines[i];
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            break;
+        }
+        indent = line[..line.len() - trimmed.len()].to_string();
+        insert_at = i + 1;
+        if let Some(rest) = trimmed.strip_prefix(event)
+            && let Some(previous) = rest.strip_prefix(':')
+        {
+            let previous = yaml_item_value(previous);
+            lines[i] = format!("{}{}: {}", indent, event, value);
+            return Ok((lines.join("\n") + "\n", Some(previous)));
+        }
+    }
+    lines.insert(insert_at, format!("{}{}: {}", indent, event, value));
+    Ok((lines.join("\n") + "\n", None))
+}
+
+pub const DEFAULT_MAX_PATCH_COUNT: usize = 100;
+
 fn default_cleanup() -> bool {
     true
 }
 
-#[derive(Debug, Deserialize, Default)]
+fn default_verify_output() -> bool {
+    true
+}
+
+fn default_already_compressed_extensions() -> Vec<String> {
+    [
+        ".mp4", ".opus", ".ogg", ".webp", ".jpg", ".jpeg", ".png", ".gif",
+    ]
+    .iter()
+    .map(|ext| ext.to_string())
+    .collect()
+}
+
+/// Values like `version: 22.2` are read as strings, so numbers are allowed too
+fn string_or_number(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({ "type": ["string", "number"] })
+}
+
+/// JSON Schema of ROMMER.yaml, for editor validation and autocomplete
+pub fn json_schema() -> Result<String> {
+    Ok(serde_json::to_string_pretty(&schemars::schema_for!(
+        Config
+    ))?)
+}
+
+#[derive(Debug, Clone, Deserialize, Default, JsonSchema)]
 pub struct Hooks {
     #[serde(flatten)]
     pub scripts: HashMap<String, String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone, JsonSchema)]
 pub struct SigningConfig {
+    #[schemars(description = "apksigner, jarsigner, pkcs11, or custom")]
     pub method: String,
+    #[serde(default)]
     pub keystore_path: String,
+    #[schemars(description = "Key alias, or the key label on the token for pkcs11")]
     pub key_alias: String,
+    #[serde(default)]
     pub keystore_password: String,
+    #[serde(default)]
     pub key_password: String,
+    #[schemars(
+        description = "keystore_password encrypted by `config encrypt-secrets`, decrypted with the key in secret_key_env"
+    )]
+    pub keystore_password_encrypted: Option<String>,
+    #[schemars(
+        description = "key_password encrypted by `config encrypt-secrets`, decrypted with the key in secret_key_env"
+    )]
+    pub key_password_encrypted: Option<String>,
+    #[schemars(
+        description = "Environment variable holding the key the encrypted passwords are decrypted with (default: ROMMER_SECRET_KEY)"
+    )]
+    pub secret_key_env: Option<String>,
+    #[schemars(description = "RSA private key (PEM or DER) used by `patch sign`")]
+    pub patch_key_path: Option<String>,
+    #[schemars(description = "Command used by the custom method; {zip_path} is replaced")]
     pub custom_command: Option<String>,
+    #[schemars(description = "PKCS#11 module (shared library) of the token, for pkcs11")]
+    pub pkcs11_module: Option<String>,
+    #[schemars(description = "Token slot, for pkcs11 (default: first slot with a token)")]
+    pub pkcs11_slot: Option<u32>,
+    #[schemars(description = "Environment variable holding the token PIN, for pkcs11")]
+    pub pkcs11_pin_env: Option<String>,
+    #[schemars(description = "Signing attempts before giving up (default 1, no retry)")]
+    pub signing_max_retries: Option<u32>,
+    #[schemars(
+        description = "Delay before the first signing retry, doubled after each retry (default 2000)"
+    )]
+    pub signing_retry_delay_ms: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct OutputConfig {
+    #[schemars(
+        description = "File name of the final ROM ZIP; {device}, {version}, {timestamp}, {variant}, {android_version}, {date} and {datetime} are expanded"
+    )]
     pub filename: String,
+    #[schemars(description = "Split the final ROM into parts of at most this many MiB")]
+    pub split_size_mb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct UploadConfig {
+    #[schemars(description = "s3, r2, or gcs")]
+    pub provider: String,
+    pub bucket: String,
+    #[schemars(description = "Prefix prepended to uploaded object keys")]
+    pub key_prefix: Option<String>,
+    pub access_key_id: Option<String>,
+    #[schemars(description = "Environment variable holding the access key ID")]
+    pub access_key_id_env: Option<String>,
+    pub secret_access_key: Option<String>,
+    #[schemars(description = "Environment variable holding the secret access key")]
+    pub secret_access_key_env: Option<String>,
+    pub region: Option<String>,
+    pub endpoint_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default, JsonSchema)]
+pub struct NotificationsConfig {
+    pub discord: Option<DiscordConfig>,
+    pub slack: Option<SlackConfig>,
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct DiscordConfig {
+    #[schemars(description = "Discord channel webhook URL")]
+    pub webhook_url: String,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SlackConfig {
+    #[schemars(description = "Slack incoming webhook URL")]
+    pub webhook_url: String,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default = "default_webhook_method")]
+    #[schemars(description = "GET or POST (default POST)")]
+    pub method: String,
+    #[schemars(description = "JSON body for POST; {message}, {device} and {status} are replaced")]
+    pub body_template: Option<String>,
+}
+
+fn default_webhook_method() -> String {
+    "POST".to_string()
+}
+
+impl UploadConfig {
+    pub fn access_key_id(&self) -> Result<Option<String>> {
+        resolve_secret(&self.access_key_id, &self.access_key_id_env)
+    }
+
+    pub fn secret_access_key(&self) -> Result<Option<String>> {
+        resolve_secret(&self.secret_access_key, &self.secret_access_key_env)
+    }
+}
+
+/// Resolves a credential given either inline or as the name of an environment variable
+fn resolve_secret(value: &Option<String>, env: &Option<String>) -> Result<Option<String>> {
+    match (value, env) {
+        (Some(value), _) => Ok(Some(value.clone())),
+        (None, Some(var)) => std::env::var(var)
+            .map(Some)
+            .with_context(|| format!("Environment variable '{}' is not set", var)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Minimal valid config for tests in other modules
+#[cfg(test)]
+pub fn test_config() -> Config {
+    serde_yaml::from_str(
+        "device: bluejay\nrom: lineageos\nmax_retries: 1\nversion: '22.2'\nandroid_version: 15\ntimestamp: '20250614'\nvariant: nightly\npatches: []\noutput:\n  filename: out.zip\n",
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_set() -> Result<()> {
+        let set = parse_patch_set("- debloat/\n- fonts\n")?;
+        assert_eq!(parse_patch_set("patches:\n  - debloat/\n  - fonts\n")?, set);
+        assert!(parse_patch_set("device: x\n").is_err());
+        let patches = vec!["hosts".to_string(), "debloat".to_string()];
+        let resolve = |p: &str| PathBuf::from("/rom").join(p);
+        assert_eq!(
+            apply_patch_set(&patches, &set, PatchSetMode::Append, resolve),
+            ["hosts", "debloat", "fonts"]
+        );
+        assert_eq!(
+            apply_patch_set(&patches, &set, PatchSetMode::Prepend, resolve),
+            ["debloat/", "fonts", "hosts"]
+        );
+        assert_eq!(
+            apply_patch_set(&patches, &set, PatchSetMode::Replace, resolve),
+            ["debloat/", "fonts"]
+        );
+        let shared = vec!["/rom/debloat".to_string(), "/shared/fonts".to_string()];
+        assert_eq!(
+            apply_patch_set(&patches, &shared, PatchSetMode::Append, resolve),
+            ["hosts", "debloat", "/shared/fonts"]
+        );
+
+        let dir = tempfile::tempdir()?;
+        let set_path = dir.path().join("set.yaml");
+        fs::write(&set_path, "- debloat/\n- /abs/fonts\n")?;
+        assert_eq!(
+            load_patch_set(&set_path.to_string_lossy())?,
+            [
+                dir.path().join("debloat/").to_string_lossy().to_string(),
+                "/abs/fonts".to_string()
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_hook() -> Result<()> {
+        let content = "device: x\nhooks:\n  pre-run: scripts/a.sh # setup\n\n  # zipping\n  pre-zip: scripts/zip.sh\noutput:\n  filename: rom.zip\n";
+        let (updated, previous) = set_hook(content, "pre-run", "scripts/b.sh")?;
+        assert_eq!(previous.as_deref(), Some("scripts/a.sh"));
+        assert!(updated.contains("  pre-run: scripts/b.sh\n"));
+        let (updated, previous) = set_hook(content, "post-sign", "upload me.sh")?;
+        assert_eq!(previous, None);
+        assert!(updated.contains("  pre-zip: scripts/zip.sh\n  post-sign: upload me.sh\noutput:"));
+        let (updated, _) = set_hook("device: x\n", "pre-run", "a.sh")?;
+        assert_eq!(updated, "device: x\nhooks:\n  pre-run: a.sh\n");
+        let config: serde_yaml::Value =
+            serde_yaml::from_str(&set_hook(content, "pre-run", "#x")?.0)?;
+        assert_eq!(config["hooks"]["pre-run"].as_str(), Some("#x"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_base_config() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ROMMER.yaml");
+        fs::write(
+            &path,
+            "device: x\nrom: y\nmax_retries: 1\nversion: '1'\nandroid_version: 15\ntimestamp: '20250614'\nvariant: z\npatches:\n  - a/\noutput:\n  filename: out.zip\ndefault_profile: dev\nprofiles:\n  dev:\n    patches:\n      - a/\n      - dev/\n",
+        )?;
+        let path = path.to_string_lossy();
+        assert_eq!(load_config(&path, None)?.patches, ["a/", "dev/"]);
+        let base = load_base_config(&path)?;
+        assert_eq!(base.patches, ["a/"]);
+        assert_eq!(base.config_dir, dir.path());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_scalar() -> Result<()> {
+        let content = "name: x # the name\nversion: 1.0.0\ndescription: >\n  folded\n  text\n\n# hashes\nfiles: []\n";
+        assert_eq!(
+            set_scalar(content, "name", "y")?,
+            "name: y # the name\nversion: 1.0.0\ndescription: >\n  folded\n  text\n\n# hashes\nfiles: []\n"
+        );
+        assert_eq!(
+            set_scalar(content, "description", "short")?,
+            "name: x # the name\nversion: 1.0.0\ndescription: short\n\n# hashes\nfiles: []\n"
+        );
+        assert_eq!(
+            set_scalar("name: x", "version", "22.2")?,
+            "name: x\nversion: '22.2'\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepend_list_item() -> Result<()> {
+        let item: Value = serde_yaml::from_str("version: 1.1.0\nchanges:\n- b\n")?;
+        let content = "name: x\n# newest first\nchangelog:\n  - version: 1.0.0 # first\n    changes:\n      - a\n";
+        assert_eq!(
+            prepend_list_item(content, "changelog", &item)?,
+            "name: x\n# newest first\nchangelog:\n  - version: 1.1.0\n    changes:\n    - b\n  - version: 1.0.0 # first\n    changes:\n      - a\n"
+        );
+        assert_eq!(
+            prepend_list_item("changelog: []\nname: x\n", "changelog", &item)?,
+            "changelog:\n  - version: 1.1.0\n    changes:\n    - b\nname: x\n"
+        );
+        assert_eq!(
+            prepend_list_item("name: x\n", "changelog", &item)?,
+            "name: x\nchangelog:\n  - version: 1.1.0\n    changes:\n    - b\n"
+        );
+        assert!(prepend_list_item("changelog: [{version: 1}]\n", "changelog", &item).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_list_block() {
+        let content = "device: x\npatches:\n  - a/ # first\n  # about b\n  - \"b/\"\n\n# trailing\noutput:\n  filename: rom.zip\n";
+        let patches = vec!["b/".to_string(), "c/".to_string(), "a/".to_string()];
+        assert_eq!(
+            replace_list_block(content, "patches", &patches).unwrap(),
+            "device: x\npatches:\n  # about b\n  - \"b/\"\n  - c/\n  - a/ # first\n\n# trailing\noutput:\n  filename: rom.zip\n"
+        );
+    }
+
+    #[test]
+    fn test_schema_is_up_to_date() {
+        assert_eq!(
+            json_schema().unwrap() + "\n",
+            include_str!("../rommer.schema.json"),
+            "rommer.schema.json is outdated, regenerate it with `rommer config generate-schema`"
+        );
+    }
+
+    #[test]
+    fn test_merge_values() {
+        let mut base: Value =
+            serde_yaml::from_str("cleanup: true\npatches: [a, b]\noutput:\n  filename: rom.zip\n")
+                .unwrap();
+        let overrides: Value =
+            serde_yaml::from_str("cleanup: false\npatches: [c]\noutput:\n  extra: 1\n").unwrap();
+        merge_values(&mut base, overrides);
+        let expected: Value = serde_yaml::from_str(
+            "cleanup: false\npatches: [c]\noutput:\n  filename: rom.zip\n  extra: 1\n",
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+    }
+
+    #[test]
+    fn test_diff_values() {
+        let a: Value = serde_yaml::from_str(
+            "android_version: 14\npatches: [a, b, c, old]\nsigning:\n  method: apksigner\n  keystore_password: one\n",
+        )
+        .unwrap();
+        let b: Value = serde_yaml::from_str(
+            "android_version: 15\npatches: [c, a, b, new]\nsigning:\n  method: jarsigner\n  keystore_password: two\nno_cache: true\n",
+        )
+        .unwrap();
+        let mut changes = Vec::new();
+        diff_values("", &a, &b, &mut changes);
+        assert_eq!(
+            changes,
+            [
+                "~ android_version: 14 -> 15",
+                "- patch: old",
+                "+ patch: new",
+                "~ patches reordered: a, b, c -> c, a, b",
+                "~ signing.method: apksigner -> jarsigner",
+                "~ signing.keystore_password: ******** -> ********",
+                "+ no_cache: true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_patch_path() {
+        let mut config = test_config();
+        config.config_dir = PathBuf::from("/projects/device-x");
+        assert_eq!(
+            config.resolve_patch_path("debloat/"),
+            Path::new("/projects/device-x/debloat/")
+        );
+        assert_eq!(
+            config.resolve_patch_path("/opt/patches/debloat"),
+            Path::new("/opt/patches/debloat")
+        );
+        config.patch_dir = Some("patches".to_string());
+        assert_eq!(
+            config.resolve_patch_path("debloat"),
+            Path::new("/projects/device-x/patches/debloat")
+        );
+        config.patch_dir = Some("/srv/patches".to_string());
+        assert_eq!(
+            config.resolve_patch_path("debloat"),
+            Path::new("/srv/patches/debloat")
+        );
+    }
+
+    #[test]
+    fn test_expand_filename() {
+        let mut config = test_config();
+        config.output.filename =
+            "out/{device}-{version}-{android_version}-{variant}-{timestamp}-{date}-{unknown}.zip"
+                .to_string();
+        assert_eq!(
+            config.expand_filename(20_743 * 86_400 + 3_723),
+            "out/bluejay-22.2-15-nightly-20250614-20261017-{unknown}.zip"
+        );
+        config.output.filename = "{datetime}.zip".to_string();
+        assert_eq!(
+            config.expand_filename(20_743 * 86_400 + 3_723),
+            "20261017T010203Z.zip"
+        );
+    }
 }
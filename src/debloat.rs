@@ -0,0 +1,206 @@
+use crate::utils;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Directories holding one folder per preinstalled app
+const APP_DIRS: [&str; 2] = ["system/app", "system/priv-app"];
+
+/// Finds the app folders of the given packages in a ROM ZIP and writes them
+/// in `.rommerdel` format, to `output` or stdout. `apps` entries that are
+/// existing files are read as one package per line.
+pub fn extract_deletions(
+    rom_zip: &str,
+    apps: &[String],
+    output: Option<&str>,
+) -> anyhow::Result<()> {
+    utils::print_section("🧹 EXTRACTING DELETIONS");
+    let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+    let file = File::open(&rom_path)
+        .with_context(|| format!("Failed to open zip file '{}'", rom_path.display()))?;
+    let mut archive = ZipArchive::new(file).context("Failed to read zip archive")?;
+    let packages = read_packages(apps)?;
+    let app_apks = app_apks(archive.file_names());
+
+    let mut manifests: Option<BTreeMap<String, Vec<u8>>> = None;
+    let mut deletions = Vec::new();
+    for package in &packages {
+        let mut found: Vec<&String> = app_apks
+            .keys()
+            .filter(|dir| folder_name(dir).eq_ignore_ascii_case(package))
+            .collect();
+        if found.is_empty() {
+            // Folder names rarely match the package, so look inside the APKs
+            let manifests = match &mut manifests {
+                Some(manifests) => manifests,
+                None => manifests.insert(read_manifests(&mut archive, &app_apks)?),
+            };
+            found = manifests
+                .iter()
+                .filter(|(_, manifest)| manifest_declares(manifest, package))
+                .map(|(dir, _)| dir)
+                .collect();
+        }
+        if found.is_empty() {
+            utils::print_warning(&format!("No app folder found for '{}'", package));
+        }
+        for dir in found {
+            utils::print_info(&format!("📦 {} -> {}", package, dir));
+            if !deletions.contains(dir) {
+                deletions.push(dir.clone());
+            }
+        }
+    }
+
+    let content: String = deletions.iter().map(|d| format!("{}\n", d)).collect();
+    match output {
+        Some(output) => {
+            let output = PathBuf::from(shellexpand::tilde(output).to_string());
+            fs::write(&output, &content)
+                .with_context(|| format!("Failed to write '{}'", output.display()))?;
+            utils::print_success(&format!(
+                "Wrote {} deletion(s) to {}",
+                deletions.len(),
+                output.display()
+            ));
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+/// Package names from the command line, expanding files of one package per
+/// line. Blank lines and `#` comments are ignored.
+fn read_packages(apps: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut packages = Vec::new();
+    for app in apps {
+        let path = Path::new(app);
+        if !path.is_file() {
+            packages.push(app.clone());
+            continue;
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read app list '{}'", app))?;
+        packages.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+    Ok(packages)
+}
+
+/// APK entries directly inside each app folder, keyed by folder
+fn app_apks<'a>(names: impl Iterator<Item = &'a str>) -> BTreeMap<String, Vec<String>> {
+    let mut apks: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for name in names {
+        let Some((dir, rest)) = APP_DIRS
+            .iter()
+            .find_map(|dir| Some((dir, name.strip_prefix(dir)?.strip_prefix('/')?)))
+        else {
+            continue;
+        };
+        if let Some((folder, file)) = rest.split_once('/')
+            && !file.contains('/')
+            && file.ends_with(".apk")
+        {
+            apks.entry(format!("{}/{}", dir, folder))
+                .or_default()
+                .push(name.to_string());
+        }
+    }
+    apks
+}
+
+/// The binary `AndroidManifest.xml` of every app folder's APKs, concatenated
+fn read_manifests(
+    archive: &mut ZipArchive<File>,
+    app_apks: &BTreeMap<String, Vec<String>>,
+) -> anyhow::Result<BTreeMap<String, Vec<u8>>> {
+    let mut manifests = BTreeMap::new();
+    for (dir, apks) in app_apks {
+        let mut manifest = Vec::new();
+        for apk in apks {
+            let mut data = Vec::new();
+            archive
+                .by_name(apk)?
+                .read_to_end(&mut data)
+                .with_context(|| format!("Failed to read '{}'", apk))?;
+            let Ok(mut apk_archive) = ZipArchive::new(Cursor::new(data)) else {
+                utils::print_warning(&format!("'{}' is not a valid APK", apk));
+                continue;
+            };
+            if let Ok(mut entry) = apk_archive.by_name("AndroidManifest.xml") {
+                entry.read_to_end(&mut manifest)?;
+            }
+        }
+        manifests.insert(dir.clone(), manifest);
+    }
+    Ok(manifests)
+}
+
+/// Whether a binary manifest's string pool holds exactly `package`. Pool
+/// strings are length-prefixed and NUL-terminated, in UTF-16 or UTF-8, so
+/// matching the whole entry avoids hits on longer names with the same prefix.
+fn manifest_declares(manifest: &[u8], package: &str) -> bool {
+    let units: Vec<u16> = package.encode_utf16().collect();
+    let mut utf16 = (units.len() as u16).to_le_bytes().to_vec();
+    utf16.extend(units.iter().flat_map(|u| u.to_le_bytes()));
+    utf16.extend([0, 0]);
+    let contains = |needle: &[u8]| manifest.windows(needle.len()).any(|w| w == needle);
+    if units.len() < 0x8000 && contains(&utf16) {
+        return true;
+    }
+    if package.len() < 0x80 {
+        let mut utf8 = vec![package.chars().count() as u8, package.len() as u8];
+        utf8.extend(package.as_bytes());
+        utf8.push(0);
+        return contains(&utf8);
+    }
+    false
+}
+
+fn folder_name(dir: &str) -> &str {
+    dir.rsplit('/').next().unwrap_or(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_apks() {
+        let names = [
+            "system/app/YouTube/YouTube.apk",
+            "system/app/YouTube/lib/arm64/libfoo.so",
+            "system/priv-app/GmsCore/GmsCore.apk",
+            "system/app/README.apk",
+            "vendor/app/Other/Other.apk",
+        ];
+        let apks = app_apks(names.into_iter());
+        assert_eq!(
+            apks.keys().collect::<Vec<_>>(),
+            ["system/app/YouTube", "system/priv-app/GmsCore"]
+        );
+    }
+
+    #[test]
+    fn test_manifest_declares() {
+        let package = "com.google.android.youtube";
+        let mut manifest = vec![0xAB; 8];
+        manifest.extend((package.len() as u16).to_le_bytes());
+        manifest.extend(package.encode_utf16().flat_map(|u| u.to_le_bytes()));
+        manifest.extend([0, 0]);
+        assert!(manifest_declares(&manifest, package));
+        assert!(!manifest_declares(&manifest, "com.google.android"));
+        assert!(!manifest_declares(
+            &manifest,
+            "com.google.android.youtube.music"
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::Config;
+use crate::patchmeta::{self, PatchMeta};
+use crate::utils;
+use std::collections::HashSet;
+
+/// Orders patches so each one comes after the patches it `depends_on`.
+/// Among patches whose dependencies are satisfied, the lowest `priority`
+/// goes first, then the configured order.
+pub fn resolve_order(config: &Config) -> anyhow::Result<Vec<String>> {
+    let patches = &config.patches;
+    let metas: Vec<Option<PatchMeta>> = patches
+        .iter()
+        .map(|patch| patchmeta::load_patch_meta(config.resolve_patch_path(patch)))
+        .collect();
+    let deps: Vec<Vec<usize>> = patches
+        .iter()
+        .zip(&metas)
+        .map(|(patch, meta)| {
+            let declared = meta
+                .as_ref()
+                .and_then(|m| m.depends_on.clone())
+                .unwrap_or_default();
+            declared
+                .iter()
+                .filter_map(|dep| {
+                    let found = find_patch(patches, &metas, dep);
+                    if found.is_none() {
+                        utils::print_warning(&format!(
+                            "'{}' depends on '{}', which is not a configured patch",
+                            patch, dep
+                        ));
+                    }
+                    found
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut done: HashSet<usize> = HashSet::new();
+    let mut order = Vec::new();
+    while order.len() < patches.len() {
+        let next = (0..patches.len())
+            .filter(|i| !done.contains(i) && deps[*i].iter().all(|d| done.contains(d)))
+            .min_by_key(|i| (metas[*i].as_ref().and_then(|m| m.priority).unwrap_or(0), *i));
+        match next {
+            Some(i) => {
+                done.insert(i);
+                order.push(patches[i].clone());
+            }
+            None => {
+                let cycle: Vec<&str> = (0..patches.len())
+                    .filter(|i| !done.contains(i))
+                    .map(|i| patches[i].as_str())
+                    .collect();
+                anyhow::bail!("Dependency cycle between patches: {}", cycle.join(", "));
+            }
+        }
+    }
+    Ok(order)
+}
+
+/// Finds a configured patch by folder or `patch.yaml` name
+pub fn find_patch(patches: &[String], metas: &[Option<PatchMeta>], name: &str) -> Option<usize> {
+    patches.iter().zip(metas).position(|(patch, meta)| {
+        utils::patch_matches(patch, name)
+            || meta
+                .as_ref()
+                .and_then(|m| m.name.as_deref())
+                .is_some_and(|n| n == name)
+    })
+}
+
+/// Prints the `depends_on` and `conflicts_with` graph of the configured
+/// patches in Graphviz DOT or Mermaid format. Dependency edges that are part
+/// of a cycle are highlighted, since they fail the build.
+pub fn print_graph(config: &Config, format: &str) -> anyhow::Result<()> {
+    let metas: Vec<Option<PatchMeta>> = config
+        .patches
+        .iter()
+        .map(|patch| patchmeta::load_patch_meta(config.resolve_patch_path(patch)))
+        .collect();
+    let edges = |field: fn(&PatchMeta) -> Option<&Vec<String>>| -> Vec<(usize, usize)> {
+        let mut edges = Vec::new();
+        for (from, meta) in metas.iter().enumerate() {
+            for name in meta.as_ref().and_then(field).into_iter().flatten() {
+                match find_patch(&config.patches, &metas, name) {
+                    Some(to) => edges.push((from, to)),
+                    None => utils::print_warning(&format!(
+                        "'{}' references '{}', which is not a configured patch",
+                        config.patches[from], name
+                    )),
+                }
+            }
+        }
+        edges
+    };
+    let depends = edges(|m| m.depends_on.as_ref());
+    let conflicts = edges(|m| m.conflicts_with.as_ref());
+    let cyclic: Vec<bool> = depends
+        .iter()
+        .map(|(from, to)| reaches(&depends, *to, *from))
+        .collect();
+    if cyclic.contains(&true) {
+        utils::print_warning("The dependency graph has a cycle (highlighted)");
+    }
+    let graph = match format {
+        "mermaid" => mermaid_graph(&config.patches, &depends, &cyclic, &conflicts),
+        _ => dot_graph(&config.patches, &depends, &cyclic, &conflicts),
+    };
+    print!("{}", graph);
+    Ok(())
+}
+
+/// Whether `to` can be reached from `from` along `edges`
+fn reaches(edges: &[(usize, usize)], from: usize, to: usize) -> bool {
+    let mut seen = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(node) = stack.pop() {
+        if node == to {
+            return true;
+        }
+        if seen.insert(node) {
+            stack.extend(edges.iter().filter(|(a, _)| *a == node).map(|(_, b)| *b));
+        }
+    }
+    false
+}
+
+fn dot_graph(
+    patches: &[String],
+    depends: &[(usize, usize)],
+    cyclic: &[bool],
+    conflicts: &[(usize, usize)],
+) -> String {
+    let mut out = String::from("digraph patches {\n    rankdir=LR;\n    node [shape=box];\n");
+    for (i, patch) in patches.iter().enumerate() {
+        out.push_str(&format!("    p{} [label={:?}];\n", i, patch));
+    }
+    for ((from, to), cyclic) in depends.iter().zip(cyclic) {
+        let style = if *cyclic {
+            " [color=orange, penwidth=2, label=\"cycle\"]"
+        } else {
+            ""
+        };
+        out.push_str(&format!("    p{} -> p{}{};\n", from, to, style));
+    }
+    for (from, to) in conflicts {
+        out.push_str(&format!(
+            "    p{} -> p{} [style=dashed, color=red, dir=none];\n",
+            from, to
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn mermaid_graph(
+    patches: &[String],
+    depends: &[(usize, usize)],
+    cyclic: &[bool],
+    conflicts: &[(usize, usize)],
+) -> String {
+    let mut out = String::from("graph LR\n");
+    for (i, patch) in patches.iter().enumerate() {
+        out.push_str(&format!(
+            "    p{}[\"{}\"]\n",
+            i,
+            patch.replace('"', "#quot;")
+        ));
+    }
+    // linkStyle refers to links by their position in the graph
+    let mut link = 0;
+    let mut styles = Vec::new();
+    for ((from, to), cyclic) in depends.iter().zip(cyclic) {
+        if *cyclic {
+            out.push_str(&format!("    p{} -->|cycle| p{}\n", from, to));
+            styles.push(format!(
+                "    linkStyle {} stroke:orange,stroke-width:3px\n",
+                link
+            ));
+        } else {
+            out.push_str(&format!("    p{} --> p{}\n", from, to));
+        }
+        link += 1;
+    }
+    for (from, to) in conflicts {
+        out.push_str(&format!("    p{} -.- p{}\n", from, to));
+        styles.push(format!("    linkStyle {} stroke:red\n", link));
+        link += 1;
+    }
+    out.extend(styles);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_graph_highlights_cycles() {
+        let patches = ["a", "b", "c"].map(String::from);
+        let depends = [(0, 1), (1, 0), (2, 0)];
+        let cyclic: Vec<bool> = depends
+            .iter()
+            .map(|(from, to)| reaches(&depends, *to, *from))
+            .collect();
+        assert_eq!(cyclic, [true, true, false]);
+        let dot = dot_graph(&patches, &depends, &cyclic, &[(2, 1)]);
+        assert!(dot.contains("p0 -> p1 [color=orange"));
+        assert!(dot.contains("p2 -> p0;\n"));
+        assert!(dot.contains("p2 -> p1 [style=dashed, color=red, dir=none];"));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::config::Config;
+use crate::utils;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MIN_JAVA_VERSION: u32 = 11;
+
+pub fn run_doctor(config: Option<&Config>) -> anyhow::Result<()> {
+    utils::print_section("🩺 ROMMER DOCTOR");
+    let mut failures = 0;
+    let mut report = |ok: bool, msg: String| {
+        if ok {
+            println!("✅ {}", msg);
+        } else {
+            println!("❌ {}", msg);
+            failures += 1;
+        }
+    };
+
+    match java_version() {
+        Some(version) => report(
+            version >= MIN_JAVA_VERSION,
+            format!("java {} (>= {} required)", version, MIN_JAVA_VERSION),
+        ),
+        None => report(false, "java not found".to_string()),
+    }
+    for tool in ["openssl", "python3"] {
+        report(find_in_path(tool).is_some(), tool_message(tool));
+    }
+
+    if let Some(config) = config {
+        if let Some(signing) = &config.signing {
+            // pkcs11 signs through jarsigner's SunPKCS11 provider
+            let tool = match signing.method.as_str() {
+                "pkcs11" => Some("jarsigner"),
+                "apksigner" | "jarsigner" => Some(signing.method.as_str()),
+                _ => None,
+            };
+            if let Some(tool) = tool {
+                report(find_in_path(tool).is_some(), tool_message(tool));
+            }
+        }
+        let mut hooks: Vec<_> = config.hooks.scripts.iter().collect();
+        hooks.sort();
+        for (name, script) in hooks {
+            report(
+                is_executable(Path::new(script)),
+                format!("hook {} is an executable file: {}", name, script),
+            );
+        }
+        for patch in &config.patches {
+            report(
+                config.resolve_patch_path(patch).is_dir(),
+                format!("patch directory exists: {}", patch),
+            );
+        }
+        let output_filename = config.output_filename();
+        let output_dir = Path::new(&output_filename)
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        report(
+            tempfile::tempfile_in(output_dir).is_ok(),
+            format!("output directory is writable: {}", output_dir.display()),
+        );
+    } else {
+        utils::print_warning("No config found, skipping config checks");
+    }
+
+    println!();
+    if failures > 0 {
+        anyhow::bail!("{} check(s) failed", failures);
+    }
+    utils::print_success("All checks passed");
+    Ok(())
+}
+
+fn tool_message(tool: &str) -> String {
+    match find_in_path(tool) {
+        Some(path) => format!("{} found at {}", tool, path.display()),
+        None => format!("{} not found on PATH", tool),
+    }
+}
+
+pub fn find_in_path(tool: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(tool);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        let candidate = dir.join(format!("{}.exe", tool));
+        candidate.is_file().then_some(candidate)
+    })
+}
+
+/// Parses the major version from `java -version`, e.g. `"17.0.2"` or `"1.8.0_292"`
+fn java_version() -> Option<u32> {
+    let output = Command::new("java").arg("-version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stderr);
+    let version = text.split('"').nth(1)?;
+    let mut parts = version.split(['.', '_', '-']);
+    let major: u32 = parts.next()?.parse().ok()?;
+    if major == 1 {
+        parts.next()?.parse().ok()
+    } else {
+        Some(major)
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -3,12 +3,36 @@ use crate::config::Config;
 use crate::utils;
 use anyhow::Context;
 use futures_util::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::ProgressStyle;
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+const CACHE_HEADERS_PATH: &str = ".rommer-cache/download-headers.json";
+
+/// Validators from the response that produced a downloaded ROM
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CachedHeaders {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Fails in `--offline` mode, where the ROM has to be given with `--romzip`
+pub fn ensure_online(offline: bool) -> anyhow::Result<()> {
+    if offline {
+        anyhow::bail!(
+            "--offline mode: no ROM file specified; use --romzip to provide a local file"
+        );
+    }
+    Ok(())
+}
 
 pub async fn download_rom(config: &Config, dry_run: bool) -> anyhow::Result<PathBuf> {
     crate::utils::print_section("📥 DOWNLOADING ROM");
@@ -17,29 +41,64 @@ pub async fn download_rom(config: &Config, dry_run: bool) -> anyhow::Result<Path
 
     if dry_run {
         crate::utils::print_info("🔍 DRY RUN: Would download ROM from URL");
-        let rom_filename = format!(
-            "{}_{}_{}.zip",
-            config.device,
-            if config.rom.starts_with("http") {
-                "custom"
-            } else {
-                &config.rom
-            },
-            config.version
-        );
+        let rom_filename = rom_filename(config);
         crate::utils::print_info(&format!("🔍 DRY RUN: Would save as: {}", rom_filename));
         return Ok(PathBuf::from(rom_filename));
     }
 
+    let rom_filename = rom_filename(config);
+    let rom_path = PathBuf::from(&rom_filename);
+    let mut cache = if config.no_cache {
+        HashMap::new()
+    } else {
+        load_cached_headers()
+    };
+    let cached = cache
+        .get(&rom_filename)
+        .filter(|cached| cached.url == download_url && rom_path.exists());
     let max_retries: u32 = config.max_retries;
     const RETRY_DELAY_MS: u64 = 2000;
-    let client = reqwest::Client::new();
+    let user_agent = config
+        .download_user_agent
+        .clone()
+        .unwrap_or_else(|| format!("ROMMER/{}", env!("CARGO_PKG_VERSION")));
+    let mut client = reqwest::Client::builder().user_agent(user_agent);
+    if let Some(timeout) = config.download_timeout_secs {
+        client = client.timeout(std::time::Duration::from_secs(timeout));
+    }
+    let client = client.build().context("Failed to create HTTP client")?;
     let mut response = None;
     let mut last_error = None;
     for attempt in 1..=max_retries {
-        match client.get(&download_url).send().await {
+        let mut request = client.get(&download_url);
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let request = request.build()?;
+        utils::print_debug(&format!("GET {}", request.url()));
+        for (name, value) in request.headers() {
+            utils::print_debug(&format!(
+                "  > {}: {}",
+                name,
+                value.to_str().unwrap_or("<binary>")
+            ));
+        }
+        match client.execute(request).await {
             Ok(resp) => {
-                if resp.status().is_success() {
+                utils::print_debug(&format!("  < {:?} {}", resp.version(), resp.status()));
+                for (name, value) in resp.headers() {
+                    utils::print_debug(&format!(
+                        "  < {}: {}",
+                        name,
+                        value.to_str().unwrap_or("<binary>")
+                    ));
+                }
+                if resp.status().is_success() || resp.status() == StatusCode::NOT_MODIFIED {
                     response = Some(resp);
                     break;
                 } else {
@@ -79,26 +138,38 @@ pub async fn download_rom(config: &Config, dry_run: bool) -> anyhow::Result<Path
             }));
         }
     };
+    if response.status() == StatusCode::NOT_MODIFIED {
+        crate::utils::print_success("♻️  ROM not modified since last download, using cached file");
+        if let Some(expected_hash) = &config.expected_checksum
+            && !checksum::verify_checksum(&rom_path, expected_hash).await?
+        {
+            fs::remove_file(&rom_path).context("Failed to remove corrupted file")?;
+            cache.remove(&rom_filename);
+            save_cached_headers(&cache)?;
+            anyhow::bail!(
+                "Cached ROM failed checksum verification and was removed, run again to re-download"
+            );
+        }
+        return Ok(rom_path);
+    }
+    if cached.is_some() {
+        crate::utils::print_info("🔄 ROM changed since last download, re-downloading...");
+        fs::remove_file(&rom_path).context("Failed to remove outdated file")?;
+    }
+    let headers = CachedHeaders {
+        url: download_url.clone(),
+        etag: header_value(&response, ETAG),
+        last_modified: header_value(&response, LAST_MODIFIED),
+    };
     let total_size = response.content_length().unwrap_or(0);
-    let pb = ProgressBar::new(total_size);
+    let pb = crate::utils::progress_bar(total_size, "Downloading");
     pb.set_style(ProgressStyle::default_bar()
         .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}, {msg})")?
         .progress_chars("█▉▊▋▌▍▎▏  "));
-    let rom_filename = format!(
-        "{}_{}_{}.zip",
-        config.device,
-        if config.rom.starts_with("http") {
-            "custom"
-        } else {
-            &config.rom
-        },
-        config.version
-    );
-    let rom_path = PathBuf::from(&rom_filename);
     if rom_path.exists() {
         crate::utils::print_info("File already exists! Checking integrity...");
         if let Some(expected_hash) = &config.expected_checksum {
-            match checksum::verify_checksum(&rom_path, expected_hash) {
+            match checksum::verify_checksum(&rom_path, expected_hash).await {
                 Ok(true) => {
                     crate::utils::print_success("✅ Existing file checksum verified successfully");
                     return Ok(rom_path);
@@ -134,7 +205,7 @@ pub async fn download_rom(config: &Config, dry_run: bool) -> anyhow::Result<Path
         hasher.update(&chunk);
         downloaded += chunk.len() as u64;
         pb.set_position(downloaded);
-        if downloaded % (1024 * 1024) == 0 {
+        if downloaded.is_multiple_of(1024 * 1024) {
             let progress_percentage = if total_size > 0 {
                 (downloaded as f64 / total_size as f64) * 100.0
             } else {
@@ -170,10 +241,120 @@ pub async fn download_rom(config: &Config, dry_run: bool) -> anyhow::Result<Path
         }
         crate::utils::print_success("✅ Checksum verified successfully");
     }
+    if !config.no_cache && (headers.etag.is_some() || headers.last_modified.is_some()) {
+        cache.insert(rom_filename, headers);
+        save_cached_headers(&cache)?;
+    }
     let _ = utils::run_hook(&config.hooks, "post-download");
     Ok(rom_path)
 }
 
+fn header_value(response: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Cached response headers keyed by ROM file name. A missing or unreadable
+/// cache file is treated as empty.
+fn load_cached_headers() -> HashMap<String, CachedHeaders> {
+    fs::read_to_string(CACHE_HEADERS_PATH)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cached_headers(cache: &HashMap<String, CachedHeaders>) -> anyhow::Result<()> {
+    let path = Path::new(CACHE_HEADERS_PATH);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create download cache directory")?;
+    }
+    fs::write(path, serde_json::to_string_pretty(cache)?)
+        .with_context(|| format!("Failed to write '{}'", CACHE_HEADERS_PATH))
+}
+
+/// The hash a ROM named `rom_name` is expected to have: its entry in the
+/// `--checksum-file` if one is given, otherwise `expected_checksum`
+pub fn expected_checksum(
+    config: &Config,
+    checksum_file: Option<&str>,
+    rom_name: &str,
+) -> anyhow::Result<Option<String>> {
+    let Some(checksum_file) = checksum_file else {
+        return Ok(config.expected_checksum.clone());
+    };
+    let path = PathBuf::from(shellexpand::tilde(checksum_file).to_string());
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read checksum file '{}'", path.display()))?;
+    let hash = checksum::parse_checksum_file(&content, rom_name).with_context(|| {
+        format!(
+            "Checksum file '{}' has no SHA-256 entry for '{}'",
+            path.display(),
+            rom_name
+        )
+    })?;
+    if config.expected_checksum.is_some() {
+        utils::print_warning(&format!(
+            "Using the checksum from '{}' instead of expected_checksum in the config",
+            path.display()
+        ));
+    }
+    Ok(Some(hash))
+}
+
+/// Checks a ROM given with `--romzip` against the `--checksum-file` or
+/// `expected_checksum`, if either is set
+pub async fn verify_local_rom(
+    config: &Config,
+    rom_path: &Path,
+    checksum_file: Option<&str>,
+) -> anyhow::Result<()> {
+    let rom_name = rom_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let Some(expected) = expected_checksum(config, checksum_file, &rom_name)? else {
+        return Ok(());
+    };
+    utils::print_info(&format!("🔍 Verifying checksum of {}", rom_path.display()));
+    if !checksum::verify_checksum(rom_path, &expected).await? {
+        anyhow::bail!(
+            "Checksum verification failed for '{}'! Expected: {}",
+            rom_path.display(),
+            expected
+        );
+    }
+    utils::print_success("Checksum verified successfully");
+    Ok(())
+}
+
+pub fn rom_filename(config: &Config) -> String {
+    format!(
+        "{}_{}_{}.zip",
+        config.device,
+        if config.rom.starts_with("http") {
+            "custom"
+        } else {
+            &config.rom
+        },
+        config.version
+    )
+}
+
+/// File name of the ROM at its download URL, as listed in the checksum files
+/// published next to it
+pub fn url_filename(config: &Config) -> anyhow::Result<String> {
+    let url = construct_download_url(config)?;
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .with_context(|| format!("Download URL '{}' has no file name", url))
+}
+
 fn construct_download_url(config: &Config) -> anyhow::Result<String> {
     let base_urls = std::collections::HashMap::from([
         ("lineageos", format!("https://mirrorbits.lineageos.org/full/{}/{}/lineage-{}-{}-{}-{}-signed.zip", config.device, config.timestamp, config.version, config.timestamp, config.variant, config.device).to_string()),
@@ -0,0 +1,64 @@
+use crate::config::Config;
+use crate::utils;
+
+/// `ROMMER_*` variables passed to hook scripts during a build
+pub fn build_env(config: &Config) -> Vec<(&'static str, String)> {
+    vec![
+        ("ROMMER_DEVICE", config.device.clone()),
+        ("ROMMER_ROM", config.rom.clone()),
+        ("ROMMER_VERSION", config.version.clone()),
+        ("ROMMER_ANDROID_VERSION", config.android_version.to_string()),
+        ("ROMMER_VARIANT", config.variant.clone()),
+        ("ROMMER_TIMESTAMP", config.timestamp.clone()),
+        ("ROMMER_OUTPUT", config.output_filename()),
+    ]
+}
+
+/// Prints the build variables as a table, or with `export` as statements
+/// for `eval` in `sh` or `fish`
+pub fn print_env(config: &Config, export: bool, format: Option<&str>) {
+    let vars = build_env(config);
+    if export {
+        for (key, value) in &vars {
+            println!("{}", export_line(key, value, format.unwrap_or("sh")));
+        }
+        return;
+    }
+    utils::print_section("🌱 BUILD ENVIRONMENT");
+    let width = vars.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, value) in &vars {
+        println!("{:width$}  {}", key, value, width = width);
+    }
+}
+
+fn export_line(key: &str, value: &str, format: &str) -> String {
+    match format {
+        "fish" => format!(
+            "set -x {} '{}';",
+            key,
+            value.replace('\\', "\\\\").replace('\'', "\\'")
+        ),
+        _ => format!("export {}='{}'", key, value.replace('\'', r"'\''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_line() {
+        assert_eq!(
+            export_line("ROMMER_DEVICE", "bluejay", "sh"),
+            "export ROMMER_DEVICE='bluejay'"
+        );
+        assert_eq!(
+            export_line("ROMMER_OUTPUT", "it's.zip", "sh"),
+            r"export ROMMER_OUTPUT='it'\''s.zip'"
+        );
+        assert_eq!(
+            export_line("ROMMER_OUTPUT", r"it's\.zip", "fish"),
+            r"set -x ROMMER_OUTPUT 'it\'s\\.zip';"
+        );
+    }
+}
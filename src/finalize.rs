@@ -1,21 +1,91 @@
+use crate::checksum;
+use crate::ci::Failure;
 use crate::config::Config;
+use crate::metrics;
+use crate::pipeline::{self, BuildEvent};
+use crate::steps::{self, Step};
 use crate::utils;
+use anyhow::Context;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+/// Rezips the patched tree unless `skip_rezip`, then verifies, signs and
+/// uploads the output ZIP unless `rezip_only`
 pub async fn finalize_rom(
     tmp_dir: &Path,
     config: &Config,
+    skip_rezip: bool,
+    rezip_only: bool,
+    force_rezip: bool,
+    skip_signing: bool,
     dry_run: bool,
 ) -> anyhow::Result<PathBuf> {
-    let output_filename = config.output.filename.clone();
-    let output_path = PathBuf::from(&output_filename);
-    let _ = utils::run_hook(&config.hooks, "pre-zip");
-    crate::rezip::rezip_rom(tmp_dir, &output_path, dry_run)?;
-    let _ = utils::run_hook(&config.hooks, "post-zip");
-    let _ = utils::run_hook(&config.hooks, "pre-sign");
-    crate::sign::sign_rom(&output_path, config, dry_run).await?;
-    let _ = utils::run_hook(&config.hooks, "post-sign");
+    // A build resumed at the sign step signs the ZIP the rezip step wrote,
+    // even if `{date}` in the file name has changed since
+    let output_path = skip_rezip
+        .then(|| steps::rezipped_output(tmp_dir))
+        .flatten()
+        .unwrap_or_else(|| PathBuf::from(config.output_filename()));
+    let hash_path = rezip_hash_path(&output_path);
+    if skip_rezip {
+        if !dry_run && !output_path.exists() {
+            anyhow::bail!("Output ZIP '{}' does not exist", output_path.display());
+        }
+    } else {
+        let tree_hash = if dry_run {
+            None
+        } else {
+            Some(checksum::calculate_tree_checksum(
+                tmp_dir,
+                &[steps::MARKER_DIR, steps::APPLIED_PATCHES_FILE],
+            )?)
+        };
+        let unchanged = !force_rezip
+            && output_path.exists()
+            && tree_hash.is_some()
+            && fs::read_to_string(&hash_path).is_ok_and(|h| Some(h.trim()) == tree_hash.as_deref());
+        if unchanged {
+            utils::print_success(&format!(
+                "♻️  {} is up to date with the patched ROM, skipping rezip (--force-rezip to override)",
+                output_path.display()
+            ));
+        } else {
+            if !dry_run {
+                crate::split::remove_split_parts(&output_path)?;
+            }
+            let _ = utils::run_hook(&config.hooks, "pre-zip");
+            pipeline::emit(BuildEvent::Rezipping {
+                output: output_path.clone(),
+            })
+            .await;
+            let start = Instant::now();
+            crate::rezip::rezip_rom(tmp_dir, &output_path, config, dry_run)?;
+            metrics::record("rezip", start.elapsed());
+            let _ = utils::run_hook(&config.hooks, "post-zip");
+            if let Some(tree_hash) = &tree_hash {
+                fs::write(&hash_path, format!("{}\n", tree_hash))
+                    .with_context(|| format!("Failed to write '{}'", hash_path.display()))?;
+            }
+        }
+        steps::mark_complete(
+            tmp_dir,
+            Step::Rezip,
+            &output_path.to_string_lossy(),
+            dry_run,
+        )?;
+        if rezip_only {
+            return Ok(output_path);
+        }
+    }
+    publish_rom(&output_path, config, skip_signing, dry_run).await?;
+    report_to_github_actions(&output_path, config, dry_run)?;
+    // Signing may have changed the output ZIP, so it no longer matches the tree
+    if hash_path.exists() {
+        let _ = fs::remove_file(&hash_path);
+    }
+    steps::mark_complete(tmp_dir, Step::Sign, "", dry_run)?;
     if config.cleanup {
         let _ = utils::run_hook(&config.hooks, "pre-cleanup");
     }
@@ -41,3 +111,146 @@ pub async fn finalize_rom(
 
     Ok(output_path)
 }
+
+/// Finalizes a `streaming_build`: the output ZIP is built directly from the
+/// base ROM and the patches, so there is no working directory to clean up
+pub async fn finalize_streamed_rom(
+    base_zip: &Path,
+    patches: &[PathBuf],
+    config: &Config,
+    skip_signing: bool,
+    dry_run: bool,
+) -> anyhow::Result<PathBuf> {
+    let output_path = PathBuf::from(config.output_filename());
+    if !dry_run {
+        crate::split::remove_split_parts(&output_path)?;
+    }
+    let _ = utils::run_hook(&config.hooks, "pre-zip");
+    pipeline::emit(BuildEvent::Rezipping {
+        output: output_path.clone(),
+    })
+    .await;
+    let start = Instant::now();
+    crate::rezip::stream_rezip(base_zip, patches, &output_path, config, dry_run)?;
+    metrics::record("rezip", start.elapsed());
+    let _ = utils::run_hook(&config.hooks, "post-zip");
+    publish_rom(&output_path, config, skip_signing, dry_run).await?;
+    report_to_github_actions(&output_path, config, dry_run)?;
+    Ok(output_path)
+}
+
+/// When running in GitHub Actions, exposes the final ROM as step outputs
+/// (`rom_path`, `rom_sha256`, `rom_size`) and adds a build summary
+fn report_to_github_actions(
+    output_path: &Path,
+    config: &Config,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let outputs = std::env::var_os("GITHUB_OUTPUT");
+    let summary = std::env::var_os("GITHUB_STEP_SUMMARY");
+    if dry_run || (outputs.is_none() && summary.is_none()) {
+        return Ok(());
+    }
+    let parts = crate::split::split_parts(output_path);
+    let sha256 = crate::split::parts_checksum(&parts)?;
+    let mut size = 0;
+    for part in &parts {
+        size += fs::metadata(part)?.len();
+    }
+    if let Some(outputs) = outputs {
+        append_to(
+            Path::new(&outputs),
+            &format!(
+                "rom_path={}\nrom_sha256={}\nrom_size={}\n",
+                output_path.display(),
+                sha256,
+                size
+            ),
+        )?;
+    }
+    if let Some(summary) = summary {
+        let mut markdown = format!(
+            "## 🔧 ROMMER build\n\n| | |\n|---|---|\n| Device | {} |\n| ROM | {} {} (Android {}) |\n| Output | `{}` |\n| SHA-256 | `{}` |\n| Size | {} |\n",
+            config.device,
+            config.rom,
+            config.version,
+            config.android_version,
+            output_path.display(),
+            sha256,
+            indicatif::HumanBytes(size)
+        );
+        if parts.len() > 1 {
+            markdown.push_str(&format!("| Parts | {} |\n", parts.len()));
+        }
+        append_to(Path::new(&summary), &markdown)?;
+    }
+    utils::print_info("🐙 Wrote GitHub Actions outputs");
+    Ok(())
+}
+
+fn append_to(path: &Path, content: &str) -> anyhow::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open '{}'", path.display()))?;
+    file.write_all(content.as_bytes())
+        .with_context(|| format!("Failed to write '{}'", path.display()))
+}
+
+/// Hash of the patched tree the output ZIP was built from, used to skip rezip
+fn rezip_hash_path(output_path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.rezip-hash", output_path.display()))
+}
+
+async fn publish_rom(
+    output_path: &Path,
+    config: &Config,
+    skip_signing: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    if config.verify_output && !dry_run {
+        let start = Instant::now();
+        crate::verify::verify_flashable(output_path).map_err(|e| {
+            let deletions = crate::verify::meta_inf_deletions(config);
+            if deletions.is_empty() {
+                e
+            } else {
+                e.context(format!(
+                    "These deletions remove META-INF content:\n  {}",
+                    deletions.join("\n  ")
+                ))
+            }
+        })?;
+        metrics::record("verify", start.elapsed());
+        utils::print_success("🔎 Output ZIP is flashable");
+    }
+    let _ = utils::run_hook(&config.hooks, "pre-sign");
+    pipeline::emit(BuildEvent::Signing {
+        output: output_path.to_path_buf(),
+    })
+    .await;
+    let start = Instant::now();
+    crate::sign::sign_rom(output_path, config, skip_signing, dry_run)
+        .await
+        .context(Failure::Signing)?;
+    metrics::record("sign", start.elapsed());
+    let _ = utils::run_hook(&config.hooks, "post-sign");
+    let parts = match config.output.split_size_mb {
+        Some(size_mb) if dry_run => {
+            utils::print_info(&format!(
+                "🔍 DRY RUN: Would split the output into parts of {} MiB",
+                size_mb
+            ));
+            vec![output_path.to_path_buf()]
+        }
+        Some(size_mb) => crate::split::split_output(output_path, size_mb)?,
+        None => vec![output_path.to_path_buf()],
+    };
+    if let Some(upload_config) = &config.upload {
+        let start = Instant::now();
+        crate::upload::upload_rom(output_path, &parts, upload_config, dry_run).await?;
+        metrics::record("upload", start.elapsed());
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use crate::utils;
+use anyhow::Context;
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+const API_URL: &str = "https://api.github.com/repos";
+
+#[derive(Debug, Deserialize)]
+struct ContentEntry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// Downloads the default branch of a GitHub repository holding a ROMMER.yaml
+/// or a `patches/` directory into `project_path`. Returns whether the
+/// repository has its own ROMMER.yaml.
+pub async fn download_repo(repo: &str, project_path: &Path) -> anyhow::Result<bool> {
+    if repo.split('/').count() != 2 || repo.split('/').any(str::is_empty) {
+        anyhow::bail!(
+            "Expected a GitHub repository as <user/repo>, got '{}'",
+            repo
+        );
+    }
+    let client = reqwest::Client::builder()
+        .user_agent(format!("rommer/{}", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    utils::print_info(&format!("🔍 Checking github.com/{}", repo));
+    let entries: Vec<ContentEntry> = client
+        .get(format!("{}/{}/contents", API_URL, repo))
+        .send()
+        .await
+        .with_context(|| format!("Failed to list the contents of '{}'", repo))?
+        .error_for_status()
+        .with_context(|| format!("Failed to list the contents of '{}'", repo))?
+        .json()
+        .await
+        .context("Failed to parse the repository contents")?;
+    let has_config = entries
+        .iter()
+        .any(|e| e.name == "ROMMER.yaml" && e.kind == "file");
+    let has_patches = entries
+        .iter()
+        .any(|e| e.name == "patches" && e.kind == "dir");
+    if !has_config && !has_patches {
+        anyhow::bail!(
+            "'{}' has neither a ROMMER.yaml nor a patches/ directory at its root",
+            repo
+        );
+    }
+
+    utils::print_info(&format!("📥 Downloading {}", repo));
+    let archive = client
+        .get(format!("{}/{}/zipball", API_URL, repo))
+        .send()
+        .await
+        .with_context(|| format!("Failed to download '{}'", repo))?
+        .error_for_status()
+        .with_context(|| format!("Failed to download '{}'", repo))?
+        .bytes()
+        .await
+        .with_context(|| format!("Failed to download '{}'", repo))?;
+    let files = extract_archive(&archive, project_path)?;
+    utils::print_success(&format!("📂 Extracted {} file(s) from {}", files, repo));
+    Ok(has_config)
+}
+
+/// Extracts a repository archive into `project_path`, dropping the
+/// `<user>-<repo>-<sha>/` folder GitHub wraps it in
+fn extract_archive(archive: &[u8], project_path: &Path) -> anyhow::Result<usize> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(archive)).context("Failed to read the repository archive")?;
+    let mut files = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(rel) = entry
+            .enclosed_name()
+            .map(|name| name.components().skip(1).collect::<PathBuf>())
+        else {
+            utils::print_warning(&format!("Skipping unsafe entry '{}'", entry.name()));
+            continue;
+        };
+        if rel.as_os_str().is_empty() {
+            continue;
+        }
+        let dest = project_path.join(&rel);
+        if entry.is_dir() {
+            fs::create_dir_all(&dest)?;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = File::create(&dest)
+            .with_context(|| format!("Failed to create '{}'", dest.display()))?;
+        std::io::copy(&mut entry, &mut out)?;
+        files += 1;
+    }
+    Ok(files)
+}
+
+/// Patch folders under the project's `patches/` directory, in name order
+pub fn find_patches(project_path: &Path) -> anyhow::Result<Vec<String>> {
+    let mut patches: Vec<String> = fs::read_dir(project_path.join("patches"))
+        .context("Failed to read the patches directory")?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| format!("patches/{}", entry.file_name().to_string_lossy()))
+        .collect();
+    patches.sort();
+    Ok(patches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_extract_archive_strips_root() -> anyhow::Result<()> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::<()>::default();
+        zip.add_directory("user-repo-abc123/", options)?;
+        zip.start_file("user-repo-abc123/patches/fonts/system/fonts/a.ttf", options)?;
+        zip.write_all(b"font")?;
+        zip.start_file("user-repo-abc123/README.md", options)?;
+        let archive = zip.finish()?.into_inner();
+
+        let project = tempdir()?;
+        assert_eq!(extract_archive(&archive, project.path())?, 2);
+        assert!(project.path().join("README.md").is_file());
+        assert_eq!(find_patches(project.path())?, ["patches/fonts"]);
+        Ok(())
+    }
+}
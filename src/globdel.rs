@@ -0,0 +1,380 @@
+use crate::config::Config;
+use crate::utils;
+use anyhow::Context;
+use regex::Regex;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Pins the glob patterns in a patch's `.rommerdel` (against the ROM's
+/// directories) and `.rommerfdel` (against its files) to the explicit paths
+/// they match in `rom_zip`, and rewrites the files with them
+pub fn convert_deletions(
+    config: &Config,
+    patch: &str,
+    rom_zip: &str,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    utils::print_section("🗂️  CONVERTING DELETION GLOBS");
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+    let (dirs, files) = split_entries(&crate::unzip::list_rom_files(&rom_path)?);
+    let mut converted = 0;
+    for (del_file, candidates) in [(".rommerdel", &dirs), (".rommerfdel", &files)] {
+        let del_path = patch_path.join(del_file);
+        if !del_path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(&del_path)
+            .with_context(|| format!("Failed to read '{}'", del_path.display()))?;
+        let (expanded, patterns) = expand_globs(&content, candidates)?;
+        if patterns.is_empty() {
+            continue;
+        }
+        println!("📄 {}", del_path.display());
+        for (pattern, matches) in &patterns {
+            if matches.is_empty() {
+                utils::print_warning(&format!("{} matches nothing, dropping it", pattern));
+                continue;
+            }
+            println!("- {}", pattern);
+            for path in matches {
+                println!("+ {}", path);
+            }
+        }
+        converted += patterns.len();
+        if !dry_run {
+            fs::write(&del_path, expanded)
+                .with_context(|| format!("Failed to write '{}'", del_path.display()))?;
+        }
+    }
+    if converted == 0 {
+        utils::print_info("No glob patterns to convert");
+    } else if dry_run {
+        utils::print_info(&format!(
+            "🔍 DRY RUN: Would convert {} pattern(s)",
+            converted
+        ));
+    } else {
+        utils::print_success(&format!("Converted {} pattern(s)", converted));
+    }
+    Ok(())
+}
+
+/// Removes the entries of a patch's `.rommerdel` (and `.rommerfdel`) that
+/// name no directory (file) of `rom_zip`, printing them. Only reports unless
+/// `apply` is set and `dry_run` isn't.
+pub fn prune_stale_deletions(
+    config: &Config,
+    patch: &str,
+    rom_zip: &str,
+    apply: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    utils::print_section("🧹 PRUNING STALE DELETIONS");
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+    let (dirs, files) = split_entries(&crate::unzip::list_rom_files(&rom_path)?);
+    let write = apply && !dry_run;
+    let mut pruned = 0;
+    for (del_file, candidates) in [(".rommerdel", &dirs), (".rommerfdel", &files)] {
+        let del_path = patch_path.join(del_file);
+        if !del_path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(&del_path)
+            .with_context(|| format!("Failed to read '{}'", del_path.display()))?;
+        let (kept, stale) = prune_lines(&content, candidates, config.android_version)
+            .with_context(|| format!("Failed to parse '{}'", del_path.display()))?;
+        if stale.is_empty() {
+            continue;
+        }
+        println!("📄 {}", del_path.display());
+        for entry in &stale {
+            println!("- {}", entry);
+        }
+        pruned += stale.len();
+        if write {
+            fs::write(&del_path, kept)
+                .with_context(|| format!("Failed to write '{}'", del_path.display()))?;
+        }
+    }
+    if pruned == 0 {
+        utils::print_success("No stale deletions");
+    } else if write {
+        utils::print_success(&format!("Removed {} stale deletion(s)", pruned));
+    } else {
+        utils::print_info(&format!(
+            "Found {} stale deletion(s), pass --apply to remove them",
+            pruned
+        ));
+    }
+    Ok(())
+}
+
+/// `content` without the path lines that aren't in `candidates`, and those
+/// lines. Blank lines, `[[ ]]` directives, glob patterns and lines in
+/// `[[ android ]]` blocks that don't apply to `android_version` are kept.
+fn prune_lines(
+    content: &str,
+    candidates: &BTreeSet<String>,
+    android_version: u32,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let mut kept = String::new();
+    let mut stale = Vec::new();
+    let mut conditions: Vec<bool> = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(directive) = trimmed
+            .strip_prefix("[[")
+            .and_then(|rest| rest.strip_suffix("]]"))
+        {
+            let directive = directive.trim();
+            if directive == "end" {
+                if conditions.pop().is_none() {
+                    anyhow::bail!("line {}: '[[ end ]]' without an open block", number + 1);
+                }
+            } else if let Some(requirement) = directive.strip_prefix("android") {
+                conditions.push(utils::android_version_matches(requirement, android_version));
+            } else {
+                anyhow::bail!("line {}: unknown condition '{}'", number + 1, directive);
+            }
+        }
+        let active = conditions.iter().all(|c| *c);
+        if trimmed.is_empty()
+            || trimmed.starts_with("[[")
+            || !active
+            || is_glob(trimmed)
+            || candidates.contains(trimmed.trim_end_matches('/'))
+        {
+            kept.push_str(line);
+            kept.push('\n');
+        } else {
+            stale.push(trimmed.to_string());
+        }
+    }
+    if !conditions.is_empty() {
+        anyhow::bail!("missing '[[ end ]]' for {} block(s)", conditions.len());
+    }
+    Ok((kept, stale))
+}
+
+/// Splits a ZIP listing into directories (entries with something under them)
+/// and files
+fn split_entries(entries: &HashSet<String>) -> (BTreeSet<String>, BTreeSet<String>) {
+    let dirs: BTreeSet<String> = entries
+        .iter()
+        .filter_map(|entry| entry.rsplit_once('/').map(|(parent, _)| parent.to_string()))
+        .collect();
+    let files = entries
+        .iter()
+        .filter(|entry| !dirs.contains(*entry))
+        .cloned()
+        .collect();
+    (dirs, files)
+}
+
+/// A glob pattern and the paths it matches
+type PatternMatches = (String, Vec<String>);
+
+/// Whether a deletion entry is a glob pattern rather than a path
+pub fn is_glob(line: &str) -> bool {
+    line.contains(['*', '?', '['])
+}
+
+/// Regex matching the same paths as a glob pattern: `*` and `?` don't cross
+/// `/`, `**` does, and `[...]` is a character class
+pub fn glob_regex(pattern: &str) -> anyhow::Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if matches!(c, '\\' | '[' | '&' | '~') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).with_context(|| format!("Invalid glob pattern '{}'", pattern))
+}
+
+/// The paths under `root` a deletion entry names: the entry itself, or for a
+/// glob pattern the directories (with `dirs`) or files it matches, sorted
+pub fn expand_deletion(root: &Path, item: &Path, dirs: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let pattern = item.to_string_lossy();
+    if !is_glob(&pattern) {
+        return Ok(vec![item.to_path_buf()]);
+    }
+    let regex = glob_regex(pattern.trim_end_matches('/'))?;
+    let mut matches = Vec::new();
+    for entry in WalkDir::new(root).min_depth(1).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_dir() != dirs {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(root)?;
+        if regex.is_match(&crate::rezip::zip_entry_name(rel)) {
+            matches.push(rel.to_path_buf());
+        }
+    }
+    Ok(matches)
+}
+
+/// Whether a deletion entry names one of `entries`, matching glob patterns
+pub fn names_entry(item: &str, entries: &HashSet<String>) -> anyhow::Result<bool> {
+    let item = item.trim_end_matches('/');
+    if !is_glob(item) {
+        return Ok(entries.contains(item));
+    }
+    let regex = glob_regex(item)?;
+    Ok(entries.iter().any(|entry| regex.is_match(entry)))
+}
+
+/// `content` with every glob pattern line replaced by the sorted paths of
+/// `candidates` it matches, in place so `[[ android ]]` blocks still apply,
+/// and each pattern with its matches
+fn expand_globs(
+    content: &str,
+    candidates: &BTreeSet<String>,
+) -> anyhow::Result<(String, Vec<PatternMatches>)> {
+    let mut expanded = String::new();
+    let mut patterns = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        let directive = trimmed.starts_with("[[") && trimmed.ends_with("]]");
+        if directive || !is_glob(trimmed) {
+            expanded.push_str(line);
+            expanded.push('\n');
+            continue;
+        }
+        let regex = glob_regex(trimmed.trim_end_matches('/'))?;
+        let matches: Vec<String> = candidates
+            .iter()
+            .filter(|path| regex.is_match(path))
+            .cloned()
+            .collect();
+        for path in &matches {
+            expanded.push_str(path);
+            expanded.push('\n');
+        }
+        patterns.push((trimmed.to_string(), matches));
+    }
+    Ok((expanded, patterns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_lines() {
+        let dirs: BTreeSet<String> = ["system/app", "system/app/Calculator"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let content = "system/app/Calculator/\n[[ android >= 14 ]]\nsystem/app/Gallery2\n[[ end ]]\n[[ android < 14 ]]\nsystem/app/Music\n[[ end ]]\n\nsystem/app/Google*\n";
+        let (kept, stale) = prune_lines(content, &dirs, 15).unwrap();
+        assert_eq!(
+            kept,
+            "system/app/Calculator/\n[[ android >= 14 ]]\n[[ end ]]\n[[ android < 14 ]]\nsystem/app/Music\n[[ end ]]\n\nsystem/app/Google*\n"
+        );
+        assert_eq!(stale, ["system/app/Gallery2"]);
+        assert!(prune_lines("[[ android >= 14 ]]\n", &dirs, 15).is_err());
+    }
+
+    #[test]
+    fn test_expand_deletion() -> anyhow::Result<()> {
+        let rom = tempfile::tempdir()?;
+        for app in ["GoogleMaps", "GooglePay", "Calculator"] {
+            fs::create_dir_all(rom.path().join("system/app").join(app))?;
+            fs::write(
+                rom.path()
+                    .join("system/app")
+                    .join(app)
+                    .join(format!("{}.apk", app)),
+                "apk",
+            )?;
+        }
+        assert_eq!(
+            expand_deletion(rom.path(), Path::new("system/app/Google*"), true)?,
+            [
+                Path::new("system/app/GoogleMaps"),
+                Path::new("system/app/GooglePay")
+            ]
+        );
+        assert_eq!(
+            expand_deletion(rom.path(), Path::new("system/**/Calc*.apk"), true)?,
+            Vec::<PathBuf>::new()
+        );
+        assert_eq!(
+            expand_deletion(rom.path(), Path::new("system/**/Calc*.apk"), false)?,
+            [Path::new("system/app/Calculator/Calculator.apk")]
+        );
+        assert_eq!(
+            expand_deletion(rom.path(), Path::new("system/app/Missing"), true)?,
+            [Path::new("system/app/Missing")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_globs() -> anyhow::Result<()> {
+        let entries: HashSet<String> = [
+            "system",
+            "system/app",
+            "system/app/GoogleMaps",
+            "system/app/GoogleMaps/GoogleMaps.apk",
+            "system/app/GooglePay",
+            "system/app/GooglePay/GooglePay.apk",
+            "system/app/Calculator",
+            "system/app/Calculator/Calculator.apk",
+            "system/priv-app",
+            "system/priv-app/GoogleServices",
+            "system/priv-app/GoogleServices/GoogleServices.apk",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let (dirs, files) = split_entries(&entries);
+        assert!(dirs.contains("system/app/Calculator"));
+        assert!(files.contains("system/app/Calculator/Calculator.apk"));
+
+        let content = "system/app/Calculator\n[[ android >= 14 ]]\nsystem/app/Google*\n[[ end ]]\nsystem/*/Foo?\n";
+        let (expanded, patterns) = expand_globs(content, &dirs)?;
+        assert_eq!(
+            expanded,
+            "system/app/Calculator\n[[ android >= 14 ]]\nsystem/app/GoogleMaps\nsystem/app/GooglePay\n[[ end ]]\n"
+        );
+        assert_eq!(patterns.len(), 2);
+        assert!(patterns[1].1.is_empty());
+
+        let (expanded, _) = expand_globs("system/**/Google[!M]*.apk\n", &files)?;
+        assert_eq!(
+            expanded,
+            "system/app/GooglePay/GooglePay.apk\nsystem/priv-app/GoogleServices/GoogleServices.apk\n"
+        );
+        Ok(())
+    }
+}
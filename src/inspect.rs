@@ -0,0 +1,199 @@
+use crate::checksum;
+use crate::utils;
+use crate::verify::UPDATER_SCRIPT;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+const BUILD_PROP: &str = "system/build.prop";
+const METADATA: &str = "META-INF/com/android/metadata";
+
+/// Values read from an existing ROM ZIP to pre-fill ROMMER.yaml
+#[derive(Debug, Default, PartialEq)]
+pub struct RomInfo {
+    pub device: Option<String>,
+    pub rom: Option<String>,
+    pub version: Option<String>,
+    pub android_version: Option<u32>,
+    pub variant: Option<String>,
+    pub timestamp: Option<String>,
+    pub checksum: String,
+}
+
+pub fn inspect_rom(zip_path: &Path) -> anyhow::Result<RomInfo> {
+    utils::print_info(&format!("🔎 Inspecting ROM: {}", zip_path.display()));
+    let file = File::open(zip_path)
+        .with_context(|| format!("Failed to open zip file '{}'", zip_path.display()))?;
+    let mut archive = ZipArchive::new(file).context("Failed to read zip archive")?;
+    let build_prop = parse_props(&read_entry(&mut archive, BUILD_PROP));
+    let metadata = parse_props(&read_entry(&mut archive, METADATA));
+    let updater_script = read_entry(&mut archive, UPDATER_SCRIPT);
+    let mut info = rom_info(&build_prop, &metadata, &updater_script);
+    info.checksum = checksum::calculate_file_checksum(zip_path)?;
+    Ok(info)
+}
+
+/// Reads a text entry from the ZIP, or an empty string if it is missing
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> String {
+    let mut content = String::new();
+    match archive.by_name(name) {
+        Ok(mut entry) => {
+            let _ = entry.read_to_string(&mut content);
+        }
+        Err(_) => utils::print_warning(&format!("'{}' not found in ROM", name)),
+    }
+    content
+}
+
+fn parse_props(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn rom_info(
+    build_prop: &HashMap<String, String>,
+    metadata: &HashMap<String, String>,
+    updater_script: &str,
+) -> RomInfo {
+    let prop = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| build_prop.get(*key).filter(|v| !v.is_empty()).cloned())
+    };
+    let device = prop(&["ro.lineage.device", "ro.product.device", "ro.build.product"])
+        .or_else(|| metadata.get("pre-device").cloned())
+        .or_else(|| updater_script_device(updater_script));
+    let android_version = prop(&["ro.build.version.release"])
+        .or_else(|| {
+            // post-build=google/bluejay/bluejay:15/AP4A.250205.002/...
+            metadata
+                .get("post-build")
+                .and_then(|b| b.split(':').nth(1))
+                .and_then(|v| v.split('/').next())
+                .map(str::to_string)
+        })
+        .and_then(|v| v.split('.').next().and_then(|major| major.parse().ok()));
+    let mut info = RomInfo {
+        device,
+        android_version,
+        timestamp: metadata.get("post-timestamp").cloned(),
+        ..Default::default()
+    };
+
+    if let Some(version) = prop(&["ro.lineage.version"]) {
+        // 22.2-20250614-NIGHTLY-bluejay
+        let mut parts = version.split('-');
+        info.rom = Some("lineageos".to_string());
+        info.version = parts.next().map(str::to_string);
+        info.timestamp = parts.next().map(str::to_string).or(info.timestamp);
+        info.variant = parts.next().map(str::to_lowercase);
+    } else if let Some(version) = prop(&["org.evolution.version", "org.evolution.build_version"]) {
+        info.rom = Some("evolutionx".to_string());
+        info.version = Some(version);
+    } else if let Some(version) = prop(&["org.pixelexperience.version"]) {
+        info.rom = Some("pixelexperience".to_string());
+        info.version = Some(version);
+    } else if let Some(version) = prop(&["org.pixelos.version", "ro.pixelos.version"]) {
+        info.rom = Some("pixelos".to_string());
+        info.version = Some(version);
+    }
+    if info.variant.is_none() {
+        info.variant = prop(&["ro.lineage.releasetype", "ro.build.type"]).map(|v| v.to_lowercase());
+    }
+    if info.timestamp.is_none() {
+        info.timestamp = prop(&["ro.build.date.utc"]);
+    }
+    info
+}
+
+/// Device codename from an `assert(getprop("ro.product.device") == "...")` line
+fn updater_script_device(updater_script: &str) -> Option<String> {
+    let re = regex::Regex::new(r#"getprop\("ro\.product\.device"\)\s*==\s*"([^"]+)""#).ok()?;
+    re.captures(updater_script).map(|c| c[1].to_string())
+}
+
+impl RomInfo {
+    /// ROMMER.yaml pre-filled with the detected values. Values that could not
+    /// be detected are left as placeholders.
+    pub fn to_config(&self) -> String {
+        let value = |v: &Option<String>, placeholder: &str| match v {
+            Some(v) => format!("\"{}\"", v),
+            None => format!("{} # not detected, fill in", placeholder),
+        };
+        format!(
+            r#"# yaml-language-server: $schema=https://raw.githubusercontent.com/TheROMMER/core/main/rommer.schema.json
+device: {}
+rom: {}
+max_retries: 3
+version: {}
+android_version: {}
+timestamp: {}
+variant: {}
+expected_checksum: "{}"
+patches:
+  - example_patch/
+
+output:
+  filename: "{{device}}-{{version}}.zip"
+
+cleanup: true
+"#,
+            value(&self.device, "your_device_codename"),
+            value(&self.rom, "lineageos"),
+            value(&self.version, "20.0"),
+            match self.android_version {
+                Some(v) => v.to_string(),
+                None => "15 # not detected, fill in".to_string(),
+            },
+            value(&self.timestamp, "20250614"),
+            value(&self.variant, "nightly"),
+            self.checksum
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_info_lineageos() {
+        let build_prop = parse_props(
+            "# begin build properties\nro.build.version.release=15\nro.lineage.version=22.2-20250614-NIGHTLY-bluejay\nro.lineage.device=bluejay\n",
+        );
+        let info = rom_info(&build_prop, &HashMap::new(), "");
+        assert_eq!(
+            info,
+            RomInfo {
+                device: Some("bluejay".to_string()),
+                rom: Some("lineageos".to_string()),
+                version: Some("22.2".to_string()),
+                android_version: Some(15),
+                variant: Some("nightly".to_string()),
+                timestamp: Some("20250614".to_string()),
+                checksum: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_rom_info_fallbacks() {
+        let metadata = parse_props(
+            "post-build=google/bluejay/bluejay:14/UP1A/123:user/release-keys\npost-timestamp=1718000000\n",
+        );
+        let script =
+            r#"assert(getprop("ro.product.device") == "bluejay" || abort("wrong device"));"#;
+        let info = rom_info(&HashMap::new(), &metadata, script);
+        assert_eq!(info.device.as_deref(), Some("bluejay"));
+        assert_eq!(info.android_version, Some(14));
+        assert_eq!(info.timestamp.as_deref(), Some("1718000000"));
+        assert_eq!(info.rom, None);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::checksum;
+use crate::config::Config;
+use crate::patchmeta;
+use crate::utils;
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// SHA-256 of every file in a patch folder by forward-slash relative path,
+/// leaving out `patch.yaml` itself
+pub fn file_hashes(patch_path: &Path) -> anyhow::Result<HashMap<String, String>> {
+    let mut hashes = HashMap::new();
+    for entry in WalkDir::new(patch_path).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() || entry.path() == patch_path.join("patch.yaml") {
+            continue;
+        }
+        let rel = crate::rezip::zip_entry_name(entry.path().strip_prefix(patch_path)?);
+        hashes.insert(rel, checksum::calculate_file_checksum(entry.path())?);
+    }
+    Ok(hashes)
+}
+
+/// Compares the files of a patch against its `file_hashes`, returning one
+/// message per modified, missing or unlisted file
+pub fn integrity_problems(
+    patch_path: &Path,
+    expected: &HashMap<String, String>,
+) -> anyhow::Result<Vec<String>> {
+    let actual = file_hashes(patch_path)?;
+    let mut problems = Vec::new();
+    for (name, hash) in expected {
+        match actual.get(name) {
+            Some(actual_hash) if actual_hash.eq_ignore_ascii_case(hash) => {}
+            Some(_) => problems.push(format!("modified: {}", name)),
+            None => problems.push(format!("missing: {}", name)),
+        }
+    }
+    for name in actual.keys().filter(|name| !expected.contains_key(*name)) {
+        problems.push(format!("not listed: {}", name));
+    }
+    problems.sort();
+    Ok(problems)
+}
+
+/// Recomputes a patch's `patch_checksum` and sets it in its `patch.yaml`,
+/// keeping the rest of the file
+pub fn update_checksum(config: &Config, patch: &str) -> anyhow::Result<()> {
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let manifest_path = patch_path.join("patch.yaml");
+    let (content, mapping) = patchmeta::read_manifest(&patch_path)?;
+    let old = mapping
+        .get("patch_checksum")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let new = checksum::calculate_directory_checksum(&patch_path)?;
+    println!("old: {}", old.as_deref().unwrap_or("(none)"));
+    println!("new: {}", new);
+    if old
+        .as_deref()
+        .is_some_and(|old| old.eq_ignore_ascii_case(&new))
+    {
+        utils::print_success(&format!("{} checksum is up to date", patch_path.display()));
+        return Ok(());
+    }
+    let updated = crate::config::set_scalar(&content, "patch_checksum", &new)?;
+    std::fs::write(&manifest_path, updated)
+        .with_context(|| format!("Failed to write '{}'", manifest_path.display()))?;
+    utils::print_success(&format!(
+        "🔒 Updated patch_checksum in {}",
+        manifest_path.display()
+    ));
+    Ok(())
+}
+
+/// Prints the Merkle tree hash of a patch, and with `--verbose` the hash of
+/// every file
+pub fn hash_tree(config: &Config, patch: &str) -> anyhow::Result<()> {
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let mut leaves = Vec::new();
+    let root = tree_node_hash(&patch_path, &patch_path, &mut leaves)?;
+    if utils::is_verbose() {
+        for (path, hash) in &leaves {
+            println!("{}  {}", hash, path);
+        }
+    }
+    println!("{}", root);
+    Ok(())
+}
+
+/// Hash of a node of a patch's Merkle tree. A file hashes its path relative to
+/// the patch root and its contents; a directory hashes the names and hashes of
+/// its children in sorted order. Since file paths are included, a subtree only
+/// hashes the same at the same place in the patch. `patch.yaml` and the patch
+/// signature at the root are left out. A symlink hashes its path and target
+/// instead of being followed. File hashes are collected into `leaves`.
+fn tree_node_hash(
+    root: &Path,
+    path: &Path,
+    leaves: &mut Vec<(String, String)>,
+) -> anyhow::Result<String> {
+    let rel = crate::rezip::zip_entry_name(path.strip_prefix(root)?);
+    let mut hasher = Sha256::new();
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    if metadata.is_symlink() {
+        let target = std::fs::read_link(path)
+            .with_context(|| format!("Failed to read link '{}'", path.display()))?;
+        hasher.update(rel.as_bytes());
+        hasher.update([1]);
+        hasher.update(crate::rezip::zip_entry_name(&target).as_bytes());
+        let hash = format!("{:x}", hasher.finalize());
+        leaves.push((rel, hash.clone()));
+        return Ok(hash);
+    }
+    if metadata.is_file() {
+        hasher.update(rel.as_bytes());
+        hasher.update([0]);
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open '{}'", path.display()))?;
+        std::io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let hash = format!("{:x}", hasher.finalize());
+        leaves.push((rel, hash.clone()));
+        return Ok(hash);
+    }
+    let mut children: Vec<PathBuf> = std::fs::read_dir(path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    children.sort();
+    for child in children {
+        let name = child.file_name().unwrap_or_default().to_string_lossy();
+        if path == root && (name == "patch.yaml" || name == crate::patchsig::SIGNATURE_FILE) {
+            continue;
+        }
+        let child_hash = tree_node_hash(root, &child, leaves)?;
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(child_hash.as_bytes());
+        hasher.update([b'\n']);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Checks one patch, or every configured patch, against the `file_hashes`
+/// in its `patch.yaml`. Patches without `file_hashes` are skipped.
+pub fn check_integrity(config: &Config, patch: Option<&str>) -> anyhow::Result<()> {
+    utils::print_section("🔒 CHECKING PATCH INTEGRITY");
+    let patches: Vec<PathBuf> = match patch {
+        Some(patch) => vec![crate::bundle::resolve_patch(config, patch)?],
+        None => config
+            .patches
+            .iter()
+            .map(|p| config.resolve_patch_path(p.trim_end_matches('/')))
+            .collect(),
+    };
+    let mut failed = 0;
+    for patch_path in &patches {
+        let Some(expected) = patchmeta::load_patch_meta(patch_path).and_then(|m| m.file_hashes)
+        else {
+            utils::print_warning(&format!(
+                "⚠️ Patch '{}' has no file_hashes, skipping",
+                patch_path.display()
+            ));
+            continue;
+        };
+        let problems = integrity_problems(patch_path, &expected)?;
+        if problems.is_empty() {
+            utils::print_success(&format!(
+                "✅ Patch '{}': {} file(s) verified",
+                patch_path.display(),
+                expected.len()
+            ));
+        } else {
+            failed += 1;
+            utils::print_warning(&format!(
+                "❌ Patch '{}' was modified:\n  {}",
+                patch_path.display(),
+                problems.join("\n  ")
+            ));
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} patch(es) failed the integrity check", failed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_integrity_problems() -> anyhow::Result<()> {
+        let patch = tempdir()?;
+        fs::create_dir_all(patch.path().join("system"))?;
+        fs::write(patch.path().join("system").join("a.txt"), "a")?;
+        fs::write(patch.path().join("system").join("b.txt"), "b")?;
+        fs::write(patch.path().join("patch.yaml"), "name: test")?;
+        let mut expected = file_hashes(patch.path())?;
+        assert_eq!(expected.len(), 2);
+        assert!(integrity_problems(patch.path(), &expected)?.is_empty());
+
+        fs::write(patch.path().join("system").join("a.txt"), "tampered")?;
+        fs::write(patch.path().join("system").join("c.txt"), "c")?;
+        expected.insert("system/gone.txt".to_string(), "00".to_string());
+        assert_eq!(
+            integrity_problems(patch.path(), &expected)?,
+            vec![
+                "missing: system/gone.txt",
+                "modified: system/a.txt",
+                "not listed: system/c.txt"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_node_hash() -> anyhow::Result<()> {
+        let root_hash = |dir: &Path| tree_node_hash(dir, dir, &mut Vec::new());
+        let (a, b) = (tempdir()?, tempdir()?);
+        for (dir, manifest) in [(&a, "name: a"), (&b, "name: b")] {
+            fs::create_dir_all(dir.path().join("system/etc"))?;
+            fs::write(dir.path().join("system/etc/hosts"), "127.0.0.1 localhost")?;
+            fs::write(dir.path().join("patch.yaml"), manifest)?;
+        }
+        assert_eq!(root_hash(a.path())?, root_hash(b.path())?);
+
+        fs::create_dir_all(b.path().join("vendor/etc"))?;
+        fs::rename(
+            b.path().join("system/etc/hosts"),
+            b.path().join("vendor/etc/hosts"),
+        )?;
+        fs::remove_dir_all(b.path().join("system"))?;
+        assert_ne!(root_hash(a.path())?, root_hash(b.path())?);
+
+        let mut leaves = Vec::new();
+        tree_node_hash(a.path(), a.path(), &mut leaves)?;
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(leaves[0].0, "system/etc/hosts");
+
+        // A link to a parent directory is hashed, not followed
+        #[cfg(unix)]
+        {
+            let before = root_hash(a.path())?;
+            std::os::unix::fs::symlink("..", a.path().join("system/loop"))?;
+            assert_ne!(root_hash(a.path())?, before);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! ROMMER customizes Android ROM ZIPs with patch folders. The `rommer`
+//! binary is a thin command line over these modules; run a build from
+//! another program with [`pipeline::BuildPipeline`].
+
+pub mod apk;
+pub mod archive;
+pub mod args;
+pub mod audit;
+pub mod benchmark;
+pub mod bundle;
+pub mod changelog;
+pub mod checksum;
+pub mod ci;
+pub mod config;
+pub mod debloat;
+pub mod deps;
+pub mod doctor;
+pub mod download;
+pub mod env;
+pub mod finalize;
+pub mod github;
+pub mod globdel;
+pub mod inspect;
+pub mod integrity;
+pub mod lint;
+pub mod lock;
+pub mod magisk;
+pub mod manage;
+pub mod manifest;
+pub mod matrix;
+pub mod metrics;
+pub mod notify;
+pub mod patchmeta;
+pub mod patchsig;
+pub mod patchtest;
+pub mod pin;
+pub mod pipeline;
+pub mod plan;
+pub mod prop;
+pub mod provenance;
+pub mod rezip;
+pub mod search;
+pub mod secrets;
+pub mod sign;
+pub mod simulate;
+pub mod split;
+pub mod steps;
+pub mod unzip;
+pub mod upload;
+pub mod utils;
+pub mod verify;
+pub mod version;
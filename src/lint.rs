@@ -0,0 +1,239 @@
+use crate::config::Config;
+use crate::patchmeta::PatchMeta;
+use crate::utils;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const KNOWN_PREFIXES: [&str; 3] = ["system/", "vendor/", "META-INF/"];
+
+pub fn lint_patches(config: &Config, rom_zip: Option<&str>) -> anyhow::Result<()> {
+    utils::print_section("🔎 LINTING PATCHES");
+    let rom_path = match rom_zip {
+        Some(path) => PathBuf::from(shellexpand::tilde(path).to_string()),
+        None => PathBuf::from(crate::download::rom_filename(config)),
+    };
+    let rom_entries = if rom_path.exists() {
+        utils::print_info(&format!(
+            "📦 Using ROM listing from: {}",
+            rom_path.display()
+        ));
+        Some(crate::unzip::list_zip_entries(&rom_path)?)
+    } else {
+        utils::print_warning(&format!(
+            "ROM '{}' not found, skipping deletion checks",
+            rom_path.display()
+        ));
+        None
+    };
+
+    let mut issues = Vec::new();
+    for patch_folder in &config.patches {
+        let patch_path = &config.resolve_patch_path(patch_folder);
+        if !patch_path.exists() {
+            issues.push(format!("{}: patch folder does not exist", patch_folder));
+            continue;
+        }
+        lint_patch_meta(patch_folder, patch_path, &mut issues);
+        lint_patch_files(patch_folder, patch_path, &mut issues);
+        if let Some(entries) = &rom_entries {
+            for del_file in [".rommerdel", ".rommerfdel"] {
+                let del_path = patch_path.join(del_file);
+                if !del_path.exists() {
+                    continue;
+                }
+                for item in utils::read_paths(&del_path, config.android_version)? {
+                    let item = item.to_string_lossy().trim_end_matches('/').to_string();
+                    if !crate::globdel::names_entry(&item, entries)? {
+                        issues.push(format!(
+                            "{}: {} entry '{}' does not exist in the ROM",
+                            patch_folder, del_file, item
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if issues.is_empty() {
+        utils::print_success(&format!(
+            "No issues found in {} patches",
+            config.patches.len()
+        ));
+        return Ok(());
+    }
+    for issue in &issues {
+        utils::print_warning(issue);
+    }
+    anyhow::bail!("Lint found {} issue(s)", issues.len())
+}
+
+fn lint_patch_meta(patch_folder: &str, patch_path: &Path, issues: &mut Vec<String>) {
+    let manifest_path = patch_path.join("patch.yaml");
+    if !manifest_path.exists() {
+        issues.push(format!("{}: missing patch.yaml", patch_folder));
+        return;
+    }
+    let meta = fs::read_to_string(&manifest_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| serde_yaml::from_str::<PatchMeta>(&content).map_err(Into::into));
+    match meta {
+        Ok(meta) => {
+            if meta.name.is_none() {
+                issues.push(format!("{}: patch.yaml is missing 'name'", patch_folder));
+            }
+            if meta.author.is_none() {
+                issues.push(format!("{}: patch.yaml is missing 'author'", patch_folder));
+            }
+        }
+        Err(e) => issues.push(format!("{}: invalid patch.yaml: {}", patch_folder, e)),
+    }
+}
+
+fn lint_patch_files(patch_folder: &str, patch_path: &Path, issues: &mut Vec<String>) {
+    for rel in utils::patch_files(patch_path) {
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if !KNOWN_PREFIXES.iter().any(|prefix| rel.starts_with(prefix)) {
+            issues.push(format!(
+                "{}: '{}' is outside of {}",
+                patch_folder,
+                rel,
+                KNOWN_PREFIXES.join(", ")
+            ));
+        }
+    }
+}
+
+/// How the files and deletions of a patch relate to the contents of a ROM
+#[derive(Debug, Default, PartialEq)]
+struct RomConflicts {
+    /// Files that replace a file of the ROM, usually on purpose
+    overrides: Vec<String>,
+    /// Files whose first two directories don't exist in the ROM, maybe a typo
+    unusual: Vec<String>,
+    /// Deletion entries that don't exist in the ROM
+    stale_deletions: Vec<String>,
+}
+
+fn rom_conflicts(
+    files: &[String],
+    deletions: &[String],
+    entries: &HashSet<String>,
+) -> anyhow::Result<RomConflicts> {
+    let mut conflicts = RomConflicts::default();
+    for file in files {
+        if entries.contains(file) {
+            conflicts.overrides.push(file.clone());
+            continue;
+        }
+        let dirs: Vec<&str> = file.split('/').collect();
+        let depth = (dirs.len() - 1).min(2);
+        let missing = (1..=depth).any(|n| !entries.contains(&dirs[..n].join("/")));
+        if missing {
+            conflicts.unusual.push(file.clone());
+        }
+    }
+    for item in deletions {
+        if !crate::globdel::names_entry(item, entries)? {
+            conflicts.stale_deletions.push(item.clone());
+        }
+    }
+    Ok(conflicts)
+}
+
+/// Compares every configured patch against the listing of a ROM ZIP and
+/// reports the ROM files it overrides, files it adds to directories the ROM
+/// doesn't have, and deletions of paths the ROM doesn't have. Fails when any
+/// of the latter two are found.
+pub fn find_conflicts(config: &Config, rom_zip: &str) -> anyhow::Result<()> {
+    utils::print_section("🔍 FINDING CONFLICTS WITH THE ROM");
+    let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+    let entries = crate::unzip::list_rom_files(&rom_path)?;
+    let mut problems = 0;
+    for patch_folder in &config.patches {
+        let patch_path = config.resolve_patch_path(patch_folder);
+        if !patch_path.exists() {
+            utils::print_warning(&format!("Patch folder '{}' does not exist!", patch_folder));
+            continue;
+        }
+        let files: Vec<String> = utils::patch_files(&patch_path)
+            .iter()
+            .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+            .collect();
+        let mut deletions = Vec::new();
+        for del_file in [".rommerdel", ".rommerfdel"] {
+            let del_path = patch_path.join(del_file);
+            if del_path.exists() {
+                for item in utils::read_paths(&del_path, config.android_version)? {
+                    let item = item.to_string_lossy().replace('\\', "/");
+                    deletions.push(item.trim_end_matches('/').to_string());
+                }
+            }
+        }
+        let conflicts = rom_conflicts(&files, &deletions, &entries)?;
+        println!("📦 {}", patch_folder);
+        for file in &conflicts.overrides {
+            println!("   override: {}", file);
+        }
+        for file in &conflicts.unusual {
+            utils::print_warning(&format!("{}: non-standard path {}", patch_folder, file));
+        }
+        for item in &conflicts.stale_deletions {
+            utils::print_warning(&format!("{}: stale deletion {}", patch_folder, item));
+        }
+        problems += conflicts.unusual.len() + conflicts.stale_deletions.len();
+    }
+    if problems > 0 {
+        anyhow::bail!("Found {} possible problem(s)", problems);
+    }
+    utils::print_success("No non-standard paths or stale deletions");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_conflicts() -> anyhow::Result<()> {
+        let entries: HashSet<String> = [
+            "system",
+            "system/app",
+            "system/app/Foo",
+            "system/app/Foo/Foo.apk",
+            "system/build.prop",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let files = [
+            "system/build.prop",
+            "system/app/Bar/Bar.apk",
+            "system/ap/Bar/Bar.apk",
+            "sytem/build.prop",
+        ]
+        .map(String::from);
+        let deletions = [
+            "system/app/Foo",
+            "system/app/Gone",
+            "system/app/F*",
+            "system/priv-app/*",
+        ]
+        .map(String::from);
+        assert_eq!(
+            rom_conflicts(&files, &deletions, &entries)?,
+            RomConflicts {
+                overrides: vec!["system/build.prop".to_string()],
+                unusual: vec![
+                    "system/ap/Bar/Bar.apk".to_string(),
+                    "sytem/build.prop".to_string()
+                ],
+                stale_deletions: vec![
+                    "system/app/Gone".to_string(),
+                    "system/priv-app/*".to_string()
+                ],
+            }
+        );
+        Ok(())
+    }
+}
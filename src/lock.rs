@@ -0,0 +1,140 @@
+use crate::utils;
+use anyhow::Context;
+use fs2::FileExt;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const LOCK_FILE: &str = ".rommer.lock";
+/// Exit code when another ROMMER build holds the lock (EX_TEMPFAIL)
+pub const LOCK_HELD_EXIT_CODE: i32 = 75;
+
+/// Exclusive build lock, removed and released when dropped
+pub struct BuildLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        // Remove the file while it is still locked, so no other build can lock
+        // it in between and then have it deleted
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Another build holds the lock; `main` exits with [`LOCK_HELD_EXIT_CODE`]
+#[derive(Debug)]
+pub struct LockHeld {
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for LockHeld {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Another ROMMER build is running in this directory (PID {}). Use --break-lock to remove a stale lock.",
+            self.pid
+                .map_or_else(|| "unknown".to_string(), |pid| pid.to_string())
+        )
+    }
+}
+
+impl std::error::Error for LockHeld {}
+
+pub fn acquire(break_lock: bool) -> anyhow::Result<BuildLock> {
+    let path = PathBuf::from(LOCK_FILE);
+    if let Some(lock) = try_acquire(&path)? {
+        return Ok(lock);
+    }
+    let holder = read_pid(&path);
+    if break_lock {
+        match holder {
+            Some(pid) if !pid_is_running(pid) => {
+                utils::print_warning("Breaking stale build lock");
+                fs::remove_file(&path).context("Failed to remove stale lock file")?;
+                if let Some(lock) = try_acquire(&path)? {
+                    return Ok(lock);
+                }
+            }
+            Some(pid) => utils::print_warning(&format!(
+                "Lock is held by running process {}, refusing to break it",
+                pid
+            )),
+            None => utils::print_warning(
+                "Lock holder is unknown (unreadable PID), refusing to break it",
+            ),
+        }
+    }
+    Err(LockHeld { pid: holder }.into())
+}
+
+fn try_acquire(path: &Path) -> anyhow::Result<Option<BuildLock>> {
+    loop {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file '{}'", path.display()))?;
+        if file.try_lock_exclusive().is_err() {
+            return Ok(None);
+        }
+        // The file may have been removed by its previous holder after we opened
+        // it, in which case the lock is on a file nobody else will see
+        if !is_current(&file, path) {
+            continue;
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id()).context("Failed to write lock file")?;
+        return Ok(Some(BuildLock {
+            file,
+            path: path.to_path_buf(),
+        }));
+    }
+}
+
+/// Whether `path` still names the open `file`
+#[cfg(unix)]
+fn is_current(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(named)) => open.dev() == named.dev() && open.ino() == named.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_current(_file: &File, _path: &Path) -> bool {
+    // Windows can't remove a file that is still open
+    true
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    let mut content = String::new();
+    File::open(path).ok()?.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn pid_is_running(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(true)
+}
+
+#[cfg(not(unix))]
+fn pid_is_running(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+        .unwrap_or(true)
+}
@@ -0,0 +1,192 @@
+use crate::utils;
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+use zip::ZipArchive;
+
+/// `patch.yaml` generated from a module's `module.prop`
+#[derive(Debug, Serialize)]
+struct ImportedMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+/// Converts a Magisk module ZIP to a ROMMER patch: its `system/` overlay is
+/// copied as is, `module.prop` becomes `patch.yaml` and the paths in its
+/// `remove` file become `.rommerfdel` entries. The patch is written to
+/// `output`, or a folder named after the module id.
+pub fn import_module(module_zip: &str, output: Option<&str>) -> anyhow::Result<()> {
+    utils::print_section("🧩 IMPORTING MAGISK MODULE");
+    let zip_path = PathBuf::from(shellexpand::tilde(module_zip).to_string());
+    let file = File::open(&zip_path)
+        .with_context(|| format!("Failed to open zip file '{}'", zip_path.display()))?;
+    let mut archive = ZipArchive::new(file).context("Failed to read zip archive")?;
+
+    let prop = read_text(&mut archive, "module.prop")?
+        .with_context(|| format!("'{}' has no module.prop", zip_path.display()))?;
+    let prop = parse_module_prop(&prop);
+    let patch_path = match output {
+        Some(output) => PathBuf::from(shellexpand::tilde(output).to_string()),
+        None => {
+            let id = prop
+                .get("id")
+                .filter(|id| !id.is_empty() && !id.contains(['/', '\\']))
+                .with_context(|| "module.prop has no usable 'id', use --output")?;
+            PathBuf::from(id)
+        }
+    };
+    if patch_path.exists() && fs::read_dir(&patch_path)?.next().is_some() {
+        anyhow::bail!("'{}' already exists and is not empty", patch_path.display());
+    }
+    fs::create_dir_all(&patch_path)
+        .with_context(|| format!("Failed to create '{}'", patch_path.display()))?;
+
+    let mut copied = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(rel) = entry.enclosed_name() else {
+            utils::print_warning(&format!("Skipping unsafe entry '{}'", entry.name()));
+            continue;
+        };
+        if !rel.starts_with("system") {
+            continue;
+        }
+        let dest = patch_path.join(&rel);
+        if entry.is_dir() {
+            fs::create_dir_all(&dest)?;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = File::create(&dest)
+            .with_context(|| format!("Failed to create '{}'", dest.display()))?;
+        std::io::copy(&mut entry, &mut out)?;
+        copied += 1;
+    }
+    utils::print_info(&format!("📁 Copied {} file(s) from system/", copied));
+
+    let meta = ImportedMeta {
+        name: prop.get("name").cloned(),
+        version: prop.get("version").cloned(),
+        author: prop.get("author").cloned(),
+        description: prop.get("description").cloned(),
+    };
+    fs::write(patch_path.join("patch.yaml"), serde_yaml::to_string(&meta)?)
+        .context("Failed to write patch.yaml")?;
+    utils::print_info(&format!(
+        "📝 patch.yaml: {} {}",
+        meta.name.as_deref().unwrap_or("(no name)"),
+        meta.version.as_deref().unwrap_or("(no version)")
+    ));
+
+    if let Some(remove) = read_text(&mut archive, "remove")? {
+        let deletions = remove_entries(&remove);
+        let content: String = deletions.iter().map(|d| format!("{}\n", d)).collect();
+        fs::write(patch_path.join(".rommerfdel"), content)
+            .context("Failed to write .rommerfdel")?;
+        utils::print_info(&format!(
+            "🗑️  Converted {} path(s) from remove to .rommerfdel",
+            deletions.len()
+        ));
+    }
+
+    let skipped: Vec<&str> = [
+        "customize.sh",
+        "post-fs-data.sh",
+        "service.sh",
+        "system.prop",
+    ]
+    .into_iter()
+    .filter(|name| archive.index_for_name(name).is_some())
+    .collect();
+    if !skipped.is_empty() {
+        utils::print_warning(&format!(
+            "Not converted (no ROMMER equivalent): {}",
+            skipped.join(", ")
+        ));
+    }
+    utils::print_success(&format!("Created patch {}", patch_path.display()));
+    Ok(())
+}
+
+/// A text file at the root of the module, if present
+fn read_text(archive: &mut ZipArchive<File>, name: &str) -> anyhow::Result<Option<String>> {
+    let Ok(mut entry) = archive.by_name(name) else {
+        return Ok(None);
+    };
+    let mut content = String::new();
+    entry
+        .read_to_string(&mut content)
+        .with_context(|| format!("Failed to read '{}'", name))?;
+    Ok(Some(content))
+}
+
+/// `key=value` lines of a `module.prop`, ignoring blank lines and `#` comments
+fn parse_module_prop(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Paths of a module's `remove` file, relative to the ROM root
+fn remove_entries(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.trim_start_matches('/').to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_import_module() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let zip_path = dir.path().join("module.zip");
+        let mut zip = ZipWriter::new(File::create(&zip_path)?);
+        let options = FileOptions::<()>::default();
+        zip.start_file("module.prop", options)?;
+        zip.write_all(b"id=hosts\nname=Hosts\nversion=v2\n# comment\nauthor=me\n")?;
+        zip.start_file("system/etc/hosts", options)?;
+        zip.write_all(b"127.0.0.1 ads.example\n")?;
+        zip.start_file("remove", options)?;
+        zip.write_all(b"/system/app/Browser/Browser.apk\n\n")?;
+        zip.start_file("service.sh", options)?;
+        zip.finish()?;
+
+        let patch = dir.path().join("hosts");
+        import_module(zip_path.to_str().unwrap(), patch.to_str())?;
+        assert!(patch.join("system/etc/hosts").is_file());
+        assert!(!patch.join("service.sh").exists());
+        assert_eq!(
+            fs::read_to_string(patch.join(".rommerfdel"))?,
+            "system/app/Browser/Browser.apk\n"
+        );
+        let meta = crate::patchmeta::load_patch_meta(&patch).unwrap();
+        assert_eq!(meta.name.as_deref(), Some("Hosts"));
+        assert_eq!(meta.version.as_deref(), Some("v2"));
+        assert_eq!(meta.description, None);
+        Ok(())
+    }
+}
@@ -1,178 +1,509 @@
-mod args;
-mod checksum;
-mod config;
-mod download;
-mod finalize;
-mod patchmeta;
-mod rezip;
-mod sign;
-mod unzip;
-mod utils;
-use crate::args::Commands;
 use anyhow::{Context, Result};
 use args::Args;
 use clap::Parser;
 use config::Config;
+use rommer::args::{Commands, ConfigCommands, PatchCommands, SignCommands};
+use rommer::{
+    apk, archive, args, audit, benchmark, bundle, changelog, ci, config, debloat, deps, doctor,
+    download, env, github, globdel, inspect, integrity, lint, lock, magisk, manage, manifest,
+    matrix, metrics, notify, patchmeta, patchsig, patchtest, pin, pipeline, prop, search, secrets,
+    sign, simulate, split, utils, version,
+};
 use std::{
     fs,
     path::{Path, PathBuf},
     time::Instant,
 };
-use tempfile::tempdir;
-use tokio;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    utils::print_banner();
     let args = Args::parse();
+    utils::set_output_level(if args.silent {
+        utils::OutputLevel::Silent
+    } else if args.quiet {
+        utils::OutputLevel::Quiet
+    } else {
+        utils::OutputLevel::Normal
+    });
+    utils::set_ci(args.ci);
+    if utils::no_color() || args.ci {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    let log_level = args.log_level.unwrap_or(if args.verbose {
+        utils::LogLevel::Debug
+    } else {
+        utils::LogLevel::Info
+    });
+    let log_file = args
+        .log_file
+        .as_ref()
+        .map(|path| PathBuf::from(shellexpand::tilde(path).to_string()));
+    utils::init_logging(log_level, log_file.as_deref())?;
+    let ci = args.ci;
+    let result = run(args).await;
+    if let Err(e) = &result {
+        tracing::error!("{:#}", e);
+        if e.downcast_ref::<lock::LockHeld>().is_some() {
+            eprintln!("❌ {:#}", e);
+            std::process::exit(lock::LOCK_HELD_EXIT_CODE);
+        }
+        if ci {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(ci::exit_code(e));
+        }
+    }
+    result
+}
+
+async fn run(args: Args) -> Result<()> {
+    // The version and schema are printed alone
+    if !args.show_version
+        && !matches!(
+            args.command,
+            Some(Commands::Config {
+                command: ConfigCommands::GenerateSchema
+            })
+        )
+    {
+        utils::print_banner();
+    }
+    if args.show_version {
+        return version::print_version(false, args.offline).await;
+    }
     match &args.command {
-        Some(Commands::Init { name }) => {
-            return initsubcommand(name).await;
+        Some(Commands::Init {
+            name,
+            from_existing_rom,
+            from_github,
+        }) => {
+            initsubcommand(
+                name,
+                from_existing_rom.as_deref(),
+                from_github.as_deref(),
+                args.offline,
+            )
+            .await
         }
-        None => {
-            nosubcommand(args).await?;
+        Some(Commands::Lint { rom_zip }) => {
+            let config = config::load_config(&args.config, args.profile.as_deref())?;
+            lint::lint_patches(&config, rom_zip.as_deref())
+        }
+        Some(Commands::Env { export, format }) => {
+            let config = config::load_config(&args.config, args.profile.as_deref())?;
+            env::print_env(&config, *export, format.as_deref());
             Ok(())
         }
+        Some(Commands::Audit) => {
+            let config = config::load_config(&args.config, args.profile.as_deref())?;
+            audit::audit_patches(&config)
+        }
+        Some(Commands::Version { check }) => version::print_version(*check, args.offline).await,
+        Some(Commands::Simulate) => simulatesubcommand(&args).await,
+        Some(Commands::Metrics { compare }) => metrics::print_metrics(compare.as_deref()),
+        Some(Commands::Doctor) => {
+            let config = if Path::new(&args.config).exists() {
+                Some(config::load_config(&args.config, args.profile.as_deref())?)
+            } else {
+                None
+            };
+            doctor::run_doctor(config.as_ref())
+        }
+        Some(Commands::Config { command }) => configsubcommand(&args, command),
+        Some(Commands::Patch { command }) => patchsubcommand(&args, command).await,
+        Some(Commands::Sign {
+            command: SignCommands::Verify { zip, method },
+        }) => {
+            let method = match method {
+                Some(method) => method.clone(),
+                None if Path::new(&args.config).exists() => {
+                    let config = config::load_config(&args.config, args.profile.as_deref())?;
+                    match config.signing.as_ref().map(|s| s.method.as_str()) {
+                        Some("jarsigner" | "pkcs11") => "jarsigner".to_string(),
+                        _ => "apksigner".to_string(),
+                    }
+                }
+                None => "apksigner".to_string(),
+            };
+            sign::verify_signature(Path::new(&shellexpand::tilde(zip).to_string()), &method)
+        }
+        None => nosubcommand(args).await,
     }
 }
+/// Runs a build and saves its step timings to `.rommer-metrics.json`, even
+/// when it fails, so slow steps can be found with `rommer metrics`
 async fn nosubcommand(args: Args) -> Result<()> {
-    let config_content = fs::read_to_string(&args.config)
-        .with_context(|| format!("Failed to read config file '{}'", args.config))?;
-    let mut config: Config =
-        serde_yaml::from_str(&config_content).with_context(|| "Failed to parse ROMMER.yaml")?;
-    if args.no_cleanup {
-        config.cleanup = false;
+    let start = Instant::now();
+    let dry_run = args.dry_run;
+    let result = build(args).await;
+    if !dry_run && let Err(e) = metrics::save(start.elapsed()) {
+        utils::print_warning(&format!("Failed to save build metrics: {:#}", e));
     }
-    utils::print_success(&format!(
-        "📱 Device: {} | 🔧 Base ROM: {} | 📦 Version: {} | Android Version: {}",
-        config.device,
-        if config.rom.starts_with("http") {
-            "custom"
-        } else {
-            &config.rom
-        },
-        config.version,
-        config.android_version
-    ));
-    let _ = utils::run_hook(&config.hooks, "pre-run");
+    result
+}
+
+/// Runs the build pipeline, logging its events and printing the final ROM
+async fn build(args: Args) -> Result<()> {
+    let config =
+        config::load_config(&args.config, args.profile.as_deref()).context(ci::Failure::Config)?;
+    let dry_run = args.dry_run;
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(16);
+    let events = tokio::spawn(async move {
+        while let Some(event) = progress_rx.recv().await {
+            tracing::info!(target: "rommer::pipeline", "{}", event);
+        }
+    });
+    let notify_config = (!dry_run && !args.offline).then(|| config.clone());
+    let options = pipeline::BuildOptions::from(&args);
+    let result = pipeline::BuildPipeline::new(config, options, Some(progress_tx))
+        .run()
+        .await;
+    let _ = events.await;
+    if let Some(config) = &notify_config {
+        let event = match &result {
+            Ok(Some(rom)) => notify::BuildNotification {
+                status: notify::Status::Success,
+                message: format!("Build finished: {}", rom.display()),
+            },
+            Ok(None) => notify::BuildNotification {
+                status: notify::Status::Success,
+                message: "Build finished".to_string(),
+            },
+            Err(e) => notify::BuildNotification {
+                status: notify::Status::Failure,
+                message: format!("Build failed: {:#}", e),
+            },
+        };
+        notify::notify_all(config, &event).await;
+    }
+    if let Some(final_rom_path) = result? {
+        print_final_rom(&final_rom_path, dry_run)?;
+    }
+    Ok(())
+}
+
+/// Reports the final ROM. With `--quiet`/`--silent` it is printed as plain
+/// `<sha256>  <path>` so scripts can capture it, with the checksum of the
+/// whole ROM when it was split.
+fn print_final_rom(path: &Path, dry_run: bool) -> Result<()> {
+    if utils::shows_info() {
+        utils::print_success(&format!("🎉 Final ROM: {}", path.display()));
+    } else if dry_run {
+        println!("{}", path.display());
+    } else {
+        println!(
+            "{}  {}",
+            split::parts_checksum(&split::split_parts(path))?,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+async fn simulatesubcommand(args: &Args) -> Result<()> {
+    let config = config::load_config(&args.config, args.profile.as_deref())?;
     let romzip_path = if args.romzip == ".download" {
-        let _ = utils::run_hook(&config.hooks, "pre-download");
-        download::download_rom(&config, args.dry_run).await?
+        download::ensure_online(args.offline)?;
+        download::download_rom(&config, false).await?
     } else {
-        let expanded = shellexpand::tilde(&args.romzip);
-        PathBuf::from(expanded.to_string())
+        PathBuf::from(shellexpand::tilde(&args.romzip).to_string())
     };
+    utils::print_section("🔧 SELECTING PATCHES");
+    let options = pipeline::BuildOptions::from(args);
+    let patches = pipeline::apply_patches(&options, &config, None).await?;
+    simulate::print_simulation(&romzip_path, &patches, &config)
+}
 
-    let tmp_dir = tempdir().context("Failed to create temp dir")?;
-    utils::print_info(&format!(
-        "🗂️  Working directory: {}",
-        tmp_dir.path().display()
-    ));
-    let _ = utils::run_hook(&config.hooks, "pre-unzip");
-    unzip::unzip_rom(&romzip_path, tmp_dir.path(), args.dry_run)?;
-    let _ = utils::run_hook(&config.hooks, "post-unzip");
-    utils::print_section("🔧 APPLYING PATCHES");
-    let _ = utils::run_hook(&config.hooks, "pre-patch");
-    for (i, patch_folder) in config.patches.iter().enumerate() {
-        let patch_path = Path::new(patch_folder);
-        if !patch_path.exists() {
-            utils::print_warning(&format!("Patch folder '{}' does not exist!", patch_folder));
-            continue;
+async fn patchsubcommand(args: &Args, command: &PatchCommands) -> Result<()> {
+    // Commands that build a new patch from a file don't need a ROMMER.yaml
+    let config = || config::load_config(&args.config, args.profile.as_deref());
+    match command {
+        PatchCommands::CreateConflictMatrix => matrix::print_conflict_matrix(&config()?),
+        PatchCommands::Rename { from, to } => {
+            let config = config::load_base_config(&args.config)?;
+            manage::rename_patch(&args.config, &config, from, to)
+        }
+        PatchCommands::Remove {
+            patch,
+            delete_directory,
+            dry_run,
+        } => manage::remove_patch(
+            &args.config,
+            &config::load_base_config(&args.config)?,
+            patch,
+            *delete_directory,
+            args.dry_run || *dry_run,
+        ),
+        PatchCommands::Enable { patch } => {
+            let config = config::load_base_config(&args.config)?;
+            manage::set_patch_enabled(&args.config, &config, patch, true)
+        }
+        PatchCommands::Disable { patch } => {
+            let config = config::load_base_config(&args.config)?;
+            manage::set_patch_enabled(&args.config, &config, patch, false)
+        }
+        PatchCommands::ConvertDeletions {
+            patch,
+            rom_zip,
+            dry_run,
+        } => globdel::convert_deletions(&config()?, patch, rom_zip, args.dry_run || *dry_run),
+        PatchCommands::PruneStaleDeletions {
+            patch,
+            rom_zip,
+            apply,
+            dry_run,
+        } => globdel::prune_stale_deletions(
+            &config()?,
+            patch,
+            rom_zip,
+            *apply,
+            args.dry_run || *dry_run,
+        ),
+        PatchCommands::FindConflicts { rom_zip } => lint::find_conflicts(&config()?, rom_zip),
+        PatchCommands::Test { patch, rom_zip } => patchtest::test_patch(&config()?, patch, rom_zip),
+        PatchCommands::TestDeletion { patch, rom_zip } => {
+            patchtest::test_deletions(&config()?, patch, rom_zip)
+        }
+        PatchCommands::Benchmark {
+            patch,
+            rom_zip,
+            runs,
+        } => benchmark::benchmark_patch(
+            &config()?,
+            patch,
+            rom_zip,
+            runs.unwrap_or(benchmark::DEFAULT_RUNS),
+        ),
+        PatchCommands::Apply { patch, to, dry_run } => {
+            applysubcommand(&config()?, patch, to, args.dry_run || *dry_run)
+        }
+        PatchCommands::ExportBundle {
+            patches,
+            output,
+            include_config,
+        } => bundle::export_bundle(&config()?, patches, output, *include_config),
+        PatchCommands::Archive {
+            patches,
+            all,
+            output,
+        } => archive::archive_patches(&args.config, &config()?, patches, *all, output.as_deref()),
+        PatchCommands::Search { query, field } => {
+            search::search_patches(&config()?, query, field.as_deref())
+        }
+        PatchCommands::Integrity { patch } => {
+            integrity::check_integrity(&config()?, patch.as_deref())
+        }
+        PatchCommands::FromApk {
+            apk,
+            target,
+            privileged,
+            patch_name,
+        } => apk::patch_from_apk(apk, target.as_deref(), *privileged, patch_name.as_deref()),
+        PatchCommands::FromProp {
+            prop_file,
+            patch_name,
+        } => prop::patch_from_prop(prop_file, patch_name.as_deref()),
+        PatchCommands::Sign { patch, key } => {
+            patchsig::sign_patch(&config()?, patch, key.as_deref())
         }
+        PatchCommands::Manifest { patch, interactive } => {
+            manifest::generate_manifest(&config()?, patch, *interactive)
+        }
+        PatchCommands::Mv {
+            source,
+            dest,
+            patch,
+        } => manage::move_file(&config()?, patch, source, dest),
+        PatchCommands::Pin { patch } => pin::pin_patch(&config()?, patch),
+        PatchCommands::HashTree { patch } => integrity::hash_tree(&config()?, patch),
+        PatchCommands::UpdateChecksum { patch } => integrity::update_checksum(&config()?, patch),
+        PatchCommands::Changelog { patch, since } => {
+            changelog::print_changelog(&config()?, patch.as_deref(), since.as_deref())
+        }
+        PatchCommands::NewVersion {
+            patch,
+            version,
+            changes,
+            dry_run,
+        } => changelog::new_version(
+            &config()?,
+            patch,
+            version,
+            changes,
+            args.dry_run || *dry_run,
+        ),
+        PatchCommands::ExtractDeletions {
+            rom_zip,
+            apps,
+            output,
+        } => debloat::extract_deletions(rom_zip, apps, output.as_deref()),
+        PatchCommands::ImportFromMagisk { module_zip, output } => {
+            magisk::import_module(module_zip, output.as_deref())
+        }
+        PatchCommands::DepsGraph { format } => {
+            deps::print_graph(&config()?, format.as_deref().unwrap_or("dot"))
+        }
+        PatchCommands::Reorder {
+            patch,
+            before,
+            after,
+            position,
+        } => {
+            let target = match (before, after, position) {
+                (Some(before), _, _) => manage::ReorderTarget::Before(before),
+                (_, Some(after), _) => manage::ReorderTarget::After(after),
+                (_, _, Some(position)) => manage::ReorderTarget::Position(*position),
+                _ => unreachable!("clap requires one of --before, --after or --position"),
+            };
+            // Move within the patches list as written, not as a profile changes it
+            let config = config::load_base_config(&args.config)?;
+            manage::reorder_patch(&args.config, &config, patch, target)
+        }
+        PatchCommands::Sort { dry_run } => {
+            let config = config::load_base_config(&args.config)?;
+            sortsubcommand(&args.config, &config, args.dry_run || *dry_run)
+        }
+    }
+}
 
-        let patch_path = Path::new(patch_folder);
-        let patch_meta = patchmeta::load_patch_meta(patch_path);
-        if let Some(ref tags_filter) = args.tags {
-            if let Some(meta) = &patch_meta {
-                let tags = meta.tags.clone().unwrap_or_default();
-                let matches = tags.iter().any(|t| tags_filter.contains(t));
-                if !matches {
-                    utils::print_info(&format!(
-                        "🚫 Skipping patch '{}' (tag mismatch)",
-                        patch_folder
-                    ));
-                    continue;
-                }
-            } else {
-                utils::print_info(&format!(
-                    "🚫 Skipping patch '{}' (no patch.yaml, no tags)",
-                    patch_folder
-                ));
-                continue;
-            }
+fn configsubcommand(args: &Args, command: &ConfigCommands) -> Result<()> {
+    match command {
+        ConfigCommands::GenerateSchema => {
+            println!("{}", config::json_schema()?);
+            Ok(())
         }
-        if let Some(meta) = &patch_meta {
-            if let Some(req) = &meta.requires_android {
-                if !utils::android_version_matches(req, config.android_version) {
-                    utils::print_info(&format!(
-                        "🚫 Skipping patch '{}' (requires Android {}, current is {})",
-                        patch_folder, req, config.android_version
-                    ));
-                    continue;
+        ConfigCommands::Merge {
+            base,
+            override_,
+            output,
+            validate,
+        } => {
+            let merged = config::merge_config_files(base, override_)?;
+            if *validate {
+                serde_yaml::from_str::<Config>(&merged)
+                    .context("Merged config is not a valid ROMMER.yaml")?;
+                utils::print_success("Merged config is valid");
+            }
+            match output {
+                Some(output) => {
+                    fs::write(output, &merged)
+                        .with_context(|| format!("Failed to write '{}'", output))?;
+                    utils::print_success(&format!("Merged config written to {}", output));
                 }
+                None => print!("{}", merged),
             }
+            Ok(())
         }
-        if let Some(meta) = &patch_meta {
-            utils::print_info(&format!(
-                "[{}/{}] Applying patch: {}{} by {}",
-                i + 1,
-                config.patches.len(),
-                meta.name
-                    .clone()
-                    .unwrap_or_else(|| patch_folder.to_string()),
-                meta.version
-                    .as_ref()
-                    .map(|v| format!(" v{}", v))
-                    .unwrap_or_default(),
-                meta.author.clone().unwrap_or_else(|| "Unknown".to_string()),
-            ));
-            if let Some(desc) = &meta.description {
-                utils::print_info(&format!("📝 {}", desc));
+        ConfigCommands::EncryptSecrets { key_env } => {
+            secrets::encrypt_config_secrets(&args.config, key_env)
+        }
+        ConfigCommands::Diff { a, b } => {
+            let changes = config::diff_config_files(a, b)?;
+            if changes.is_empty() {
+                utils::print_success("No semantic differences");
             }
-        } else {
-            utils::print_info(&format!(
-                "[{}/{}] Applying patch '{}'",
-                i + 1,
-                config.patches.len(),
-                patch_folder
-            ));
+            for change in changes {
+                println!("{}", change);
+            }
+            Ok(())
         }
-        let start = Instant::now();
-        utils::copy_dir_all(patch_path, tmp_dir.path(), args.dry_run)
-            .with_context(|| format!("Failed to copy patch folder '{}'", patch_folder))?;
-        utils::handle_deletions(
-            patch_path,
-            tmp_dir.path(),
-            ".rommerdel",
-            "directory",
-            args.dry_run,
-        )?;
-        utils::handle_file_deletions(
-            patch_path,
-            tmp_dir.path(),
-            ".rommerfdel",
-            "file",
-            args.dry_run,
-        )?;
-        let duration = start.elapsed();
-        utils::print_info(&format!("⏱️ Patch applied in {:.2?}", duration).to_string());
+        ConfigCommands::AddHook { event, script } => manage::add_hook(&args.config, event, script),
+        ConfigCommands::AddPatch {
+            patch,
+            position,
+            after,
+        } => {
+            // Edit the patches list as written, not as a profile changes it
+            let config = config::load_base_config(&args.config)?;
+            manage::add_patch(
+                &args.config,
+                &config,
+                patch,
+                position.as_deref() == Some("first"),
+                after.as_deref(),
+            )
+        }
+    }
+}
+
+fn applysubcommand(config: &Config, patch: &str, to: &str, dry_run: bool) -> Result<()> {
+    utils::print_section("🔧 APPLYING PATCH");
+    let patch_path = bundle::resolve_patch(config, patch)?;
+    let target_dir = PathBuf::from(shellexpand::tilde(to).to_string());
+    if !patch_path.is_dir() {
+        anyhow::bail!("Patch folder '{}' does not exist", patch_path.display());
+    }
+    if !target_dir.is_dir() {
+        anyhow::bail!("Target directory '{}' does not exist", target_dir.display());
     }
-    let kept_path = tmp_dir.keep();
-    utils::print_section("✅ PATCHING COMPLETE");
-    utils::print_success(&format!("📂 Patched ROM: {}", kept_path.display()));
-    let _ = utils::run_hook(&config.hooks, "post-patch");
-    let final_rom_path = finalize::finalize_rom(&kept_path, &config, args.dry_run).await?;
-    utils::print_success(&format!("🎉 Final ROM: {}", final_rom_path.display()));
+    let patch_meta = patchmeta::load_patch_meta(&patch_path);
+    if let Some(meta) = &patch_meta {
+        utils::print_info(&format!(
+            "Applying patch: {}{} by {}",
+            meta.name.clone().unwrap_or_else(|| patch.to_string()),
+            meta.version
+                .as_ref()
+                .map(|v| format!(" v{}", v))
+                .unwrap_or_default(),
+            meta.author.clone().unwrap_or_else(|| "Unknown".to_string()),
+        ));
+    }
+    let stats = utils::extension_stats(&patch_path);
+    if !stats.is_empty() {
+        utils::print_info(&format!("📊 {}", utils::format_extension_stats(&stats)));
+    }
+    let start = Instant::now();
+    utils::apply_patch(
+        &patch_path,
+        &target_dir,
+        patch_meta
+            .as_ref()
+            .and_then(|m| m.file_conflict_strategy.as_deref()),
+        config,
+        &utils::CopiedFiles::default(),
+        dry_run,
+    )?;
+    utils::print_success(&format!(
+        "⏱️ Patch applied to {} in {:.2?}",
+        target_dir.display(),
+        start.elapsed()
+    ));
     Ok(())
 }
 
-async fn initsubcommand(name: &Option<String>) -> Result<()> {
+fn sortsubcommand(config_path: &str, config: &Config, dry_run: bool) -> Result<()> {
+    utils::print_section("🔀 SORTING PATCHES");
+    let sorted = deps::resolve_order(config)?;
+    if sorted == config.patches {
+        utils::print_success("Patches are already in dependency order");
+        return Ok(());
+    }
+    for (i, (old, new)) in config.patches.iter().zip(&sorted).enumerate() {
+        let marker = if old == new { " " } else { "~" };
+        println!("{} {:>3}. {}  →  {}", marker, i + 1, old, new);
+    }
+    if dry_run {
+        utils::print_info("🔍 DRY RUN: Would rewrite the patches list");
+        return Ok(());
+    }
+    config::write_patches(config_path, &sorted)?;
+    utils::print_success(&format!("Updated patch order in {}", config_path));
+    Ok(())
+}
+
+async fn initsubcommand(
+    name: &Option<String>,
+    from_existing_rom: Option<&str>,
+    from_github: Option<&str>,
+    offline: bool,
+) -> Result<()> {
     let project_name = name.as_ref().unwrap();
     utils::print_section("🚀 INITIALIZING NEW ROMMER PROJECT");
     let project_path = Path::new(project_name);
     fs::create_dir_all(project_path).context("Failed to create project directory")?;
     let config_path = project_path.join("ROMMER.yaml");
-    let example_config = r#"device: your_device_codename
+    let example_config = r#"# yaml-language-server: $schema=https://raw.githubusercontent.com/TheROMMER/core/main/rommer.schema.json
+device: your_device_codename
 rom: lineageos
 max_retries: 3
 version: 20.0
@@ -183,39 +514,81 @@ patches:
   - example_patch/
 
 output:
-  filename: custom-rom.zip
+  filename: "{device}-{version}.zip"
 
 cleanup: true
 "#;
-    fs::write(&config_path, example_config).context("Failed to create ROMMER.yaml config file")?;
-    let patches_dir = project_path;
-    let example_patch_dir = patches_dir.join("example_patch");
-    fs::create_dir_all(&example_patch_dir).context("Failed to create example patch directory")?;
-    let example_patch_system_dir = example_patch_dir.join("system").join("etc");
-    fs::create_dir_all(&example_patch_system_dir)
-        .context("Failed to create example patch system directory")?;
-    let example_file_path = example_patch_system_dir.join("example_custom_file.txt");
-    fs::write(
-        &example_file_path,
-        "This is an example custom file that will be added to the ROM\n",
-    )
-    .context("Failed to create example custom file")?;
-    let rommerdel_path = example_patch_dir.join(".rommerdel");
-    fs::write(
-        &rommerdel_path,
-        "system/app/ExampleBloatwareApp\nsystem/priv-app/UnwantedSystemApp\n",
-    )
-    .context("Failed to create .rommerdel file")?;
-    let rommerfdel_path = example_patch_dir.join(".rommerfdel");
-    fs::write(
-        &rommerfdel_path,
-        "system/media/bootanimation.zip\nsystem/etc/example_unwanted_file.conf\n",
-    )
-    .context("Failed to create .rommerfdel file")?;
+    if let Some(repo) = from_github {
+        if offline {
+            anyhow::bail!("--offline mode: can't download '{}' from GitHub", repo);
+        }
+        if github::download_repo(repo, project_path).await? {
+            utils::print_info("📝 Using the repository's ROMMER.yaml");
+        } else {
+            let patches = github::find_patches(project_path)?;
+            let list: String = patches.iter().map(|p| format!("  - {}\n", p)).collect();
+            fs::write(
+                &config_path,
+                example_config.replace("  - example_patch/\n", &list),
+            )
+            .context("Failed to create ROMMER.yaml config file")?;
+            utils::print_info(&format!(
+                "📝 Generated ROMMER.yaml with {} patches from patches/",
+                patches.len()
+            ));
+        }
+    } else {
+        let config_content = match from_existing_rom {
+            Some(rom_zip) => {
+                let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+                inspect::inspect_rom(&rom_path)?.to_config()
+            }
+            None => example_config.to_string(),
+        };
+        fs::write(&config_path, config_content)
+            .context("Failed to create ROMMER.yaml config file")?;
+        let patches_dir = project_path;
+        let example_patch_dir = patches_dir.join("example_patch");
+        fs::create_dir_all(&example_patch_dir)
+            .context("Failed to create example patch directory")?;
+        let example_patch_system_dir = example_patch_dir.join("system").join("etc");
+        fs::create_dir_all(&example_patch_system_dir)
+            .context("Failed to create example patch system directory")?;
+        let example_file_path = example_patch_system_dir.join("example_custom_file.txt");
+        fs::write(
+            &example_file_path,
+            "This is an example custom file that will be added to the ROM\n",
+        )
+        .context("Failed to create example custom file")?;
+        let rommerdel_path = example_patch_dir.join(".rommerdel");
+        fs::write(
+            &rommerdel_path,
+            "system/app/ExampleBloatwareApp\nsystem/priv-app/UnwantedSystemApp\n",
+        )
+        .context("Failed to create .rommerdel file")?;
+        let rommerfdel_path = example_patch_dir.join(".rommerfdel");
+        fs::write(
+            &rommerfdel_path,
+            "system/media/bootanimation.zip\nsystem/etc/example_unwanted_file.conf\n",
+        )
+        .context("Failed to create .rommerfdel file")?;
+    }
     let gitignore_path = project_path.join(".gitignore");
-    fs::write(
-        &gitignore_path,
-        r#"# ROMMER Output Files
+    // A project downloaded with --from-github keeps its own .gitignore
+    if !gitignore_path.exists() {
+        fs::write(&gitignore_path, GITIGNORE).context("Failed to create .gitignore file")?;
+    }
+    utils::print_success(&format!(
+        "✅ Project '{}' initialized successfully!",
+        project_name
+    ));
+    utils::print_info("📝 Edit ROMMER.yaml to configure your device and ROM settings");
+    utils::print_info("📂 Add your patches to the created directory");
+    utils::print_info("🚀 Run 'rommer' inside your created directory to build your custom ROM");
+    Ok(())
+}
+
+const GITIGNORE: &str = r#"# ROMMER Output Files
 # Generated ROM ZIP files and build artifacts
 *.zip
 custom-rom*.zip
@@ -238,6 +611,7 @@ base-*.zip
 *.sf
 MANIFEST.MF
 META-INF/
+*.rezip-hash
 
 # Signing Keys and Certificates
 # Never commit private keys or keystores
@@ -298,18 +672,13 @@ cache/
 *.md5
 checksums.txt
 
+# Build Lock
+.rommer.lock
+
+# Download Cache
+.rommer-cache/
+
 # Documentation Generated Files
 # Auto-generated documentation
 docs/build/
-site/"#,
-    )
-    .context("Failed to create .rommerfdel file")?;
-    utils::print_success(&format!(
-        "✅ Project '{}' initialized successfully!",
-        project_name
-    ));
-    utils::print_info("📝 Edit ROMMER.yaml to configure your device and ROM settings");
-    utils::print_info("📂 Add your patches to the created directory");
-    utils::print_info("🚀 Run 'rommer' inside your created directory to build your custom ROM");
-    Ok(())
-}
+site/"#;
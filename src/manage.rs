@@ -0,0 +1,446 @@
+use crate::config::{self, Config};
+use crate::utils;
+use anyhow::Context;
+use serde_yaml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// `patch.yaml` keys that reference other patches
+const REFERENCE_KEYS: [&str; 2] = ["conflicts_with", "depends_on"];
+
+pub fn rename_patch(
+    config_path: &str,
+    config: &Config,
+    from: &str,
+    to: &str,
+) -> anyhow::Result<()> {
+    utils::print_section("✏️  RENAMING PATCH");
+    let index = config
+        .patches
+        .iter()
+        .position(|p| utils::patch_matches(p, from));
+    let old_dir = index.map_or(from, |i| config.patches[i].as_str());
+    let (old_dir, to) = (old_dir.trim_end_matches('/'), to.trim_end_matches('/'));
+    // Configured folders are relative to the config file, others to the cwd
+    let (old_path, new_path) = match index {
+        Some(_) => (
+            config.resolve_patch_path(old_dir),
+            config.resolve_patch_path(to),
+        ),
+        None => (PathBuf::from(old_dir), PathBuf::from(to)),
+    };
+    let (old_path, new_path) = (old_path.as_path(), new_path.as_path());
+    if !old_path.is_dir() {
+        anyhow::bail!("Patch folder '{}' does not exist", old_path.display());
+    }
+    if new_path.exists() {
+        anyhow::bail!("'{}' already exists", new_path.display());
+    }
+    fs::rename(old_path, new_path).with_context(|| {
+        format!(
+            "Failed to rename '{}' to '{}'",
+            old_path.display(),
+            new_path.display()
+        )
+    })?;
+    utils::print_success(&format!(
+        "📂 Renamed {} -> {}",
+        old_path.display(),
+        new_path.display()
+    ));
+
+    if let Some(i) = index {
+        let mut patches = config.patches.clone();
+        let trailing = if patches[i].ends_with('/') { "/" } else { "" };
+        patches[i] = format!("{}{}", to, trailing);
+        config::write_patches(config_path, &patches)?;
+        utils::print_success(&format!("📝 Updated {}", config_path));
+    }
+
+    let old_name = folder_name(old_path);
+    let new_name = folder_name(new_path);
+    let (old_full, new_full) = (old_dir.to_string(), to.to_string());
+    for file in update_references(config_path, false, |reference| {
+        let reference = reference.trim_end_matches('/');
+        if reference == old_full {
+            Some(Some(new_full.clone()))
+        } else if reference == old_name {
+            Some(Some(new_name.clone()))
+        } else {
+            None
+        }
+    })? {
+        utils::print_success(&format!("📝 Updated {}", file.display()));
+    }
+    Ok(())
+}
+
+pub fn remove_patch(
+    config_path: &str,
+    config: &Config,
+    patch: &str,
+    delete_directory: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    utils::print_section("🗑️  REMOVING PATCH");
+    let prefix = if dry_run {
+        "🔍 DRY RUN: Would update"
+    } else {
+        "📝 Updated"
+    };
+    let index = config
+        .patches
+        .iter()
+        .position(|p| utils::patch_matches(p, patch));
+    let folder = index.map_or(patch, |i| config.patches[i].as_str());
+    let path = match index {
+        Some(_) => config.resolve_patch_path(folder.trim_end_matches('/')),
+        None => PathBuf::from(folder.trim_end_matches('/')),
+    };
+    let path = path.as_path();
+    if index.is_none() && !path.is_dir() {
+        anyhow::bail!("'{}' is neither a configured patch nor a directory", patch);
+    }
+
+    if let Some(i) = index {
+        let mut patches = config.patches.clone();
+        patches.remove(i);
+        if !dry_run {
+            config::write_patches(config_path, &patches)?;
+        }
+        utils::print_success(&format!(
+            "{} {} (removed '{}')",
+            prefix, config_path, folder
+        ));
+    } else {
+        utils::print_info(&format!("'{}' is not listed in {}", patch, config_path));
+    }
+
+    let name = folder_name(path);
+    let full = folder.trim_end_matches('/').to_string();
+    let meta_name = crate::patchmeta::load_patch_meta(path).and_then(|m| m.name);
+    for file in update_references(config_path, dry_run, |reference| {
+        let reference = reference.trim_end_matches('/');
+        let matches =
+            reference == full || reference == name || meta_name.as_deref() == Some(reference);
+        matches.then_some(None)
+    })? {
+        utils::print_success(&format!("{} {}", prefix, file.display()));
+    }
+
+    if delete_directory && path.is_dir() {
+        if dry_run {
+            utils::print_info(&format!("🔍 DRY RUN: Would delete {}", path.display()));
+        } else {
+            fs::remove_dir_all(path)
+                .with_context(|| format!("Failed to delete '{}'", path.display()))?;
+            utils::print_success(&format!("🗑️  Deleted {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Adds a patch to the `patches` list in ROMMER.yaml, at the end, at the
+/// start with `first`, or after the patch named by `after`
+pub fn add_patch(
+    config_path: &str,
+    config: &Config,
+    patch: &str,
+    first: bool,
+    after: Option<&str>,
+) -> anyhow::Result<()> {
+    if let Some(existing) = config.patches.iter().find(|p| {
+        p.trim_end_matches('/') == patch.trim_end_matches('/') || utils::patch_matches(p, patch)
+    }) {
+        utils::print_info(&format!(
+            "'{}' is already listed in {} as '{}'",
+            patch, config_path, existing
+        ));
+        return Ok(());
+    }
+    if !config.resolve_patch_path(patch).is_dir() {
+        utils::print_warning(&format!("Patch folder '{}' does not exist", patch));
+    }
+    let index = match after {
+        Some(after) => {
+            config
+                .patches
+                .iter()
+                .position(|p| utils::patch_matches(p, after))
+                .with_context(|| format!("'{}' is not a configured patch", after))?
+                + 1
+        }
+        None if first => 0,
+        None => config.patches.len(),
+    };
+    let mut patches = config.patches.clone();
+    patches.insert(index, patch.to_string());
+    config::write_patches(config_path, &patches)?;
+    utils::print_success(&format!(
+        "➕ Added patch '{}' to {} (position {})",
+        patch,
+        config_path,
+        index + 1
+    ));
+    Ok(())
+}
+
+/// Where `patch reorder` moves a patch
+pub enum ReorderTarget<'a> {
+    Before(&'a str),
+    After(&'a str),
+    /// 0-based index in the new list
+    Position(usize),
+}
+
+/// Moves a patch within the `patches` list in ROMMER.yaml, keeping comments
+/// and formatting, and prints the old and new order
+pub fn reorder_patch(
+    config_path: &str,
+    config: &Config,
+    patch: &str,
+    target: ReorderTarget,
+) -> anyhow::Result<()> {
+    let find = |name: &str| {
+        config
+            .patches
+            .iter()
+            .position(|p| utils::patch_matches(p, name))
+            .with_context(|| format!("'{}' is not a configured patch", name))
+    };
+    let from = find(patch)?;
+    let mut patches = config.patches.clone();
+    let moved = patches.remove(from);
+    let index = match target {
+        ReorderTarget::Before(other) | ReorderTarget::After(other) => {
+            let other_index = find(other)?;
+            if other_index == from {
+                anyhow::bail!("Can't move '{}' relative to itself", patch);
+            }
+            let other_index = if other_index > from {
+                other_index - 1
+            } else {
+                other_index
+            };
+            match target {
+                ReorderTarget::After(_) => other_index + 1,
+                _ => other_index,
+            }
+        }
+        ReorderTarget::Position(position) => {
+            if position > patches.len() {
+                anyhow::bail!(
+                    "Position {} is out of range (0-{})",
+                    position,
+                    patches.len()
+                );
+            }
+            position
+        }
+    };
+    patches.insert(index, moved);
+    if patches == config.patches {
+        utils::print_info(&format!("'{}' is already at position {}", patch, index));
+        return Ok(());
+    }
+    for (i, (old, new)) in config.patches.iter().zip(&patches).enumerate() {
+        let marker = if old == new { " " } else { "~" };
+        println!("{} {:>3}. {}  →  {}", marker, i, old, new);
+    }
+    config::write_patches(config_path, &patches)?;
+    utils::print_success(&format!(
+        "🔀 Moved patch '{}' to position {} in {}",
+        patch, index, config_path
+    ));
+    Ok(())
+}
+
+/// Adds or removes a patch from `disabled_patches` in ROMMER.yaml
+pub fn set_patch_enabled(
+    config_path: &str,
+    config: &Config,
+    patch: &str,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    let Some(folder) = config
+        .patches
+        .iter()
+        .find(|p| utils::patch_matches(p, patch))
+    else {
+        anyhow::bail!("'{}' is not a configured patch", patch);
+    };
+    let name = folder_name(Path::new(folder.trim_end_matches('/')));
+    let is_disabled = config
+        .disabled_patches
+        .iter()
+        .any(|d| utils::patch_matches(folder, d));
+    if enabled != is_disabled {
+        let state = if enabled { "enabled" } else { "disabled" };
+        utils::print_info(&format!("Patch '{}' is already {}", name, state));
+        return Ok(());
+    }
+
+    let mut disabled: Vec<String> = config
+        .disabled_patches
+        .iter()
+        .filter(|d| !utils::patch_matches(folder, d))
+        .cloned()
+        .collect();
+    if !enabled {
+        disabled.push(name.clone());
+    }
+    config::write_list(config_path, "disabled_patches", &disabled, true)?;
+    if enabled {
+        utils::print_success(&format!("✅ Enabled patch '{}'", name));
+    } else {
+        utils::print_success(&format!("🚫 Disabled patch '{}'", name));
+    }
+    Ok(())
+}
+
+/// Sets the script run for a hook in ROMMER.yaml, replacing the hook's
+/// current script if it has one
+pub fn add_hook(config_path: &str, event: &str, script: &str) -> anyhow::Result<()> {
+    if !config::HOOK_NAMES.contains(&event) {
+        anyhow::bail!(
+            "Unknown hook '{}', expected one of: {}",
+            event,
+            config::HOOK_NAMES.join(", ")
+        );
+    }
+    let script_path = Path::new(script);
+    if !script_path.is_file() {
+        utils::print_warning(&format!(
+            "Script '{}' does not exist (relative to the current directory)",
+            script
+        ));
+    }
+    match config::write_hook(config_path, event, script)? {
+        Some(previous) => utils::print_success(&format!(
+            "🪝 Replaced hook '{}' in {}: {} -> {}",
+            event, config_path, previous, script
+        )),
+        None => utils::print_success(&format!(
+            "🪝 Added hook '{}' to {}: {}",
+            event, config_path, script
+        )),
+    }
+    Ok(())
+}
+
+/// Deploys a patch file under a new path in the ROM by adding a
+/// `<source> -> <dest>` line to the patch's `.rommercp`. The file stays in
+/// the patch as the rename source, so it is no longer copied to its old path.
+pub fn move_file(config: &Config, patch: &str, source: &str, dest: &str) -> anyhow::Result<()> {
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let source = source.trim().trim_start_matches("./").replace('\\', "/");
+    let dest = dest.trim().trim_start_matches("./").replace('\\', "/");
+    if !patch_path.join(&source).is_file() {
+        anyhow::bail!(
+            "'{}' is not a file in patch '{}'",
+            source,
+            patch_path.display()
+        );
+    }
+    if utils::PATCH_CONTROL_FILES.contains(&source.as_str()) {
+        anyhow::bail!("'{}' configures the patch and can't be moved", source);
+    }
+    if source == dest {
+        anyhow::bail!("Source and destination are both '{}'", source);
+    }
+    let renames = utils::read_copy_renames(&patch_path, config.android_version)?;
+    if let Some((_, existing)) = renames.iter().find(|(src, _)| *src == Path::new(&source)) {
+        anyhow::bail!(
+            "'{}' is already copied to '{}' by .rommercp",
+            source,
+            existing.display()
+        );
+    }
+    let cp_path = patch_path.join(".rommercp");
+    let mut content = fs::read_to_string(&cp_path).unwrap_or_default();
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&format!("{} -> {}\n", source, dest));
+    fs::write(&cp_path, content)
+        .with_context(|| format!("Failed to write '{}'", cp_path.display()))?;
+    println!("- {}", source);
+    println!("+ {}", dest);
+    utils::print_success(&format!(
+        "📋 {} is now deployed as {} (via {})",
+        source,
+        dest,
+        cp_path.display()
+    ));
+    Ok(())
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Rewrites patch references in every `patch.yaml` of the project in place,
+/// keeping comments. `update` returns `None` to keep a reference,
+/// `Some(None)` to drop it or `Some(Some(new))` to replace it. Returns the
+/// modified files.
+pub fn update_references(
+    config_path: &str,
+    dry_run: bool,
+    update: impl Fn(&str) -> Option<Option<String>>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let project_dir = Path::new(config_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut modified = Vec::new();
+    let walker = WalkDir::new(project_dir)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'));
+    for entry in walker.flatten() {
+        if entry.file_name() != "patch.yaml" {
+            continue;
+        }
+        let content = fs::read_to_string(entry.path())?;
+        let Ok(meta) = serde_yaml::from_str::<Value>(&content) else {
+            utils::print_warning(&format!("Skipping invalid {}", entry.path().display()));
+            continue;
+        };
+        let mut changed = false;
+        let mut edited = content.clone();
+        for key in REFERENCE_KEYS {
+            let Some(Value::Sequence(references)) = meta.get(key) else {
+                continue;
+            };
+            let mut updated = Vec::new();
+            let mut key_changed = false;
+            for reference in references.iter().filter_map(Value::as_str) {
+                match update(reference) {
+                    Some(Some(new)) => {
+                        updated.push(new);
+                        key_changed = true;
+                    }
+                    Some(None) => key_changed = true,
+                    None => updated.push(reference.to_string()),
+                }
+            }
+            if !key_changed {
+                continue;
+            }
+            edited = config::replace_list_block(&edited, key, &updated).with_context(|| {
+                format!("No top-level '{}' in '{}'", key, entry.path().display())
+            })?;
+            changed = true;
+        }
+        if changed && !dry_run {
+            fs::write(entry.path(), edited)
+                .with_context(|| format!("Failed to write '{}'", entry.path().display()))?;
+        }
+        if changed {
+            modified.push(entry.into_path());
+        }
+    }
+    Ok(modified)
+}
@@ -0,0 +1,151 @@
+use crate::config::Config;
+use crate::utils;
+use anyhow::Context;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes a `patch.yaml` for a patch that has none, with the name taken from
+/// the folder and a description generated from the files it adds and the
+/// paths it deletes. With `interactive`, every field is asked for, the
+/// generated value being the default.
+pub fn generate_manifest(config: &Config, patch: &str, interactive: bool) -> anyhow::Result<()> {
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let manifest_path = patch_path.join("patch.yaml");
+    if manifest_path.exists() {
+        anyhow::bail!("'{}' already exists", manifest_path.display());
+    }
+    let name = patch_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| patch.to_string());
+    let deletions = read_deletions(&patch_path, config.android_version)?;
+    let description = describe(&utils::patch_files(&patch_path), &deletions);
+
+    let mut fields = vec![
+        ("name", name),
+        ("version", "1.0.0".to_string()),
+        ("author", String::new()),
+        ("description", description),
+    ];
+    if interactive && utils::is_ci() {
+        utils::print_warning("--interactive is ignored with --ci, using the generated values");
+    } else if interactive {
+        let stdin = std::io::stdin();
+        for (field, value) in &mut fields {
+            print!("{} [{}]: ", field, value);
+            std::io::stdout().flush()?;
+            let mut line = String::new();
+            stdin.lock().read_line(&mut line)?;
+            if !line.trim().is_empty() {
+                *value = line.trim().to_string();
+            }
+        }
+    }
+    let mut manifest = Mapping::new();
+    for (field, value) in fields {
+        manifest.insert(field.into(), Value::String(value));
+    }
+    let content = serde_yaml::to_string(&manifest)?;
+    std::fs::write(&manifest_path, &content)
+        .with_context(|| format!("Failed to write '{}'", manifest_path.display()))?;
+    print!("{}", content);
+    utils::print_success(&format!("📝 Created {}", manifest_path.display()));
+    Ok(())
+}
+
+/// Paths in the patch's `.rommerdel` and `.rommerfdel`
+fn read_deletions(patch_path: &Path, android_version: u32) -> anyhow::Result<Vec<PathBuf>> {
+    let mut deletions = Vec::new();
+    for del_file in [".rommerdel", ".rommerfdel"] {
+        let del_path = patch_path.join(del_file);
+        if del_path.exists() {
+            deletions.extend(utils::read_paths(&del_path, android_version)?);
+        }
+    }
+    Ok(deletions)
+}
+
+/// Summarizes what a patch does, e.g. "Adds 3 apps to system/app, removes
+/// 2 apps from system/priv-app". Paths are grouped by their first two
+/// components; under app directories, app folders are counted instead of
+/// files.
+fn describe(files: &[PathBuf], deletions: &[PathBuf]) -> String {
+    let summarize = |paths: &[PathBuf]| {
+        let mut groups: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for path in paths {
+            let parts: Vec<String> = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            if parts.is_empty() {
+                continue;
+            }
+            let depth = if parts.len() > 2 { 2 } else { parts.len() - 1 };
+            let dir = parts[..depth].join("/");
+            let is_app_dir = dir.ends_with("app") && parts.len() > depth;
+            let item = if is_app_dir {
+                parts[depth].clone()
+            } else {
+                parts.join("/")
+            };
+            groups.entry(dir).or_default().insert(item);
+        }
+        groups
+            .into_iter()
+            .map(|(dir, items)| {
+                let noun = match (dir.ends_with("app"), items.len()) {
+                    (true, 1) => "app",
+                    (true, _) => "apps",
+                    (false, 1) => "file",
+                    (false, _) => "files",
+                };
+                let dir = if dir.is_empty() { "the ROM root" } else { &dir };
+                (items.len(), noun, dir.to_string())
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut parts: Vec<String> = summarize(files)
+        .into_iter()
+        .map(|(count, noun, dir)| format!("adds {} {} to {}", count, noun, dir))
+        .collect();
+    parts.extend(
+        summarize(deletions)
+            .into_iter()
+            .map(|(count, noun, dir)| format!("removes {} {} from {}", count, noun, dir)),
+    );
+    let description = parts.join(", ");
+    let mut chars = description.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => "Empty patch".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let files: Vec<PathBuf> = [
+            "system/app/Foo/Foo.apk",
+            "system/app/Foo/lib/arm64/libfoo.so",
+            "system/app/Bar/Bar.apk",
+            "system/etc/hosts",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let deletions: Vec<PathBuf> = ["system/priv-app/Gmail", "system/priv-app/Maps"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(
+            describe(&files, &deletions),
+            "Adds 2 apps to system/app, adds 1 file to system/etc, removes 2 apps from system/priv-app"
+        );
+        assert_eq!(describe(&[], &[]), "Empty patch");
+    }
+}
@@ -0,0 +1,95 @@
+use crate::config::Config;
+use crate::patchmeta::{self, PatchMeta};
+use crate::utils;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+struct PatchInfo {
+    folder: String,
+    name: String,
+    meta: Option<PatchMeta>,
+    files: HashSet<PathBuf>,
+}
+
+impl PatchInfo {
+    /// Whether this patch lists `other` in its `conflicts_with`
+    fn declares_conflict(&self, other: &PatchInfo) -> bool {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.conflicts_with.as_ref())
+            .is_some_and(|conflicts| {
+                conflicts
+                    .iter()
+                    .any(|c| utils::patch_matches(&other.folder, c) || *c == other.name)
+            })
+    }
+}
+
+pub fn print_conflict_matrix(config: &Config) -> anyhow::Result<()> {
+    utils::print_section("🧮 PATCH CONFLICT MATRIX");
+    let patches: Vec<PatchInfo> = config
+        .patches
+        .iter()
+        .filter(|folder| {
+            let exists = config.resolve_patch_path(folder).exists();
+            if !exists {
+                utils::print_warning(&format!("Patch folder '{}' does not exist!", folder));
+            }
+            exists
+        })
+        .map(|folder| {
+            let path = config.resolve_patch_path(folder);
+            let meta = patchmeta::load_patch_meta(&path);
+            PatchInfo {
+                folder: folder.clone(),
+                name: meta
+                    .as_ref()
+                    .and_then(|m| m.name.clone())
+                    .unwrap_or_else(|| folder.trim_end_matches('/').to_string()),
+                files: utils::patch_files(&path).into_iter().collect(),
+                meta,
+            }
+        })
+        .collect();
+
+    let mut cells: HashMap<(usize, usize), &str> = HashMap::new();
+    for (i, a) in patches.iter().enumerate() {
+        for (j, b) in patches.iter().enumerate().skip(i + 1) {
+            let declared = a.declares_conflict(b) || b.declares_conflict(a);
+            let mut overlap: Vec<&PathBuf> = a.files.intersection(&b.files).collect();
+            overlap.sort();
+            let cell = if declared {
+                "🚫"
+            } else if !overlap.is_empty() {
+                utils::print_warning(&format!(
+                    "'{}' and '{}' both modify {} file(s):",
+                    a.name,
+                    b.name,
+                    overlap.len()
+                ));
+                for file in overlap {
+                    println!("    {}", file.display());
+                }
+                "⚠️"
+            } else {
+                "✅"
+            };
+            cells.insert((i, j), cell);
+            cells.insert((j, i), cell);
+        }
+    }
+
+    println!();
+    let header: Vec<&str> = patches.iter().map(|p| p.name.as_str()).collect();
+    println!("| Patch | {} |", header.join(" | "));
+    println!("|---|{}", "---|".repeat(patches.len()));
+    for (i, patch) in patches.iter().enumerate() {
+        let row: Vec<&str> = (0..patches.len())
+            .map(|j| cells.get(&(i, j)).copied().unwrap_or("—"))
+            .collect();
+        println!("| {} | {} |", patch.name, row.join(" | "));
+    }
+    println!();
+    utils::print_info("✅ compatible • ⚠️ overlapping files • 🚫 declared conflict");
+    Ok(())
+}
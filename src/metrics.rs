@@ -0,0 +1,138 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Metrics of the last build, written to the current directory
+pub const METRICS_PATH: &str = ".rommer-metrics.json";
+
+static TIMINGS: Mutex<Vec<StepTiming>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepTiming {
+    pub step: String,
+    pub secs: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BuildMetrics {
+    pub total_secs: f64,
+    pub steps: Vec<StepTiming>,
+}
+
+/// Records how long a build step took
+pub fn record(step: &str, duration: Duration) {
+    if let Ok(mut timings) = TIMINGS.lock() {
+        timings.push(StepTiming {
+            step: step.to_string(),
+            secs: duration.as_secs_f64(),
+        });
+    }
+}
+
+/// Writes the steps recorded so far to `.rommer-metrics.json`. Nothing is
+/// written when no step was recorded.
+pub fn save(total: Duration) -> anyhow::Result<()> {
+    let steps = TIMINGS.lock().map(|t| t.clone()).unwrap_or_default();
+    if steps.is_empty() {
+        return Ok(());
+    }
+    let metrics = BuildMetrics {
+        total_secs: total.as_secs_f64(),
+        steps,
+    };
+    fs::write(METRICS_PATH, serde_json::to_string_pretty(&metrics)? + "\n")
+        .with_context(|| format!("Failed to write '{}'", METRICS_PATH))
+}
+
+fn load(path: &Path) -> anyhow::Result<BuildMetrics> {
+    let content = fs::read_to_string(path).with_context(|| {
+        format!(
+            "Failed to read '{}', run a build first to record metrics",
+            path.display()
+        )
+    })?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse '{}'", path.display()))
+}
+
+/// Formats the change from `before` to `after` seconds, e.g. `-1.20s (-25%)`
+fn format_change(before: f64, after: f64) -> String {
+    let diff = after - before;
+    if before > 0.0 {
+        format!("{:+.2}s ({:+.0}%)", diff, diff / before * 100.0)
+    } else {
+        format!("{:+.2}s", diff)
+    }
+}
+
+/// Prints the metrics of the last build as a table, or compared against
+/// another metrics file
+pub fn print_metrics(compare: Option<&str>) -> anyhow::Result<()> {
+    crate::utils::print_section("⏱️  BUILD METRICS");
+    let metrics = load(Path::new(METRICS_PATH))?;
+    let Some(other_path) = compare else {
+        println!("{:<40} {:>10} {:>7}", "Step", "Duration", "%");
+        for timing in &metrics.steps {
+            let share = if metrics.total_secs > 0.0 {
+                timing.secs / metrics.total_secs * 100.0
+            } else {
+                0.0
+            };
+            println!("{:<40} {:>9.2}s {:>6.1}%", timing.step, timing.secs, share);
+        }
+        println!("{:<40} {:>9.2}s", "Total", metrics.total_secs);
+        return Ok(());
+    };
+    let other = load(Path::new(other_path))?;
+    println!(
+        "{:<40} {:>10} {:>10} {:>18}",
+        "Step", "Other", "Last", "Change"
+    );
+    for timing in &metrics.steps {
+        match other.steps.iter().find(|o| o.step == timing.step) {
+            Some(before) => println!(
+                "{:<40} {:>9.2}s {:>9.2}s {:>18}",
+                timing.step,
+                before.secs,
+                timing.secs,
+                format_change(before.secs, timing.secs)
+            ),
+            None => println!(
+                "{:<40} {:>10} {:>9.2}s {:>18}",
+                timing.step, "-", timing.secs, "new"
+            ),
+        }
+    }
+    for before in other
+        .steps
+        .iter()
+        .filter(|o| !metrics.steps.iter().any(|t| t.step == o.step))
+    {
+        println!(
+            "{:<40} {:>9.2}s {:>10} {:>18}",
+            before.step, before.secs, "-", "removed"
+        );
+    }
+    println!(
+        "{:<40} {:>9.2}s {:>9.2}s {:>18}",
+        "Total",
+        other.total_secs,
+        metrics.total_secs,
+        format_change(other.total_secs, metrics.total_secs)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_change() {
+        assert_eq!(format_change(4.0, 3.0), "-1.00s (-25%)");
+        assert_eq!(format_change(2.0, 3.0), "+1.00s (+50%)");
+        assert_eq!(format_change(0.0, 1.5), "+1.50s");
+    }
+}
@@ -0,0 +1,170 @@
+use crate::config::Config;
+use crate::utils;
+use anyhow::Context;
+use std::fmt;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_BODY_TEMPLATE: &str =
+    r#"{"message": "{message}", "device": "{device}", "status": "{status}"}"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Success => "success",
+            Status::Failure => "failure",
+        })
+    }
+}
+
+/// Outcome of a build, sent to every configured channel
+#[derive(Debug, Clone)]
+pub struct BuildNotification {
+    pub status: Status,
+    pub message: String,
+}
+
+/// Sends `event` to every configured channel. Failures are only warned
+/// about, so a broken webhook never fails a build.
+pub async fn notify_all(config: &Config, event: &BuildNotification) {
+    let notifications = &config.notifications;
+    if notifications.discord.is_some()
+        && let Err(e) = send_discord(config, event).await
+    {
+        utils::print_warning(&format!("Discord notification failed: {:#}", e));
+    }
+    if notifications.slack.is_some()
+        && let Err(e) = send_slack(config, event).await
+    {
+        utils::print_warning(&format!("Slack notification failed: {:#}", e));
+    }
+    if notifications.webhook.is_some()
+        && let Err(e) = send_webhook(config, event).await
+    {
+        utils::print_warning(&format!("Webhook notification failed: {:#}", e));
+    }
+}
+
+pub async fn send_discord(config: &Config, event: &BuildNotification) -> anyhow::Result<()> {
+    let Some(discord) = &config.notifications.discord else {
+        return Ok(());
+    };
+    let body = serde_json::json!({ "content": text(config, event) });
+    client()?
+        .post(&discord.webhook_url)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+pub async fn send_slack(config: &Config, event: &BuildNotification) -> anyhow::Result<()> {
+    let Some(slack) = &config.notifications.slack else {
+        return Ok(());
+    };
+    let body = serde_json::json!({ "text": text(config, event) });
+    client()?
+        .post(&slack.webhook_url)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Calls a generic webhook: GET with `message`, `device` and `status` query
+/// parameters, or POST with the rendered `body_template`
+pub async fn send_webhook(config: &Config, event: &BuildNotification) -> anyhow::Result<()> {
+    let Some(webhook) = &config.notifications.webhook else {
+        return Ok(());
+    };
+    let status = event.status.to_string();
+    let request = match webhook.method.to_uppercase().as_str() {
+        "GET" => {
+            let url = reqwest::Url::parse_with_params(
+                &webhook.url,
+                [
+                    ("message", event.message.as_str()),
+                    ("device", config.device.as_str()),
+                    ("status", status.as_str()),
+                ],
+            )
+            .with_context(|| format!("Invalid webhook URL '{}'", webhook.url))?;
+            client()?.get(url)
+        }
+        "POST" => {
+            let template = webhook
+                .body_template
+                .as_deref()
+                .unwrap_or(DEFAULT_BODY_TEMPLATE);
+            client()?
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(render_template(
+                    template,
+                    &event.message,
+                    &config.device,
+                    &status,
+                ))
+        }
+        other => anyhow::bail!("Unsupported webhook method '{}', use GET or POST", other),
+    };
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+fn client() -> anyhow::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(format!("ROMMER/{}", env!("CARGO_PKG_VERSION")))
+        .timeout(TIMEOUT)
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+fn text(config: &Config, event: &BuildNotification) -> String {
+    let icon = match event.status {
+        Status::Success => "✅",
+        Status::Failure => "❌",
+    };
+    format!("{} [{}] {}", icon, config.device, event.message)
+}
+
+/// Replaces the placeholders of a JSON body template with the values
+/// escaped as JSON string contents
+fn render_template(template: &str, message: &str, device: &str, status: &str) -> String {
+    let escape = |value: &str| {
+        let quoted = serde_json::Value::from(value).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    template
+        .replace("{message}", &escape(message))
+        .replace("{device}", &escape(device))
+        .replace("{status}", &escape(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let body = render_template(
+            DEFAULT_BODY_TEMPLATE,
+            "Failed: \"unzip\" error\nline 2",
+            "bluejay",
+            "failure",
+        );
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["message"], "Failed: \"unzip\" error\nline 2");
+        assert_eq!(value["device"], "bluejay");
+        assert_eq!(value["status"], "failure");
+    }
+}
@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Debug, Deserialize, Default)]
 pub struct PatchMeta {
@@ -8,6 +9,24 @@ pub struct PatchMeta {
     pub tags: Option<Vec<String>>,
     pub requires_android: Option<String>,
     pub author: Option<String>,
+    pub conflicts_with: Option<Vec<String>>,
+    pub depends_on: Option<Vec<String>>,
+    pub priority: Option<i32>,
+    pub patch_checksum: Option<String>,
+    pub file_conflict_strategy: Option<String>,
+    /// SHA-256 of every patch file by relative path, written by `patch export-bundle`
+    pub file_hashes: Option<HashMap<String, String>>,
+    pub changelog: Option<Vec<ChangelogEntry>>,
+    /// Git commit the patch was pinned at by `patch pin`
+    pub pinned_commit: Option<String>,
+}
+
+/// One released version of a patch, listed under `changelog` in `patch.yaml`
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChangelogEntry {
+    pub version: String,
+    pub date: Option<String>,
+    pub changes: Vec<String>,
 }
 
 pub fn load_patch_meta<P: AsRef<std::path::Path>>(patch_path: P) -> Option<PatchMeta> {
@@ -19,3 +38,25 @@ pub fn load_patch_meta<P: AsRef<std::path::Path>>(patch_path: P) -> Option<Patch
     let content = std::fs::read_to_string(&manifest_path).ok()?;
     serde_yaml::from_str(&content).ok()
 }
+
+/// The text of a patch's `patch.yaml` and its mapping, for commands that edit
+/// it in place. A missing or empty file reads as an empty mapping.
+pub fn read_manifest(
+    patch_path: &std::path::Path,
+) -> anyhow::Result<(String, serde_yaml::Mapping)> {
+    use anyhow::Context;
+    let manifest_path = patch_path.join("patch.yaml");
+    if !manifest_path.exists() {
+        return Ok((String::new(), serde_yaml::Mapping::new()));
+    }
+    let content = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read '{}'", manifest_path.display()))?;
+    let mapping = match serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse '{}'", manifest_path.display()))?
+    {
+        serde_yaml::Value::Mapping(mapping) => mapping,
+        serde_yaml::Value::Null => serde_yaml::Mapping::new(),
+        _ => anyhow::bail!("'{}' is not a YAML mapping", manifest_path.display()),
+    };
+    Ok((content, mapping))
+}
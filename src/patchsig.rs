@@ -0,0 +1,243 @@
+use crate::config::Config;
+use crate::utils;
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::rand::SystemRandom;
+use ring::signature::{
+    RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_SHA256, RsaKeyPair, UnparsedPublicKey,
+};
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Signature written by `patch sign` into the patch folder
+pub const SIGNATURE_FILE: &str = "patch-signature.bin";
+
+/// Signs a patch folder with an RSA private key, from `key` or
+/// `signing.patch_key_path`, writing the signature to `patch-signature.bin`
+pub fn sign_patch(config: &Config, patch: &str, key: Option<&str>) -> anyhow::Result<()> {
+    utils::print_section("✍️  SIGNING PATCH");
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let key_path = key
+        .map(str::to_string)
+        .or_else(|| {
+            config
+                .signing
+                .as_ref()
+                .and_then(|s| s.patch_key_path.clone())
+        })
+        .context("No private key, pass --key or set signing.patch_key_path")?;
+    let key_path = shellexpand::tilde(&key_path).to_string();
+    let key = fs::read(&key_path)
+        .with_context(|| format!("Failed to read private key '{}'", key_path))?;
+    let signature = sign_dir(&patch_path, &key)?;
+    let signature_path = patch_path.join(SIGNATURE_FILE);
+    fs::write(&signature_path, signature)
+        .with_context(|| format!("Failed to write '{}'", signature_path.display()))?;
+    utils::print_success(&format!("🔏 Signed {}", signature_path.display()));
+    Ok(())
+}
+
+/// Checks the `patch-signature.bin` of a patch folder against an RSA public
+/// key. Fails when the patch is unsigned, or was changed since it was signed.
+pub fn verify_patch_signature(patch_path: &Path, public_key_path: &Path) -> anyhow::Result<()> {
+    let public_key = fs::read(public_key_path)
+        .with_context(|| format!("Failed to read public key '{}'", public_key_path.display()))?;
+    verify_dir(patch_path, &public_key)
+}
+
+/// What is signed: the tree checksum of the patch, covering every file
+/// including `patch.yaml`, but not the signature itself. Symlinks are
+/// refused, as the checksum skips them but applying the patch follows them.
+fn signed_message(patch_path: &Path) -> anyhow::Result<String> {
+    if let Some(link) = WalkDir::new(patch_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.path_is_symlink())
+    {
+        anyhow::bail!(
+            "Patch contains a symlink, which can't be signed: {}",
+            link.path().display()
+        );
+    }
+    crate::checksum::calculate_tree_checksum(patch_path, &[SIGNATURE_FILE])
+}
+
+fn sign_dir(patch_path: &Path, key: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let der = decode_key(key, "PRIVATE KEY")?;
+    let key_pair = RsaKeyPair::from_pkcs8(&der)
+        .or_else(|_| RsaKeyPair::from_der(&der))
+        .map_err(|e| anyhow::anyhow!("Not a valid RSA private key: {}", e))?;
+    let message = signed_message(patch_path)?;
+    let mut signature = vec![0; key_pair.public().modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            message.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to sign patch"))?;
+    Ok(signature)
+}
+
+fn verify_dir(patch_path: &Path, public_key: &[u8]) -> anyhow::Result<()> {
+    let signature_path = patch_path.join(SIGNATURE_FILE);
+    if !signature_path.exists() {
+        anyhow::bail!("Patch is not signed (no {})", SIGNATURE_FILE);
+    }
+    let signature = fs::read(&signature_path)
+        .with_context(|| format!("Failed to read '{}'", signature_path.display()))?;
+    let der = decode_key(public_key, "PUBLIC KEY")?;
+    let der = spki_to_pkcs1(&der).unwrap_or(der);
+    let message = signed_message(patch_path)?;
+    UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, der)
+        .verify(message.as_bytes(), &signature)
+        .map_err(|_| anyhow::anyhow!("Invalid signature, the patch was changed since it was signed or another key signed it"))
+}
+
+/// The DER bytes of a key file, decoding it first if it is PEM
+fn decode_key(data: &[u8], kind: &str) -> anyhow::Result<Vec<u8>> {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return Ok(data.to_vec());
+    };
+    if !text.trim_start().starts_with("-----BEGIN") {
+        return Ok(data.to_vec());
+    }
+    let body: String = text
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .map(str::trim)
+        .collect();
+    STANDARD
+        .decode(body)
+        .with_context(|| format!("Invalid PEM {}", kind.to_lowercase()))
+}
+
+/// Tag and content range of the DER element at `pos`
+fn der_element(der: &[u8], pos: usize) -> Option<(u8, usize, usize)> {
+    let tag = *der.get(pos)?;
+    let first = usize::from(*der.get(pos + 1)?);
+    let (len, start) = if first < 0x80 {
+        (first, pos + 2)
+    } else {
+        let n = first & 0x7F;
+        let bytes = der.get(pos + 2..pos + 2 + n)?;
+        (
+            bytes.iter().fold(0, |len, b| (len << 8) | usize::from(*b)),
+            pos + 2 + n,
+        )
+    };
+    (start + len <= der.len()).then_some((tag, start, start + len))
+}
+
+/// The PKCS#1 `RSAPublicKey` inside a `SubjectPublicKeyInfo`, the format of
+/// `openssl pkey -pubout`. `None` if `der` isn't one.
+fn spki_to_pkcs1(der: &[u8]) -> Option<Vec<u8>> {
+    let (0x30, start, _) = der_element(der, 0)? else {
+        return None;
+    };
+    let (0x30, _, algorithm_end) = der_element(der, start)? else {
+        return None;
+    };
+    let (0x03, bits_start, bits_end) = der_element(der, algorithm_end)? else {
+        return None;
+    };
+    // The bit string starts with the number of unused bits, always 0 here
+    (der.get(bits_start) == Some(&0)).then(|| der[bits_start + 1..bits_end].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// A fresh RSA key pair as PEM, or `None` when `openssl` isn't installed
+    fn generate_key_pair(dir: &Path) -> anyhow::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let (private, public) = (dir.join("key.pem"), dir.join("key.pub.pem"));
+        let generated = std::process::Command::new("openssl")
+            .args([
+                "genpkey",
+                "-algorithm",
+                "RSA",
+                "-pkeyopt",
+                "rsa_keygen_bits:2048",
+            ])
+            .arg("-out")
+            .arg(&private)
+            .output();
+        match generated {
+            Ok(output) if output.status.success() => {}
+            _ => return Ok(None),
+        }
+        let status = std::process::Command::new("openssl")
+            .args(["pkey", "-pubout", "-in"])
+            .arg(&private)
+            .arg("-out")
+            .arg(&public)
+            .status()?;
+        anyhow::ensure!(status.success(), "openssl failed to export the public key");
+        Ok(Some((fs::read(private)?, fs::read(public)?)))
+    }
+
+    #[test]
+    fn test_sign_and_verify() -> anyhow::Result<()> {
+        let keys = tempdir()?;
+        let Some((private_key, public_key)) = generate_key_pair(keys.path())? else {
+            eprintln!("openssl is not installed, skipping");
+            return Ok(());
+        };
+        let patch = tempdir()?;
+        fs::create_dir_all(patch.path().join("system/etc"))?;
+        fs::write(patch.path().join("system/etc/hosts"), "127.0.0.1 localhost")?;
+        fs::write(patch.path().join("patch.yaml"), "name: hosts\n")?;
+        assert!(verify_dir(patch.path(), &public_key).is_err());
+
+        let signature = sign_dir(patch.path(), &private_key)?;
+        fs::write(patch.path().join(SIGNATURE_FILE), signature)?;
+        verify_dir(patch.path(), &public_key)?;
+
+        // Only the top-level signature is left out of what is signed
+        fs::write(patch.path().join("system/etc").join(SIGNATURE_FILE), "")?;
+        assert!(verify_dir(patch.path(), &public_key).is_err());
+        fs::remove_file(patch.path().join("system/etc").join(SIGNATURE_FILE))?;
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(keys.path(), patch.path().join("system/keys"))?;
+            assert!(verify_dir(patch.path(), &public_key).is_err());
+            fs::remove_file(patch.path().join("system/keys"))?;
+        }
+
+        fs::write(patch.path().join("system/etc/hosts"), "0.0.0.0 example.com")?;
+        assert!(verify_dir(patch.path(), &public_key).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_survives_export_bundle() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let Some((private_key, public_key)) = generate_key_pair(dir.path())? else {
+            eprintln!("openssl is not installed, skipping");
+            return Ok(());
+        };
+        let patch = dir.path().join("hosts");
+        fs::create_dir_all(patch.join("system/etc"))?;
+        fs::write(patch.join("system/etc/hosts"), "127.0.0.1 localhost")?;
+        fs::write(patch.join("patch.yaml"), "# Blocks ads\nname: hosts\n")?;
+        fs::write(patch.join(SIGNATURE_FILE), sign_dir(&patch, &private_key)?)?;
+
+        let bundle = dir.path().join("bundle.zip");
+        crate::bundle::export_bundle(
+            &crate::config::test_config(),
+            &[patch.to_string_lossy().to_string()],
+            &bundle.to_string_lossy(),
+            false,
+        )?;
+        let extracted = dir.path().join("extracted");
+        zip::ZipArchive::new(fs::File::open(&bundle)?)?.extract(&extracted)?;
+        verify_dir(&extracted.join("hosts"), &public_key)?;
+        Ok(())
+    }
+}
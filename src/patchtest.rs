@@ -0,0 +1,284 @@
+use crate::config::Config;
+use crate::utils;
+use crate::verify::UPDATER_SCRIPT;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+use walkdir::WalkDir;
+
+/// Applies a single patch to an extracted copy of `rom_zip` and runs
+/// structural checks on the result
+pub fn test_patch(config: &Config, patch: &str, rom_zip: &str) -> anyhow::Result<()> {
+    utils::print_section("🧪 TESTING PATCH");
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+    if !patch_path.is_dir() {
+        anyhow::bail!("Patch folder '{}' does not exist", patch_path.display());
+    }
+    if !rom_path.exists() {
+        anyhow::bail!("ROM '{}' does not exist", rom_path.display());
+    }
+
+    let tmp_dir = tempdir()?;
+    let work_dir = tmp_dir.path();
+    crate::unzip::unzip_rom(&rom_path, work_dir, config.validate_extraction, false)?;
+    utils::print_info(&format!("🔧 Applying patch: {}", patch_path.display()));
+    let strategy =
+        crate::patchmeta::load_patch_meta(&patch_path).and_then(|meta| meta.file_conflict_strategy);
+    utils::apply_patch(
+        &patch_path,
+        work_dir,
+        strategy.as_deref(),
+        config,
+        &utils::CopiedFiles::default(),
+        false,
+    )?;
+
+    utils::print_section("📋 CHECKS");
+    let checks = [
+        ("updater-script is present", check_updater_script(work_dir)),
+        (
+            "no zero-byte files introduced",
+            check_zero_byte_files(&patch_path, work_dir),
+        ),
+        (
+            "deleted paths are absent",
+            check_deletions(&patch_path, work_dir, config.android_version)?,
+        ),
+    ];
+    let mut failed = 0;
+    for (name, problems) in &checks {
+        if problems.is_empty() {
+            utils::print_success(&format!("PASS: {}", name));
+        } else {
+            failed += 1;
+            utils::print_warning(&format!("FAIL: {}", name));
+            for problem in problems {
+                println!("    {}", problem);
+            }
+        }
+    }
+    tmp_dir.close()?;
+    if failed > 0 {
+        anyhow::bail!("{} of {} check(s) failed", failed, checks.len());
+    }
+    utils::print_success("🎉 All checks passed");
+    Ok(())
+}
+
+fn check_updater_script(work_dir: &Path) -> Vec<String> {
+    if work_dir.join(UPDATER_SCRIPT).is_file() {
+        Vec::new()
+    } else {
+        vec![format!("'{}' is missing", UPDATER_SCRIPT)]
+    }
+}
+
+fn check_zero_byte_files(patch_path: &Path, work_dir: &Path) -> Vec<String> {
+    utils::patch_files(patch_path)
+        .into_iter()
+        .filter(|rel| {
+            work_dir
+                .join(rel)
+                .metadata()
+                .is_ok_and(|meta| meta.is_file() && meta.len() == 0)
+        })
+        .map(|rel| format!("'{}' is empty", rel.display()))
+        .collect()
+}
+
+fn check_deletions(
+    patch_path: &Path,
+    work_dir: &Path,
+    android_version: u32,
+) -> anyhow::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    for del_file in [".rommerdel", ".rommerfdel"] {
+        let del_path = patch_path.join(del_file);
+        if !del_path.exists() {
+            continue;
+        }
+        let dirs = del_file == ".rommerdel";
+        for entry in utils::read_paths(&del_path, android_version)? {
+            for item in crate::globdel::expand_deletion(work_dir, &entry, dirs)? {
+                if work_dir.join(&item).exists() {
+                    problems.push(format!(
+                        "'{}' from {} still exists",
+                        item.display(),
+                        del_file
+                    ));
+                }
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// Runs only the `.rommerdel` and `.rommerfdel` deletions of a patch on an
+/// extracted copy of `rom_zip`, reporting what they delete and which entries
+/// match nothing
+pub fn test_deletions(config: &Config, patch: &str, rom_zip: &str) -> anyhow::Result<()> {
+    utils::print_section("🧪 TESTING DELETIONS");
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let rom_path = PathBuf::from(shellexpand::tilde(rom_zip).to_string());
+    if !patch_path.is_dir() {
+        anyhow::bail!("Patch folder '{}' does not exist", patch_path.display());
+    }
+    if !rom_path.exists() {
+        anyhow::bail!("ROM '{}' does not exist", rom_path.display());
+    }
+
+    let tmp_dir = tempdir()?;
+    let work_dir = tmp_dir.path();
+    crate::unzip::unzip_rom(&rom_path, work_dir, config.validate_extraction, false)?;
+    let (dirs_before, files_before) = count_entries(work_dir);
+    let (matched, unmatched) = deletion_outcomes(&patch_path, work_dir, config.android_version)?;
+    utils::handle_deletions(
+        &patch_path,
+        work_dir,
+        ".rommerdel",
+        "directory",
+        config.android_version,
+        false,
+    )?;
+    utils::handle_file_deletions(
+        &patch_path,
+        work_dir,
+        ".rommerfdel",
+        "file",
+        config.android_version,
+        false,
+    )?;
+    let (dirs_after, files_after) = count_entries(work_dir);
+    tmp_dir.close()?;
+
+    utils::print_section("📋 RESULTS");
+    println!("deleted: {}", matched.len());
+    for item in &matched {
+        println!("    {}", item);
+    }
+    println!(
+        "directories: {} -> {} ({} removed)",
+        dirs_before,
+        dirs_after,
+        dirs_before - dirs_after
+    );
+    println!(
+        "files: {} -> {} ({} removed)",
+        files_before,
+        files_after,
+        files_before - files_after
+    );
+    if !unmatched.is_empty() {
+        for item in &unmatched {
+            utils::print_warning(item);
+        }
+        anyhow::bail!("{} deletion(s) matched nothing", unmatched.len());
+    }
+    utils::print_success("🎉 Every deletion matched");
+    Ok(())
+}
+
+/// The deletion entries of a patch that match a path in `work_dir`, and a
+/// message for each one that doesn't, including directories listed in
+/// `.rommerfdel` and files listed in `.rommerdel`, which are not deleted
+fn deletion_outcomes(
+    patch_path: &Path,
+    work_dir: &Path,
+    android_version: u32,
+) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let (mut matched, mut unmatched) = (Vec::new(), Vec::new());
+    for (del_file, wants_dir) in [(".rommerdel", true), (".rommerfdel", false)] {
+        let del_path = patch_path.join(del_file);
+        if !del_path.exists() {
+            continue;
+        }
+        for entry in utils::read_paths(&del_path, android_version)? {
+            if crate::globdel::is_glob(&entry.to_string_lossy()) {
+                let items = crate::globdel::expand_deletion(work_dir, &entry, wants_dir)?;
+                if items.is_empty() {
+                    unmatched.push(format!(
+                        "{}: '{}' matches nothing",
+                        del_file,
+                        entry.display()
+                    ));
+                }
+                for item in items {
+                    matched.push(format!(
+                        "{} ({}: {})",
+                        item.display(),
+                        del_file,
+                        entry.display()
+                    ));
+                }
+                continue;
+            }
+            let path = work_dir.join(&entry);
+            let item = entry.display();
+            if !path.exists() {
+                unmatched.push(format!("{}: '{}' matches nothing", del_file, item));
+            } else if path.is_dir() != wants_dir {
+                let kind = if wants_dir { "a file" } else { "a directory" };
+                unmatched.push(format!(
+                    "{}: '{}' is {}, which {} doesn't delete",
+                    del_file, item, kind, del_file
+                ));
+            } else {
+                matched.push(format!("{} ({})", item, del_file));
+            }
+        }
+    }
+    Ok((matched, unmatched))
+}
+
+/// Number of directories and files under `dir`, not counting `dir` itself
+fn count_entries(dir: &Path) -> (usize, usize) {
+    WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .flatten()
+        .fold((0, 0), |(dirs, files), entry| {
+            if entry.file_type().is_dir() {
+                (dirs + 1, files)
+            } else {
+                (dirs, files + 1)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_deletion_outcomes() -> anyhow::Result<()> {
+        let (patch, rom) = (tempdir()?, tempdir()?);
+        fs::create_dir_all(rom.path().join("system/app/Browser"))?;
+        fs::write(rom.path().join("system/app/Browser/Browser.apk"), "apk")?;
+        fs::create_dir_all(rom.path().join("system/etc"))?;
+        fs::write(rom.path().join("system/etc/hosts"), "127.0.0.1 localhost")?;
+        fs::write(
+            patch.path().join(".rommerdel"),
+            "system/app/Browser\nsystem/app/Browsr\nsystem/etc/hosts\nsystem/priv-app/*\n",
+        )?;
+        fs::write(patch.path().join(".rommerfdel"), "system/etc/hosts\n")?;
+        let (matched, unmatched) = deletion_outcomes(patch.path(), rom.path(), 14)?;
+        assert_eq!(
+            matched,
+            [
+                "system/app/Browser (.rommerdel)",
+                "system/etc/hosts (.rommerfdel)"
+            ]
+        );
+        assert_eq!(
+            unmatched,
+            [
+                ".rommerdel: 'system/app/Browsr' matches nothing",
+                ".rommerdel: 'system/etc/hosts' is a file, which .rommerdel doesn't delete",
+                ".rommerdel: 'system/priv-app/*' matches nothing",
+            ]
+        );
+        assert_eq!(count_entries(rom.path()), (4, 2));
+        Ok(())
+    }
+}
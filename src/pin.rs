@@ -0,0 +1,130 @@
+use crate::config::Config;
+use crate::patchmeta;
+use crate::utils;
+use anyhow::Context;
+use std::path::Path;
+use std::process::Command;
+
+/// Pathspec limiting git to the patch folder, leaving out `patch.yaml` so
+/// writing and committing the pin itself doesn't count as a modification
+const PATCH_PATHSPEC: [&str; 2] = [".", ":(exclude)patch.yaml"];
+
+/// Runs git in `dir` and returns its trimmed stdout
+fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Writes the current git commit of a patch's repository as `pinned_commit`
+/// in its `patch.yaml`, in place
+pub fn pin_patch(config: &Config, patch: &str) -> anyhow::Result<()> {
+    let patch_path = crate::bundle::resolve_patch(config, patch)?;
+    let commit = git(&patch_path, &["rev-parse", "HEAD"]).with_context(|| {
+        format!(
+            "Patch '{}' is not in a git repository with commits",
+            patch_path.display()
+        )
+    })?;
+    let manifest_path = patch_path.join("patch.yaml");
+    let (content, _) = patchmeta::read_manifest(&patch_path)?;
+    let updated = crate::config::set_scalar(&content, "pinned_commit", &commit)?;
+    std::fs::write(&manifest_path, updated)
+        .with_context(|| format!("Failed to write '{}'", manifest_path.display()))?;
+    if let Some(reason) = pin_mismatch(&patch_path, &commit)? {
+        utils::print_warning(&format!(
+            "Patch '{}' is pinned, but {}",
+            patch_path.display(),
+            reason
+        ));
+    }
+    utils::print_success(&format!("📌 Pinned {} at {}", patch_path.display(), commit));
+    Ok(())
+}
+
+/// Why a patch folder no longer matches `pinned`, or `None` when no file
+/// besides `patch.yaml` changed since that commit, committed or not
+pub fn pin_mismatch(patch_path: &Path, pinned: &str) -> anyhow::Result<Option<String>> {
+    let range = format!("{}..HEAD", pinned);
+    let mut log_args = vec!["log", "--format=%H", "-n1", range.as_str(), "--"];
+    log_args.extend(PATCH_PATHSPEC);
+    let last_change = git(patch_path, &log_args)
+        .with_context(|| format!("Failed to compare against pinned commit {}", pinned))?;
+    if !last_change.is_empty() {
+        return Ok(Some(format!("it was changed in commit {}", last_change)));
+    }
+    let mut status_args = vec!["status", "--porcelain", "--"];
+    status_args.extend(PATCH_PATHSPEC);
+    if !git(patch_path, &status_args)?.is_empty() {
+        return Ok(Some("it has uncommitted changes".to_string()));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pin_mismatch() -> anyhow::Result<()> {
+        let repo = tempdir()?;
+        let patch = repo.path().join("hosts");
+        fs::create_dir_all(&patch)?;
+        fs::write(patch.join("hosts"), "127.0.0.1 localhost\n")?;
+        let commit = |message: &str| {
+            git(
+                repo.path(),
+                &[
+                    "-c",
+                    "user.name=test",
+                    "-c",
+                    "user.email=test@example.com",
+                    "commit",
+                    "-qm",
+                    message,
+                ],
+            )
+        };
+        git(repo.path(), &["init", "-q"])?;
+        git(repo.path(), &["add", "-A"])?;
+        commit("add hosts")?;
+        let pinned = git(&patch, &["rev-parse", "HEAD"])?;
+        assert_eq!(pin_mismatch(&patch, &pinned)?, None);
+
+        // Recording the pin in patch.yaml is not a modification
+        fs::write(
+            patch.join("patch.yaml"),
+            format!("pinned_commit: {}\n", pinned),
+        )?;
+        git(repo.path(), &["add", "-A"])?;
+        commit("pin hosts")?;
+        assert_eq!(pin_mismatch(&patch, &pinned)?, None);
+
+        fs::write(patch.join("hosts"), "0.0.0.0 ads.example.com\n")?;
+        assert_eq!(
+            pin_mismatch(&patch, &pinned)?.as_deref(),
+            Some("it has uncommitted changes")
+        );
+        git(repo.path(), &["add", "-A"])?;
+        commit("block ads")?;
+        let changed = git(&patch, &["rev-parse", "HEAD"])?;
+        assert_eq!(
+            pin_mismatch(&patch, &pinned)?,
+            Some(format!("it was changed in commit {}", changed))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,857 @@
+use crate::args::Args;
+use crate::ci::Failure;
+use crate::config::{self, Config, PatchSetMode};
+use crate::steps::{self, Step};
+use crate::{
+    checksum, download, env, finalize, lock, metrics, patchmeta, patchsig, pin, plan, provenance,
+    unzip, utils,
+};
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+use tokio::sync::mpsc::Sender;
+
+/// Flags of a build. The command line fills them in from its arguments;
+/// library users start from `BuildOptions::default()`.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// ROM ZIP to patch, or `.download` to download the configured ROM
+    pub romzip: String,
+    /// sha256sum file with the expected hash of the ROM
+    pub checksum_file: Option<String>,
+    /// Directory to create the working directory in
+    pub temp_dir: Option<String>,
+    /// Build in this fixed directory and keep it (overrides `keep_dir`)
+    pub keep_dir: Option<String>,
+    /// Resume a previous build from this step
+    pub from_step: Option<Step>,
+    /// Run only this step and stop
+    pub only_step: Option<Step>,
+    /// Resume patching a kept directory at this patch
+    pub resume_from_patch: Option<String>,
+    /// Resume patching a kept directory after its applied patches
+    pub resume: bool,
+    /// Work directory to build in, or to resume
+    pub work_dir: Option<String>,
+    /// Keep the working directory
+    pub no_cleanup: bool,
+    /// Don't sign the final ROM
+    pub skip_signing: bool,
+    /// Don't upload the final ROM
+    pub no_upload: bool,
+    /// Make no network calls
+    pub offline: bool,
+    /// Stop after patching, keeping the patched directory
+    pub no_rezip: bool,
+    /// Only report what the build would do
+    pub dry_run: bool,
+    /// Override `max_patch_count`
+    pub max_patches: Option<usize>,
+    /// Override `max_patch_size_mb`
+    pub max_patch_size: Option<u64>,
+    /// Rezip even if the output ZIP matches the patched ROM
+    pub force_rezip: bool,
+    /// Write a JSON build plan to this file instead of building
+    pub plan: Option<String>,
+    /// Remove a stale build lock left by a dead process
+    pub break_lock: bool,
+    /// Only apply patches with these tags
+    pub tags: Option<Vec<String>>,
+    /// Abort on a bad `patch_checksum` instead of skipping the patch
+    pub strict_patch_checksums: bool,
+    /// Abort when a pinned patch changed since its `pinned_commit`
+    pub strict_pins: bool,
+    /// Abort on unsigned patches instead of skipping them
+    pub strict_patch_signatures: bool,
+    /// Skip patches whose files are all already in the ROM
+    pub apply_only_new: bool,
+    /// Apply these patches first, in this order
+    pub patch_order: Option<Vec<String>>,
+    /// Patch set file to combine with the configured patches
+    pub patch_set: Option<String>,
+    /// How `patch_set` combines with the configured patches
+    pub patch_set_mode: PatchSetMode,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            romzip: ".download".to_string(),
+            checksum_file: None,
+            temp_dir: None,
+            keep_dir: None,
+            from_step: None,
+            only_step: None,
+            resume_from_patch: None,
+            resume: false,
+            work_dir: None,
+            no_cleanup: false,
+            skip_signing: false,
+            no_upload: false,
+            offline: false,
+            no_rezip: false,
+            dry_run: false,
+            max_patches: None,
+            max_patch_size: None,
+            force_rezip: false,
+            plan: None,
+            break_lock: false,
+            tags: None,
+            strict_patch_checksums: false,
+            strict_pins: false,
+            strict_patch_signatures: false,
+            apply_only_new: false,
+            patch_order: None,
+            patch_set: None,
+            patch_set_mode: PatchSetMode::Replace,
+        }
+    }
+}
+
+impl From<&Args> for BuildOptions {
+    fn from(args: &Args) -> Self {
+        Self {
+            romzip: args.romzip.clone(),
+            checksum_file: args.checksum_file.clone(),
+            temp_dir: args.temp_dir.clone(),
+            keep_dir: args.keep_dir.clone(),
+            from_step: args.from_step,
+            only_step: args.only_step,
+            resume_from_patch: args.resume_from_patch.clone(),
+            resume: args.resume,
+            work_dir: args.work_dir.clone(),
+            no_cleanup: args.no_cleanup,
+            skip_signing: args.skip_signing,
+            no_upload: args.no_upload,
+            offline: args.offline,
+            no_rezip: args.no_rezip,
+            dry_run: args.dry_run,
+            max_patches: args.max_patches,
+            max_patch_size: args.max_patch_size,
+            force_rezip: args.force_rezip,
+            plan: args.plan.clone(),
+            break_lock: args.break_lock,
+            tags: args.tags.clone(),
+            strict_patch_checksums: args.strict_patch_checksums,
+            strict_pins: args.strict_pins,
+            strict_patch_signatures: args.strict_patch_signatures,
+            apply_only_new: args.apply_only_new,
+            patch_order: args.patch_order.clone(),
+            patch_set: args.patch_set.clone(),
+            patch_set_mode: args.patch_set_mode,
+        }
+    }
+}
+
+/// A lifecycle stage of a build, sent to the progress channel of a
+/// [`BuildPipeline`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildEvent {
+    Downloading,
+    Extracting {
+        rom: PathBuf,
+    },
+    /// `index` counts from 1 among the `total` configured patches
+    ApplyingPatch {
+        index: usize,
+        total: usize,
+        patch: String,
+    },
+    Rezipping {
+        output: PathBuf,
+    },
+    Signing {
+        output: PathBuf,
+    },
+    Finished {
+        rom: PathBuf,
+    },
+}
+
+impl fmt::Display for BuildEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildEvent::Downloading => write!(f, "Downloading ROM"),
+            BuildEvent::Extracting { rom } => write!(f, "Extracting {}", rom.display()),
+            BuildEvent::ApplyingPatch {
+                index,
+                total,
+                patch,
+            } => write!(f, "Applying patch {} of {}: {}", index, total, patch),
+            BuildEvent::Rezipping { output } => write!(f, "Rezipping {}", output.display()),
+            BuildEvent::Signing { output } => write!(f, "Signing {}", output.display()),
+            BuildEvent::Finished { rom } => write!(f, "Finished {}", rom.display()),
+        }
+    }
+}
+
+/// Progress channel of the running build
+static PROGRESS: Mutex<Option<Sender<BuildEvent>>> = Mutex::new(None);
+
+/// Sends an event to the progress channel of the running build, if any,
+/// waiting while the channel is full so no event is lost
+pub async fn emit(event: BuildEvent) {
+    let tx = PROGRESS.lock().ok().and_then(|progress| progress.clone());
+    if let Some(tx) = tx {
+        let _ = tx.send(event).await;
+    }
+}
+
+/// A full build: download, extract, patch, rezip, sign and publish
+pub struct BuildPipeline {
+    config: Config,
+    options: BuildOptions,
+    progress_tx: Option<Sender<BuildEvent>>,
+}
+
+impl BuildPipeline {
+    /// A build of `config` with `options`. Its events are sent to
+    /// `progress_tx`, waiting while the channel is full, so the receiver
+    /// must keep reading them until the build returns.
+    pub fn new(
+        config: Config,
+        options: BuildOptions,
+        progress_tx: Option<Sender<BuildEvent>>,
+    ) -> Self {
+        Self {
+            config,
+            options,
+            progress_tx,
+        }
+    }
+
+    /// Runs the build and returns the final ROM, or `None` when the build
+    /// stops before producing one (`--plan`, `--no-rezip`)
+    pub async fn run(self) -> Result<Option<PathBuf>> {
+        if let Ok(mut progress) = PROGRESS.lock() {
+            *progress = self.progress_tx.clone();
+        }
+        let result = self.build().await;
+        if let Ok(Some(rom)) = &result {
+            emit(BuildEvent::Finished { rom: rom.clone() }).await;
+        }
+        if let Ok(mut progress) = PROGRESS.lock() {
+            *progress = None;
+        }
+        result
+    }
+
+    async fn build(self) -> Result<Option<PathBuf>> {
+        let BuildPipeline {
+            mut config,
+            options: args,
+            ..
+        } = self;
+        let _lock = lock::acquire(args.break_lock)?;
+        if let Some(keep_dir) = &args.keep_dir {
+            config.keep_dir = Some(keep_dir.clone());
+        }
+        if args.no_cleanup || args.no_rezip || args.only_step.is_some() || config.keep_dir.is_some()
+        {
+            config.cleanup = false;
+        }
+        if args.offline && config.upload.is_some() {
+            utils::print_info("📴 Skipping upload (--offline)");
+        }
+        if args.no_upload || args.offline {
+            config.upload = None;
+        }
+        if let Some(patch_set) = &args.patch_set {
+            let set = config::load_patch_set(patch_set).context(Failure::Config)?;
+            config.patches =
+                config::apply_patch_set(&config.patches, &set, args.patch_set_mode, |p| {
+                    std::path::absolute(config.resolve_patch_path(p)).unwrap_or_default()
+                });
+        }
+        if let Some(order) = &args.patch_order {
+            config.patches = utils::apply_patch_order(&config.patches, order);
+        }
+        let max_patches = args
+            .max_patches
+            .or(config.max_patch_count)
+            .unwrap_or(config::DEFAULT_MAX_PATCH_COUNT);
+        if config.patches.len() > max_patches {
+            anyhow::bail!(
+                "{} patches configured, which exceeds the limit of {} (raise it with max_patch_count or --max-patches). First patches:\n  {}",
+                config.patches.len(),
+                max_patches,
+                config
+                    .patches
+                    .iter()
+                    .take(20)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("\n  ")
+            );
+        }
+        utils::print_success(&format!(
+            "📱 Device: {} | 🔧 Base ROM: {} | 📦 Version: {} | Android Version: {}",
+            config.device,
+            if config.rom.starts_with("http") {
+                "custom"
+            } else {
+                &config.rom
+            },
+            config.version,
+            config.android_version
+        ));
+        utils::set_hook_env(env::build_env(&config));
+        if let Some(plan_path) = &args.plan {
+            utils::print_section("📝 PLANNING BUILD");
+            let patches = apply_patches(&args, &config, None).await?;
+            let plan = plan::build_plan(&args, &config, &patches)?;
+            let plan_path = PathBuf::from(shellexpand::tilde(plan_path).to_string());
+            plan::write_plan(&plan, &plan_path)?;
+            return Ok(None);
+        }
+        let _ = utils::run_hook(&config.hooks, "pre-run");
+        let streaming = config.streaming_build && !args.no_rezip;
+        // Resuming at a patch continues the patch step of an earlier build
+        let resuming_patches = args.resume || args.resume_from_patch.is_some();
+        let from_step = if resuming_patches {
+            Step::Patch
+        } else {
+            args.only_step.or(args.from_step).unwrap_or(Step::Download)
+        };
+        let keep_dir = config
+            .keep_dir
+            .as_ref()
+            .map(|dir| PathBuf::from(shellexpand::tilde(dir).to_string()));
+        let resume_dir = args
+            .work_dir
+            .as_ref()
+            .map(|dir| PathBuf::from(shellexpand::tilde(dir).to_string()))
+            .or_else(|| {
+                keep_dir
+                    .clone()
+                    .filter(|_| resuming_patches || args.only_step.is_some())
+            });
+        if args.only_step.is_some() {
+            if resume_dir.is_none() {
+                anyhow::bail!("--only-step requires --keep-dir or --work-dir");
+            }
+            if streaming {
+                anyhow::bail!("--only-step is not supported with streaming_build");
+            }
+        }
+        if from_step > Step::Download {
+            let (flag, dirs) = if resuming_patches {
+                ("--resume/--resume-from-patch", "--keep-dir or --work-dir")
+            } else if args.only_step.is_some() {
+                ("--only-step", "--keep-dir or --work-dir")
+            } else {
+                ("--from-step", "--work-dir")
+            };
+            let Some(dir) = &resume_dir else {
+                anyhow::bail!("{} requires {}", flag, dirs);
+            };
+            if streaming {
+                anyhow::bail!("{} is not supported with streaming_build", flag);
+            }
+            steps::validate(dir, from_step)?;
+            if args.only_step.is_none() {
+                utils::print_info(&format!("⏩ Resuming from step: {}", from_step.name()));
+            }
+        }
+
+        let mut downloaded = false;
+        let romzip_path =
+            if let Some(dir) = resume_dir.as_ref().filter(|_| from_step > Step::Download) {
+                steps::downloaded_rom(dir)?
+            } else if args.romzip == ".download" {
+                download::ensure_online(args.offline)?;
+                if args.checksum_file.is_some() {
+                    config.expected_checksum = download::expected_checksum(
+                        &config,
+                        args.checksum_file.as_deref(),
+                        &download::url_filename(&config)?,
+                    )?;
+                }
+                let _ = utils::run_hook(&config.hooks, "pre-download");
+                emit(BuildEvent::Downloading).await;
+                let start = Instant::now();
+                let path = download::download_rom(&config, args.dry_run)
+                    .await
+                    .context(Failure::Download)?;
+                metrics::record("download", start.elapsed());
+                downloaded = true;
+                path
+            } else {
+                let expanded = shellexpand::tilde(&args.romzip);
+                let path = PathBuf::from(expanded.to_string());
+                download::verify_local_rom(&config, &path, args.checksum_file.as_deref()).await?;
+                path
+            };
+
+        let mut tmp_dir = None;
+        let work_path = if streaming {
+            utils::print_info("🌊 Streaming build: the ROM will not be extracted");
+            None
+        } else {
+            let work_path = match (&resume_dir, keep_dir) {
+                (Some(dir), _) => {
+                    fs::create_dir_all(dir).with_context(|| {
+                        format!("Failed to create work dir '{}'", dir.display())
+                    })?;
+                    dir.clone()
+                }
+                (None, Some(dir)) => {
+                    if fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_some()) {
+                        utils::print_warning(&format!(
+                            "⚠️ Keep dir '{}' is not empty, files from an earlier build may remain",
+                            dir.display()
+                        ));
+                    }
+                    fs::create_dir_all(&dir).with_context(|| {
+                        format!("Failed to create keep dir '{}'", dir.display())
+                    })?;
+                    dir
+                }
+                (None, None) => tmp_dir
+                    .insert(create_work_dir(&args, &config)?)
+                    .path()
+                    .to_path_buf(),
+            };
+            utils::print_info(&format!("🗂️  Working directory: {}", work_path.display()));
+            steps::mark_complete(
+                &work_path,
+                Step::Download,
+                &romzip_path
+                    .canonicalize()
+                    .unwrap_or(romzip_path.clone())
+                    .to_string_lossy(),
+                args.dry_run,
+            )?;
+            if stop_after(&args, Step::Download) {
+                return Ok(None);
+            }
+            if from_step <= Step::Extract {
+                let _ = utils::run_hook(&config.hooks, "pre-unzip");
+                emit(BuildEvent::Extracting {
+                    rom: romzip_path.clone(),
+                })
+                .await;
+                let start = Instant::now();
+                unzip::unzip_rom(
+                    &romzip_path,
+                    &work_path,
+                    config.validate_extraction,
+                    args.dry_run,
+                )?;
+                metrics::record("extract", start.elapsed());
+                let _ = utils::run_hook(&config.hooks, "post-unzip");
+                steps::mark_complete(&work_path, Step::Extract, "", args.dry_run)?;
+            }
+            if stop_after(&args, Step::Extract) {
+                return Ok(None);
+            }
+            Some(work_path)
+        };
+        let mut streamed_patches = Vec::new();
+        if from_step <= Step::Patch {
+            utils::print_section("🔧 APPLYING PATCHES");
+            let _ = utils::run_hook(&config.hooks, "pre-patch");
+            streamed_patches = apply_patches(&args, &config, work_path.as_deref()).await?;
+            if let Some(work_path) = &work_path {
+                steps::mark_complete(work_path, Step::Patch, "", args.dry_run)?;
+            }
+            if stop_after(&args, Step::Patch) {
+                return Ok(None);
+            }
+        }
+        let Some(kept_path) = work_path else {
+            if let Some(patch) = streamed_patches.iter().find(|p| {
+                patchmeta::load_patch_meta(p)
+                    .and_then(|m| m.file_conflict_strategy)
+                    .is_some_and(|s| s != "override")
+            }) {
+                anyhow::bail!(
+                    "Patch '{}' sets file_conflict_strategy, which is not supported with streaming_build",
+                    patch.display()
+                );
+            }
+            if let Some(patch) = streamed_patches
+                .iter()
+                .find(|p| p.join(".rommermerge").exists())
+            {
+                anyhow::bail!(
+                    "Patch '{}' has a .rommermerge, which is not supported with streaming_build",
+                    patch.display()
+                );
+            }
+            if let Some(patch) = streamed_patches
+                .iter()
+                .find(|p| p.join(".rommerprop").exists())
+            {
+                anyhow::bail!(
+                    "Patch '{}' has a .rommerprop, which is not supported with streaming_build",
+                    patch.display()
+                );
+            }
+            utils::print_section("✅ PATCHING COMPLETE");
+            let _ = utils::run_hook(&config.hooks, "post-patch");
+            let final_rom_path = finalize::finalize_streamed_rom(
+                &romzip_path,
+                &streamed_patches,
+                &config,
+                args.skip_signing,
+                args.dry_run,
+            )
+            .await?;
+            if downloaded {
+                cleanup_download(&config, &romzip_path, args.dry_run);
+            }
+            return Ok(Some(final_rom_path));
+        };
+        if let Some(tmp_dir) = tmp_dir {
+            let _ = tmp_dir.keep();
+        }
+        utils::print_section("✅ PATCHING COMPLETE");
+        utils::print_success(&format!("📂 Patched ROM: {}", kept_path.display()));
+        let _ = utils::run_hook(&config.hooks, "post-patch");
+        if args.no_rezip {
+            utils::print_info("📂 Skipping rezip and signing (--no-rezip)");
+            return Ok(None);
+        }
+        let final_rom_path = finalize::finalize_rom(
+            &kept_path,
+            &config,
+            from_step > Step::Rezip,
+            args.only_step == Some(Step::Rezip),
+            args.force_rezip,
+            args.skip_signing,
+            args.dry_run,
+        )
+        .await?;
+        if downloaded {
+            cleanup_download(&config, &romzip_path, args.dry_run);
+        }
+        Ok(Some(final_rom_path))
+    }
+}
+
+/// Whether `--only-step` asks to stop once `step` has run
+fn stop_after(args: &BuildOptions, step: Step) -> bool {
+    if args.only_step != Some(step) {
+        return false;
+    }
+    utils::print_success(&format!(
+        "⏹️  Step '{}' complete (--only-step)",
+        step.name()
+    ));
+    true
+}
+
+/// Deletes the downloaded ROM ZIP after a successful build when
+/// `cleanup_downloads` is set. ROMs given with `--romzip` are never deleted.
+/// The ROM is already built by then, so a failed delete only warns.
+fn cleanup_download(config: &Config, romzip_path: &Path, dry_run: bool) {
+    if !config.cleanup_downloads || dry_run || !romzip_path.exists() {
+        return;
+    }
+    match fs::remove_file(romzip_path) {
+        Ok(()) => utils::print_info(&format!(
+            "🗑️  Deleted downloaded ROM {} (cleanup_downloads)",
+            romzip_path.display()
+        )),
+        Err(e) => utils::print_warning(&format!(
+            "Failed to delete '{}': {}",
+            romzip_path.display(),
+            e
+        )),
+    }
+}
+
+/// Applies the configured patches to `work_dir`, or only selects them when
+/// `work_dir` is `None` (streaming build). Returns the selected patches of a
+/// streaming build.
+pub async fn apply_patches(
+    args: &BuildOptions,
+    config: &Config,
+    work_dir: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let mut streamed_patches = Vec::new();
+    let provenance = config.generate_provenance && !args.dry_run;
+    let resuming = args.resume || args.resume_from_patch.is_some();
+    let mut already_applied = Vec::new();
+    let mut first_patch = 0;
+    if let Some(work_dir) = work_dir {
+        if args.resume {
+            already_applied = steps::applied_patches(work_dir)?;
+        }
+        if let Some(name) = &args.resume_from_patch {
+            first_patch = config
+                .patches
+                .iter()
+                .position(|p| utils::patch_matches(p, name))
+                .with_context(|| {
+                    format!("--resume-from-patch: patch '{}' is not configured", name)
+                })?;
+        }
+        if !resuming {
+            steps::reset_applied_patches(work_dir, args.dry_run)?;
+        }
+    }
+    if provenance && !resuming {
+        match work_dir {
+            Some(work_dir) => provenance::reset(work_dir)?,
+            None => utils::print_warning(
+                "generate_provenance is not supported with streaming_build, skipping provenance",
+            ),
+        }
+    }
+    // Identical files are only deduplicated within this build's work dir
+    let copied = utils::CopiedFiles::default();
+    for (i, patch_folder) in config.patches.iter().enumerate() {
+        let patch_path = &config.resolve_patch_path(patch_folder);
+        if !patch_path.exists() {
+            utils::print_warning(&format!("Patch folder '{}' does not exist!", patch_folder));
+            continue;
+        }
+
+        if i < first_patch {
+            utils::print_info(&format!(
+                "⏩ Skipping patch '{}' (before --resume-from-patch)",
+                patch_folder
+            ));
+            continue;
+        }
+        if already_applied.contains(patch_folder) {
+            utils::print_info(&format!(
+                "⏩ Skipping patch '{}' (already applied)",
+                patch_folder
+            ));
+            continue;
+        }
+        if config
+            .disabled_patches
+            .iter()
+            .any(|d| utils::patch_matches(patch_folder, d))
+        {
+            utils::print_info(&format!("🚫 Skipping patch '{}' (disabled)", patch_folder));
+            continue;
+        }
+        let patch_meta = patchmeta::load_patch_meta(patch_path);
+        if let Some(ref tags_filter) = args.tags {
+            if let Some(meta) = &patch_meta {
+                let tags = meta.tags.clone().unwrap_or_default();
+                let matches = tags.iter().any(|t| tags_filter.contains(t));
+                if !matches {
+                    utils::print_info(&format!(
+                        "🚫 Skipping patch '{}' (tag mismatch)",
+                        patch_folder
+                    ));
+                    continue;
+                }
+            } else {
+                utils::print_info(&format!(
+                    "🚫 Skipping patch '{}' (no patch.yaml, no tags)",
+                    patch_folder
+                ));
+                continue;
+            }
+        }
+        if let Some(meta) = &patch_meta
+            && let Some(req) = &meta.requires_android
+            && !utils::android_version_matches(req, config.android_version)
+        {
+            utils::print_info(&format!(
+                "🚫 Skipping patch '{}' (requires Android {}, current is {})",
+                patch_folder, req, config.android_version
+            ));
+            continue;
+        }
+        if let Some(expected) = patch_meta.as_ref().and_then(|m| m.patch_checksum.as_ref()) {
+            let actual = checksum::calculate_directory_checksum(patch_path)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                let msg = format!(
+                    "Checksum mismatch for patch '{}'! Expected: {}, Got: {}",
+                    patch_folder, expected, actual
+                );
+                if args.strict_patch_checksums {
+                    anyhow::bail!(msg);
+                }
+                utils::print_warning(&format!("{}. Skipping patch", msg));
+                continue;
+            }
+            utils::print_success(&format!("🔒 Patch '{}' checksum verified", patch_folder));
+        }
+        if config.verify_patch_signatures {
+            let public_key = config
+                .patch_public_key
+                .as_ref()
+                .context("verify_patch_signatures is set but patch_public_key is not")?;
+            let public_key = PathBuf::from(shellexpand::tilde(public_key).to_string());
+            if let Err(e) = patchsig::verify_patch_signature(patch_path, &public_key) {
+                let msg = format!(
+                    "Signature check failed for patch '{}': {:#}",
+                    patch_folder, e
+                );
+                if args.strict_patch_signatures {
+                    anyhow::bail!(msg);
+                }
+                utils::print_warning(&format!("{}. Skipping patch", msg));
+                continue;
+            }
+            utils::print_success(&format!("🔏 Patch '{}' signature verified", patch_folder));
+        }
+        if let Some(pinned) = patch_meta.as_ref().and_then(|m| m.pinned_commit.as_ref()) {
+            let msg = match pin::pin_mismatch(patch_path, pinned) {
+                Ok(None) => None,
+                Ok(Some(reason)) => Some(format!(
+                    "Patch '{}' was modified since it was pinned at {}: {}",
+                    patch_folder, pinned, reason
+                )),
+                Err(e) => Some(format!(
+                    "Could not verify the pin of patch '{}': {:#}",
+                    patch_folder, e
+                )),
+            };
+            match msg {
+                Some(msg) if args.strict_pins => anyhow::bail!(msg),
+                Some(msg) => utils::print_warning(&msg),
+                None => utils::print_success(&format!(
+                    "📌 Patch '{}' matches its pinned commit",
+                    patch_folder
+                )),
+            }
+        }
+        if let Some(meta) = &patch_meta {
+            utils::print_info(&format!(
+                "[{}/{}] Applying patch: {}{} by {}",
+                i + 1,
+                config.patches.len(),
+                meta.name
+                    .clone()
+                    .unwrap_or_else(|| patch_folder.to_string()),
+                meta.version
+                    .as_ref()
+                    .map(|v| format!(" v{}", v))
+                    .unwrap_or_default(),
+                meta.author.clone().unwrap_or_else(|| "Unknown".to_string()),
+            ));
+            if let Some(desc) = &meta.description {
+                utils::print_info(&format!("📝 {}", desc));
+            }
+        } else {
+            utils::print_info(&format!(
+                "[{}/{}] Applying patch '{}'",
+                i + 1,
+                config.patches.len(),
+                patch_folder
+            ));
+        }
+        if let Some(limit_mb) = args.max_patch_size.or(config.max_patch_size_mb) {
+            let size = utils::dir_size(patch_path);
+            let limit = limit_mb.saturating_mul(1_000_000);
+            if size > limit {
+                anyhow::bail!(
+                    "Patch '{}' exceeds max size limit ({} > {} limit)",
+                    patch_folder,
+                    utils::format_size_mb(size),
+                    utils::format_size_mb(limit)
+                );
+            }
+        }
+        let stats = utils::extension_stats(patch_path);
+        if !stats.is_empty() {
+            utils::print_info(&format!("📊 {}", utils::format_extension_stats(&stats)));
+        }
+        let Some(work_dir) = work_dir else {
+            streamed_patches.push(patch_path.to_path_buf());
+            continue;
+        };
+        if args.apply_only_new && utils::patch_files_match(patch_path, work_dir)? {
+            utils::print_info(&format!(
+                "⏩ Skipping patch '{}' (all files already match)",
+                patch_folder
+            ));
+            continue;
+        }
+        emit(BuildEvent::ApplyingPatch {
+            index: i + 1,
+            total: config.patches.len(),
+            patch: patch_folder.clone(),
+        })
+        .await;
+        let start = Instant::now();
+        let strategy = patch_meta
+            .as_ref()
+            .and_then(|m| m.file_conflict_strategy.clone());
+        let applied = apply_patch_async(
+            patch_path,
+            work_dir,
+            strategy,
+            config,
+            copied.clone(),
+            args.dry_run,
+        );
+        match config.patch_timeout_secs {
+            Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), applied).await {
+                Ok(result) => result?,
+                // The patch thread can't be interrupted and keeps writing to the
+                // work directory, so later patches can't safely be applied
+                Err(_) => anyhow::bail!(
+                    "Patch '{}' timed out after {:.2?} (patch_timeout_secs: {})",
+                    patch_folder,
+                    start.elapsed(),
+                    secs
+                ),
+            },
+            None => applied.await?,
+        }
+        let duration = start.elapsed();
+        metrics::record(&format!("patch {}", patch_folder), duration);
+        if provenance {
+            provenance::record_patch(work_dir, patch_folder, patch_path, config.android_version)?;
+        }
+        steps::record_applied_patch(work_dir, patch_folder, args.dry_run)?;
+        utils::print_info(&format!("⏱️ Patch applied in {:.2?}", duration).to_string());
+    }
+    Ok(streamed_patches)
+}
+
+/// Applies a patch on its own thread, so the build can abort when
+/// `patch_timeout_secs` expires. The thread isn't joined and doesn't keep the
+/// process alive once the build has aborted.
+async fn apply_patch_async(
+    patch_path: &Path,
+    work_dir: &Path,
+    strategy: Option<String>,
+    config: &Config,
+    copied: utils::CopiedFiles,
+    dry_run: bool,
+) -> Result<()> {
+    let (patch_path, work_dir) = (patch_path.to_path_buf(), work_dir.to_path_buf());
+    let config = config.clone();
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(utils::apply_patch(
+            &patch_path,
+            &work_dir,
+            strategy.as_deref(),
+            &config,
+            &copied,
+            dry_run,
+        ));
+    });
+    rx.await.context("Patch thread exited without a result")?
+}
+
+fn create_work_dir(args: &BuildOptions, config: &Config) -> Result<tempfile::TempDir> {
+    match args.temp_dir.as_ref().or(config.temp_dir.as_ref()) {
+        Some(dir) => {
+            let dir = PathBuf::from(shellexpand::tilde(dir).to_string());
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create temp dir '{}'", dir.display()))?;
+            tempfile::Builder::new()
+                .tempdir_in(&dir)
+                .with_context(|| format!("Failed to create temp dir in '{}'", dir.display()))
+        }
+        None => tempdir().context("Failed to create temp dir"),
+    }
+}
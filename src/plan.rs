@@ -0,0 +1,68 @@
+use crate::config::Config;
+use crate::pipeline::BuildOptions;
+use crate::utils;
+use anyhow::Context;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Machine-readable description of what a build would do
+#[derive(Debug, Serialize)]
+pub struct BuildPlan {
+    pub would_download: bool,
+    pub patches: Vec<String>,
+    pub files_to_add: Vec<String>,
+    pub files_to_delete: Vec<String>,
+    pub output: String,
+    pub signing_method: String,
+}
+
+pub fn build_plan(
+    args: &BuildOptions,
+    config: &Config,
+    patches: &[PathBuf],
+) -> anyhow::Result<BuildPlan> {
+    let mut files_to_add = Vec::new();
+    let mut files_to_delete = Vec::new();
+    for patch in patches {
+        for rel in utils::patch_files(patch) {
+            files_to_add.push(rel.to_string_lossy().replace('\\', "/"));
+        }
+        for del_file in [".rommerdel", ".rommerfdel"] {
+            let del_path = patch.join(del_file);
+            if !del_path.exists() {
+                continue;
+            }
+            for item in utils::read_paths(&del_path, config.android_version)? {
+                files_to_delete.push(item.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+    let signing_method = if args.skip_signing || args.no_rezip {
+        "none".to_string()
+    } else {
+        match &config.signing {
+            Some(signing) => signing.method.clone(),
+            None => "test".to_string(),
+        }
+    };
+    Ok(BuildPlan {
+        would_download: args.romzip == ".download",
+        patches: patches
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect(),
+        files_to_add,
+        files_to_delete,
+        output: config.output_filename(),
+        signing_method,
+    })
+}
+
+pub fn write_plan(plan: &BuildPlan, path: &Path) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(plan)?;
+    fs::write(path, json + "\n")
+        .with_context(|| format!("Failed to write build plan '{}'", path.display()))?;
+    utils::print_success(&format!("📝 Build plan written to: {}", path.display()));
+    Ok(())
+}
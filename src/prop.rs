@@ -0,0 +1,116 @@
+use crate::utils;
+use anyhow::Context;
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Property file that `patch from-prop` patches, relative to the ROM root
+const PROP_TARGET: &str = "system/build.prop";
+
+/// Creates a patch that merges the properties of a `key=value` file into the
+/// ROM's `system/build.prop` through `.rommerprop`
+pub fn patch_from_prop(prop_file: &str, patch_name: Option<&str>) -> anyhow::Result<()> {
+    utils::print_section("🧩 CREATING PATCH FROM PROPERTIES");
+    let prop_path = PathBuf::from(shellexpand::tilde(prop_file).to_string());
+    let content = fs::read_to_string(&prop_path)
+        .with_context(|| format!("Failed to read '{}'", prop_path.display()))?;
+    let (properties, invalid) = parse_properties(&content);
+    for line in &invalid {
+        utils::print_warning(&format!("Skipping invalid property line: {}", line));
+    }
+    if properties.is_empty() {
+        anyhow::bail!("'{}' has no key=value properties", prop_path.display());
+    }
+    let name = match patch_name {
+        Some(name) => name.to_string(),
+        None => prop_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .context("Cannot derive a patch name, use --patch-name")?,
+    };
+    let patch_path = PathBuf::from(&name);
+    if patch_path.exists() {
+        anyhow::bail!("'{}' already exists", patch_path.display());
+    }
+
+    let target = patch_path.join(PROP_TARGET);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create '{}'", parent.display()))?;
+    }
+    let mut props = String::new();
+    for (key, value) in &properties {
+        props.push_str(&format!("{}={}\n", key, value));
+    }
+    write(&target, &props)?;
+    write(
+        &patch_path.join(".rommerprop"),
+        &format!("{}\n", PROP_TARGET),
+    )?;
+    let mut manifest = Mapping::new();
+    manifest.insert("name".into(), name.clone().into());
+    manifest.insert("version".into(), "1.0.0".into());
+    manifest.insert(
+        "description".into(),
+        format!("Sets {} properties in {}", properties.len(), PROP_TARGET).into(),
+    );
+    write(
+        &patch_path.join("patch.yaml"),
+        &serde_yaml::to_string(&Value::Mapping(manifest))?,
+    )?;
+
+    println!("{}/", patch_path.display());
+    println!("├── .rommerprop");
+    println!("├── patch.yaml");
+    println!("└── {} ({} properties)", PROP_TARGET, properties.len());
+    utils::print_success(&format!("📁 Created patch '{}'", patch_path.display()));
+    Ok(())
+}
+
+fn write(path: &Path, content: &str) -> anyhow::Result<()> {
+    fs::write(path, content).with_context(|| format!("Failed to write '{}'", path.display()))
+}
+
+/// The `key=value` pairs of a property file in order, a repeated key keeping
+/// its last value, and the lines that are neither properties, comments nor
+/// blank
+fn parse_properties(content: &str) -> (Vec<(String, String)>, Vec<String>) {
+    let mut properties: Vec<(String, String)> = Vec::new();
+    let mut invalid = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                let (key, value) = (key.trim().to_string(), value.trim().to_string());
+                match properties.iter_mut().find(|(k, _)| *k == key) {
+                    Some(existing) => existing.1 = value,
+                    None => properties.push((key, value)),
+                }
+            }
+            _ => invalid.push(line.to_string()),
+        }
+    }
+    (properties, invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_properties() {
+        let content = "# spoof\nro.product.model=Pixel 8\n\nro.debuggable = 0\nnot a property\nro.product.model=Pixel 9\n=orphan\n";
+        let (properties, invalid) = parse_properties(content);
+        assert_eq!(
+            properties,
+            [
+                ("ro.product.model".to_string(), "Pixel 9".to_string()),
+                ("ro.debuggable".to_string(), "0".to_string()),
+            ]
+        );
+        assert_eq!(invalid, ["not a property", "=orphan"]);
+    }
+}
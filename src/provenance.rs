@@ -0,0 +1,102 @@
+use crate::{checksum, utils};
+use anyhow::Context;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// JSON Lines file in the root of the work directory, zipped with the ROM
+pub const PROVENANCE_FILE: &str = "rommer-provenance.jsonl";
+
+/// What one patch added to and deleted from the ROM
+#[derive(Debug, Serialize)]
+struct ProvenanceRecord {
+    patch: String,
+    patch_tree_hash: String,
+    files_added: Vec<String>,
+    files_deleted: Vec<String>,
+    timestamp_utc: String,
+}
+
+/// Removes the provenance left by an earlier run in the same work directory
+pub fn reset(work_dir: &Path) -> anyhow::Result<()> {
+    let path = work_dir.join(PROVENANCE_FILE);
+    if path.exists() {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Appends the record of an applied patch to the work directory's provenance
+pub fn record_patch(
+    work_dir: &Path,
+    patch_folder: &str,
+    patch_path: &Path,
+    android_version: u32,
+) -> anyhow::Result<()> {
+    let mut files_deleted = Vec::new();
+    for del_file in [".rommerdel", ".rommerfdel"] {
+        let del_path = patch_path.join(del_file);
+        if del_path.exists() {
+            files_deleted.extend(
+                utils::read_paths(&del_path, android_version)?
+                    .iter()
+                    .map(|p| crate::rezip::zip_entry_name(p)),
+            );
+        }
+    }
+    let record = ProvenanceRecord {
+        patch: patch_folder.to_string(),
+        patch_tree_hash: checksum::calculate_directory_checksum(patch_path)?,
+        files_added: utils::patch_files(patch_path)
+            .iter()
+            .map(|p| crate::rezip::zip_entry_name(p))
+            .collect(),
+        files_deleted,
+        timestamp_utc: utils::rfc3339(utils::unix_now()),
+    };
+    let path = work_dir.join(PROVENANCE_FILE);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open '{}'", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_record_patch() -> anyhow::Result<()> {
+        let patch = tempdir()?;
+        fs::create_dir_all(patch.path().join("system/etc"))?;
+        fs::write(patch.path().join("system/etc/hosts"), "127.0.0.1 localhost")?;
+        fs::write(patch.path().join(".rommerfdel"), "system/app/Browser.apk\n")?;
+        let work = tempdir()?;
+        record_patch(work.path(), "hosts", patch.path(), 15)?;
+        record_patch(work.path(), "hosts", patch.path(), 15)?;
+
+        let content = fs::read_to_string(work.path().join(PROVENANCE_FILE))?;
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["patch"], "hosts");
+        assert_eq!(
+            lines[0]["files_added"],
+            serde_json::json!(["system/etc/hosts"])
+        );
+        assert_eq!(
+            lines[0]["files_deleted"],
+            serde_json::json!(["system/app/Browser.apk"])
+        );
+        assert_eq!(lines[0]["patch_tree_hash"].as_str().map(str::len), Some(64));
+        Ok(())
+    }
+}
@@ -1,20 +1,42 @@
+use crate::config::Config;
 use anyhow::Context;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, ProgressStyle};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs::File;
-use std::path::Path;
+use std::io::{Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 use zip::write::FileOptions;
-use zip::{CompressionMethod, ZipWriter};
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
-pub fn rezip_rom(source_dir: &Path, output_path: &Path, dry_run: bool) -> anyhow::Result<()> {
+/// Where an entry of a streamed build comes from
+pub enum Source {
+    /// Entry of the base ROM, copied without recompressing
+    ZipEntry(usize),
+    /// File from a patch folder
+    PatchFile(PathBuf),
+    /// Directory added by a patch
+    PatchDir,
+}
+
+/// Files up to this size are deflated in memory first, to store them
+/// uncompressed when deflating doesn't make them smaller
+const TRIAL_DEFLATE_LIMIT: u64 = 64 * 1024 * 1024;
+
+pub fn rezip_rom(
+    source_dir: &Path,
+    output_path: &Path,
+    config: &Config,
+    dry_run: bool,
+) -> anyhow::Result<()> {
     crate::utils::print_section("📦 CREATING FLASHABLE ZIP");
     if dry_run {
         crate::utils::print_info(&format!(
             "🔍 DRY RUN: Would create zip file: {}",
             output_path.display()
         ));
-        let _walker = WalkDir::new(source_dir).into_iter();
-        let total_files = WalkDir::new(source_dir).into_iter().count();
+        let total_files = work_dir_entries(source_dir).count();
         crate::utils::print_info(&format!("🔍 DRY RUN: Would compress {} files", total_files));
         return Ok(());
     }
@@ -22,9 +44,9 @@ pub fn rezip_rom(source_dir: &Path, output_path: &Path, dry_run: bool) -> anyhow
     let file = File::create(output_path)
         .with_context(|| format!("Failed to create output zip '{}'", output_path.display()))?;
     let mut zip = ZipWriter::new(file);
-    let walker = WalkDir::new(source_dir).into_iter();
-    let total_files = WalkDir::new(source_dir).into_iter().count();
-    let pb = ProgressBar::new(total_files as u64);
+    let walker = work_dir_entries(source_dir);
+    let total_files = work_dir_entries(source_dir).count();
+    let pb = crate::utils::progress_bar(total_files as u64, "Zipping");
     pb.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -37,15 +59,19 @@ pub fn rezip_rom(source_dir: &Path, output_path: &Path, dry_run: bool) -> anyhow
         .compression_method(CompressionMethod::Deflated)
         .unix_permissions(0o755);
 
+    let mut input_size = 0u64;
+    let mut largest: Vec<(u64, String)> = Vec::new();
     for entry in walker.filter_map(|e| e.ok()) {
         let path = entry.path();
         let name = path.strip_prefix(source_dir)?;
+        let entry_name = zip_entry_name(name);
         if path.is_file() {
-            zip.start_file(name.to_string_lossy(), options)?;
-            let mut f = File::open(path)?;
-            std::io::copy(&mut f, &mut zip)?;
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            input_size += size;
+            largest.push((size, entry_name.clone()));
+            add_file(&mut zip, &entry_name, path, size, config)?;
         } else if !name.as_os_str().is_empty() {
-            zip.add_directory(name.to_string_lossy(), options)?;
+            zip.add_directory(entry_name, options)?;
         }
         pb.inc(1);
     }
@@ -53,5 +79,364 @@ pub fn rezip_rom(source_dir: &Path, output_path: &Path, dry_run: bool) -> anyhow
     zip.finish()?;
     pb.finish_with_message("Rezip complete!");
     crate::utils::print_success(&format!("📦 Created: {}", output_path.display()));
+    print_size_stats(output_path, input_size, largest)?;
+    Ok(())
+}
+
+/// Adds a file to the output ZIP. Files with an already compressed extension
+/// are stored as they are, and so is anything deflate would make larger.
+fn add_file<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    name: &str,
+    path: &Path,
+    size: u64,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let options = FileOptions::<()>::default().unix_permissions(0o755);
+    let stored = options.compression_method(CompressionMethod::Stored);
+    let deflated = options.compression_method(CompressionMethod::Deflated);
+    if is_already_compressed(name, &config.already_compressed_extensions) {
+        zip.start_file(name, stored)?;
+        std::io::copy(&mut File::open(path)?, zip)?;
+        return Ok(());
+    }
+    if size > TRIAL_DEFLATE_LIMIT {
+        zip.start_file(name, deflated)?;
+        std::io::copy(&mut File::open(path)?, zip)?;
+        return Ok(());
+    }
+
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let mut trial = ZipWriter::new(Cursor::new(Vec::new()));
+    trial.start_file(name, deflated)?;
+    trial.write_all(&data)?;
+    let mut trial = trial.finish_into_readable()?;
+    let entry = trial.by_index_raw(0)?;
+    if entry.compressed_size() < entry.size() {
+        zip.raw_copy_file(entry)?;
+    } else {
+        zip.start_file(name, stored)?;
+        zip.write_all(&data)?;
+    }
+    Ok(())
+}
+
+/// Whether an entry name ends with one of the configured extensions, ignoring case
+fn is_already_compressed(name: &str, extensions: &[String]) -> bool {
+    let name = name.to_ascii_lowercase();
+    extensions.iter().any(|ext| {
+        let ext = ext.to_ascii_lowercase();
+        let ext = ext.trim_start_matches('.');
+        name.rsplit_once('.').is_some_and(|(_, e)| e == ext)
+    })
+}
+
+/// Prints the output size against the uncompressed input, and the largest files
+fn print_size_stats(
+    output_path: &Path,
+    input_size: u64,
+    mut largest: Vec<(u64, String)>,
+) -> anyhow::Result<()> {
+    let output_size = std::fs::metadata(output_path)?.len();
+    let saved = if input_size > 0 {
+        100.0 - output_size as f64 / input_size as f64 * 100.0
+    } else {
+        0.0
+    };
+    crate::utils::print_info(&format!(
+        "📊 Compressed: {} (input {}, ratio {:.0}%)",
+        HumanBytes(output_size),
+        HumanBytes(input_size),
+        saved.max(0.0)
+    ));
+    largest.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+    for (size, name) in largest.iter().take(5) {
+        crate::utils::print_info(&format!(
+            "   {:>10}  {}",
+            HumanBytes(*size).to_string(),
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Builds the output ZIP straight from the base ROM and the patch folders,
+/// without extracting the ROM to disk. See [`resolve_entries`].
+pub fn stream_rezip(
+    base_zip: &Path,
+    patches: &[PathBuf],
+    output_path: &Path,
+    config: &Config,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    crate::utils::print_section("📦 STREAMING FLASHABLE ZIP");
+    let file = File::open(base_zip)
+        .with_context(|| format!("Failed to open zip file '{}'", base_zip.display()))?;
+    let mut archive = ZipArchive::new(file).context("Failed to read zip archive")?;
+    let (order, entries) = resolve_entries(&archive, patches, config)?;
+
+    if dry_run {
+        crate::utils::print_info(&format!(
+            "🔍 DRY RUN: Would stream {} entries into: {}",
+            order.len(),
+            output_path.display()
+        ));
+        return Ok(());
+    }
+
+    let file = File::create(output_path)
+        .with_context(|| format!("Failed to create output zip '{}'", output_path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let pb = crate::utils::progress_bar(order.len() as u64, "Zipping");
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files",
+            )?
+            .progress_chars("█▉▊▋▌▍▎▏  "),
+    );
+    let options = FileOptions::<()>::default()
+        .compression_method(CompressionMethod::Deflated)
+        .unix_permissions(0o755);
+    let mut input_size = 0u64;
+    let mut largest: Vec<(u64, String)> = Vec::new();
+    for name in &order {
+        match &entries[name] {
+            Source::ZipEntry(index) => {
+                let entry = archive.by_index_raw(*index)?;
+                if entry.is_file() {
+                    input_size += entry.size();
+                    largest.push((entry.size(), name.clone()));
+                }
+                zip.raw_copy_file(entry)?
+            }
+            Source::PatchFile(path) => {
+                let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+                input_size += size;
+                largest.push((size, name.clone()));
+                add_file(&mut zip, name, path, size, config)?;
+            }
+            Source::PatchDir => zip.add_directory(name.as_str(), options)?,
+        }
+        pb.inc(1);
+    }
+    zip.finish()?;
+    pb.finish_with_message("Rezip complete!");
+    crate::utils::print_success(&format!("📦 Created: {}", output_path.display()));
+    print_size_stats(output_path, input_size, largest)?;
     Ok(())
 }
+
+/// Resolves the entries of the output ZIP from the base ROM and the patch
+/// folders, in output order. Patches are applied in order: their files
+/// replace ROM entries, then their `.rommerdel`/`.rommerfdel` entries (and
+/// the entries their glob patterns match) are removed, just like a regular
+/// build.
+pub fn resolve_entries<R: std::io::Read + std::io::Seek>(
+    archive: &ZipArchive<R>,
+    patches: &[PathBuf],
+    config: &Config,
+) -> anyhow::Result<(Vec<String>, HashMap<String, Source>)> {
+    let mut order: Vec<String> = Vec::new();
+    let mut entries: HashMap<String, Source> = HashMap::new();
+    for index in 0..archive.len() {
+        if let Some(name) = archive.name_for_index(index) {
+            order.push(name.to_string());
+            entries.insert(name.to_string(), Source::ZipEntry(index));
+        }
+    }
+
+    let android_version = config.android_version;
+    for patch_path in patches {
+        let renames = crate::utils::read_copy_renames(patch_path, android_version)?;
+        let skipped = crate::utils::conditional_skips(patch_path, config)?;
+        for entry in WalkDir::new(patch_path).min_depth(1).into_iter().flatten() {
+            let rel = zip_entry_name(entry.path().strip_prefix(patch_path)?);
+            if crate::utils::PATCH_CONTROL_FILES.contains(&rel.as_str())
+                || renames.iter().any(|(src, _)| zip_entry_name(src) == rel)
+                || skipped.iter().any(|file| zip_entry_name(file) == rel)
+            {
+                continue;
+            }
+            if entry.file_type().is_dir() {
+                let name = format!("{}/", rel);
+                if let Entry::Vacant(e) = entries.entry(name) {
+                    order.push(e.key().clone());
+                    e.insert(Source::PatchDir);
+                }
+            } else if entry.file_name() != "patch.yaml" {
+                if !entries.contains_key(&rel) {
+                    order.push(rel.clone());
+                }
+                entries.insert(rel, Source::PatchFile(entry.into_path()));
+            }
+        }
+        for (src, dst) in renames {
+            let name = zip_entry_name(&dst);
+            if !entries.contains_key(&name) {
+                order.push(name.clone());
+            }
+            entries.insert(name, Source::PatchFile(patch_path.join(src)));
+        }
+        let rommerdel = patch_path.join(".rommerdel");
+        if rommerdel.exists() {
+            for item in crate::utils::read_paths(&rommerdel, android_version)? {
+                let item = zip_entry_name(&item);
+                let item = item.trim_end_matches('/');
+                if crate::globdel::is_glob(item) {
+                    // Drop the entries under any directory the pattern matches
+                    let regex = crate::globdel::glob_regex(item)?;
+                    entries.retain(|name, _| {
+                        !name
+                            .match_indices('/')
+                            .any(|(i, _)| regex.is_match(&name[..i]))
+                    });
+                } else {
+                    let prefix = format!("{}/", item);
+                    entries.retain(|name, _| !name.starts_with(&prefix));
+                }
+            }
+        }
+        let rommerfdel = patch_path.join(".rommerfdel");
+        if rommerfdel.exists() {
+            for item in crate::utils::read_paths(&rommerfdel, android_version)? {
+                let item = zip_entry_name(&item);
+                if crate::globdel::is_glob(&item) {
+                    let regex = crate::globdel::glob_regex(&item)?;
+                    entries.retain(|name, _| name.ends_with('/') || !regex.is_match(name));
+                } else {
+                    entries.remove(&item);
+                }
+            }
+        }
+    }
+    order.retain(|name| entries.contains_key(name));
+    Ok((order, entries))
+}
+
+/// Walks the work directory, leaving out ROMMER's own step markers
+fn work_dir_entries(source_dir: &Path) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> {
+    WalkDir::new(source_dir).into_iter().filter_entry(|e| {
+        e.file_name() != crate::steps::MARKER_DIR
+            && e.file_name() != crate::steps::APPLIED_PATCHES_FILE
+    })
+}
+
+/// ZIP entry names must use forward slashes, whatever the host separator is
+pub fn zip_entry_name(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_rezip_uses_forward_slashes() -> anyhow::Result<()> {
+        let source = tempdir()?;
+        let nested = source.path().join("system").join("etc").join("permissions");
+        fs::create_dir_all(&nested)?;
+        fs::write(nested.join("privapp.xml"), "<permissions/>")?;
+        fs::write(source.path().join("system").join("build.prop"), "ro.test=1")?;
+        let output = tempdir()?;
+        let zip_path = output.path().join("out.zip");
+        rezip_rom(
+            source.path(),
+            &zip_path,
+            &crate::config::test_config(),
+            false,
+        )?;
+
+        let archive = ZipArchive::new(File::open(&zip_path)?)?;
+        let names: Vec<&str> = archive.file_names().collect();
+        assert!(names.contains(&"system/etc/permissions/privapp.xml"));
+        assert!(names.contains(&"system/build.prop"));
+        assert!(names.iter().all(|name| !name.contains('\\')));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rezip_stores_incompressible_files() -> anyhow::Result<()> {
+        let source = tempdir()?;
+        fs::write(source.path().join("boot.png"), "a".repeat(4096))?;
+        fs::write(source.path().join("build.prop"), "a".repeat(4096))?;
+        fs::write(source.path().join("tiny.txt"), "a")?;
+        let output = tempdir()?;
+        let zip_path = output.path().join("out.zip");
+        rezip_rom(
+            source.path(),
+            &zip_path,
+            &crate::config::test_config(),
+            false,
+        )?;
+
+        let mut archive = ZipArchive::new(File::open(&zip_path)?)?;
+        let mut method = |name: &str| -> anyhow::Result<CompressionMethod> {
+            Ok(archive.by_name(name)?.compression())
+        };
+        assert_eq!(method("boot.png")?, CompressionMethod::Stored);
+        assert_eq!(method("build.prop")?, CompressionMethod::Deflated);
+        assert_eq!(method("tiny.txt")?, CompressionMethod::Stored);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_rezip_applies_patches() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let base = dir.path().join("base.zip");
+        let mut zip = ZipWriter::new(File::create(&base)?);
+        let options = FileOptions::<()>::default();
+        for (name, content) in [
+            ("system/build.prop", "ro.test=1"),
+            ("system/app/Foo/Foo.apk", "apk"),
+            ("system/etc/old.xml", "<old/>"),
+            (
+                "META-INF/com/google/android/updater-script",
+                "ui_print(\"\");",
+            ),
+        ] {
+            zip.start_file(name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.finish()?;
+
+        let patch = dir.path().join("patch");
+        fs::create_dir_all(patch.join("system/etc"))?;
+        fs::write(patch.join("patch.yaml"), "name: test")?;
+        fs::write(patch.join(".rommerdel"), "system/app/Foo\n")?;
+        fs::write(patch.join(".rommerfdel"), "system/etc/old.xml\n")?;
+        fs::write(patch.join("system/build.prop"), "ro.test=2")?;
+        fs::write(patch.join("system/etc/new.xml"), "<new/>")?;
+
+        let output = dir.path().join("out.zip");
+        stream_rezip(
+            &base,
+            &[patch],
+            &output,
+            &crate::config::test_config(),
+            false,
+        )?;
+
+        let mut archive = ZipArchive::new(File::open(&output)?)?;
+        let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "META-INF/com/google/android/updater-script",
+                "system/",
+                "system/build.prop",
+                "system/etc/",
+                "system/etc/new.xml",
+            ]
+        );
+        let mut prop = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("system/build.prop")?, &mut prop)?;
+        assert_eq!(prop, "ro.test=2");
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use crate::config::Config;
+use crate::patchmeta::{self, PatchMeta};
+use crate::utils;
+use std::io::IsTerminal;
+
+/// `patch.yaml` fields searched by `patch search`
+const SEARCH_FIELDS: [&str; 4] = ["name", "description", "tags", "author"];
+
+/// Prints the configured patches whose metadata contains `query`, ignoring
+/// case, in every searched field or only in `field`. Fails when nothing
+/// matches.
+pub fn search_patches(config: &Config, query: &str, field: Option<&str>) -> anyhow::Result<()> {
+    let highlight = std::io::stdout().is_terminal() && !utils::no_color();
+    let mut matches = 0;
+    for patch_folder in &config.patches {
+        let Some(meta) = patchmeta::load_patch_meta(config.resolve_patch_path(patch_folder)) else {
+            continue;
+        };
+        let hits: Vec<(&str, String)> = SEARCH_FIELDS
+            .iter()
+            .filter(|name| field.is_none_or(|f| f == **name))
+            .filter_map(|name| {
+                let value = field_value(&meta, name)?;
+                let marked = mark(&value, query, highlight)?;
+                Some((*name, marked))
+            })
+            .collect();
+        if hits.is_empty() {
+            continue;
+        }
+        matches += 1;
+        println!(
+            "📦 {} ({})",
+            patch_folder,
+            meta.name.as_deref().unwrap_or("no name")
+        );
+        for (name, value) in hits {
+            println!("   {}: {}", name, value);
+        }
+    }
+    if matches == 0 {
+        anyhow::bail!("No patches match '{}'", query);
+    }
+    utils::print_success(&format!("{} patch(es) match '{}'", matches, query));
+    Ok(())
+}
+
+fn field_value(meta: &PatchMeta, field: &str) -> Option<String> {
+    match field {
+        "name" => meta.name.clone(),
+        "description" => meta.description.clone(),
+        "tags" => meta.tags.as_ref().map(|tags| tags.join(", ")),
+        "author" => meta.author.clone(),
+        _ => None,
+    }
+}
+
+/// `value` with every case-insensitive occurrence of `query` highlighted,
+/// or `None` when it doesn't occur
+fn mark(value: &str, query: &str, highlight: bool) -> Option<String> {
+    let lower = value.to_lowercase();
+    let needle = query.to_lowercase();
+    if needle.is_empty() || !lower.contains(&needle) {
+        return None;
+    }
+    // Lowercasing can change byte lengths; fall back to plain output then
+    if !highlight || lower.len() != value.len() {
+        return Some(value.to_string());
+    }
+    let mut marked = String::new();
+    let mut rest = 0;
+    for (start, _) in lower.match_indices(&needle) {
+        let end = start + needle.len();
+        marked.push_str(&value[rest..start]);
+        marked.push_str(&format!("\x1b[1;33m{}\x1b[0m", &value[start..end]));
+        rest = end;
+    }
+    marked.push_str(&value[rest..]);
+    Some(marked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark() {
+        assert_eq!(mark("Remove Google apps", "xyz", true), None);
+        assert_eq!(
+            mark("Remove Google apps", "GOOGLE", false).as_deref(),
+            Some("Remove Google apps")
+        );
+        assert_eq!(
+            mark("go Google", "go", true).as_deref(),
+            Some("\x1b[1;33mgo\x1b[0m \x1b[1;33mGo\x1b[0mogle")
+        );
+    }
+}
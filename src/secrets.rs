@@ -0,0 +1,212 @@
+use crate::config::SigningConfig;
+use crate::utils;
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::num::NonZeroU32;
+
+/// Environment variable holding the key that decrypts `*_encrypted` fields,
+/// unless `signing.secret_key_env` names another one
+pub const SECRET_KEY_ENV: &str = "ROMMER_SECRET_KEY";
+/// Length of the random salt stored in front of each encrypted value
+const SALT_LEN: usize = 16;
+/// PBKDF2-HMAC-SHA256 rounds, as recommended by OWASP (fewer in tests, which
+/// run unoptimized)
+const PBKDF2_ITERATIONS: NonZeroU32 =
+    NonZeroU32::new(if cfg!(test) { 1_000 } else { 600_000 }).unwrap();
+
+/// Password fields of the signing config that `config encrypt-secrets` encrypts
+const PASSWORD_FIELDS: [&str; 2] = ["keystore_password", "key_password"];
+
+/// AES-256-GCM key derived from the secret and `salt` with PBKDF2, so any
+/// passphrase can be used without making it cheap to guess
+fn cipher_key(secret: &str, salt: &[u8]) -> anyhow::Result<LessSafeKey> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PBKDF2_ITERATIONS,
+        salt,
+        secret.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&AES_256_GCM, &key)
+        .map_err(|_| anyhow::anyhow!("Failed to create encryption key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypts `plaintext` with a random salt and nonce, returning base64 of the
+/// salt, the nonce, then the ciphertext and tag
+pub fn encrypt(secret: &str, plaintext: &str) -> anyhow::Result<String> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    let rng = SystemRandom::new();
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| anyhow::anyhow!("Failed to generate a salt and nonce"))?;
+    let mut data = plaintext.as_bytes().to_vec();
+    cipher_key(secret, &salt)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt"))?;
+    Ok(STANDARD.encode([salt.as_slice(), &nonce, &data].concat()))
+}
+
+/// Decrypts a value written by [`encrypt`]
+pub fn decrypt(secret: &str, encoded: &str) -> anyhow::Result<String> {
+    let data = STANDARD
+        .decode(encoded.trim())
+        .context("Encrypted value is not valid base64")?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        anyhow::bail!("Encrypted value is too short");
+    }
+    let (salt, data) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| anyhow::anyhow!("Encrypted value has an invalid nonce"))?;
+    let mut ciphertext = ciphertext.to_vec();
+    let plaintext = cipher_key(secret, salt)?
+        .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt, wrong key or corrupted value"))?;
+    String::from_utf8(plaintext.to_vec()).context("Decrypted value is not UTF-8")
+}
+
+/// Replaces every password field in a config file with an `_encrypted` field
+/// holding its value encrypted with the key in the environment variable
+/// `key_env`, which is recorded as `secret_key_env` unless it is the default.
+/// Comments and other lines are kept.
+pub fn encrypt_config_secrets(config_path: &str, key_env: &str) -> anyhow::Result<()> {
+    let secret = std::env::var(key_env)
+        .with_context(|| format!("Environment variable '{}' is not set", key_env))?;
+    if secret.is_empty() {
+        anyhow::bail!("Environment variable '{}' is empty", key_env);
+    }
+    let content = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read config file '{}'", config_path))?;
+    let (updated, fields) = encrypt_lines(&content, &secret, key_env)?;
+    if fields.is_empty() {
+        anyhow::bail!("'{}' has no passwords to encrypt", config_path);
+    }
+    fs::write(config_path, updated)
+        .with_context(|| format!("Failed to write config file '{}'", config_path))?;
+    for field in &fields {
+        println!("🔐 {} -> {}_encrypted", field, field);
+    }
+    utils::print_success(&format!(
+        "Encrypted {} password(s) in {}. Set {} to the same key to build",
+        fields.len(),
+        config_path,
+        key_env
+    ));
+    Ok(())
+}
+
+/// `content` with each non-empty `<field>: <password>` line replaced by its
+/// encrypted form, and the names of the replaced fields. A `secret_key_env`
+/// line is added to each mapping with a replaced field when `key_env` is not
+/// the default and the file doesn't set one yet.
+fn encrypt_lines(
+    content: &str,
+    secret: &str,
+    key_env: &str,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let record_key_env = key_env != SECRET_KEY_ENV && !content.contains("secret_key_env:");
+    let mut updated = String::new();
+    let mut fields = Vec::new();
+    // Indent of the mapping `secret_key_env` was last added to
+    let mut recorded_at: Option<usize> = None;
+    for line in content.split_inclusive('\n') {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        if !line.trim().is_empty() && recorded_at.is_some_and(|i| indent.len() < i) {
+            recorded_at = None;
+        }
+        let password = PASSWORD_FIELDS.iter().find_map(|field| {
+            let value = line.trim_start().strip_prefix(field)?.strip_prefix(':')?;
+            match serde_yaml::from_str(value).ok()? {
+                serde_yaml::Value::String(s) if !s.is_empty() => Some((*field, s)),
+                serde_yaml::Value::Number(n) => Some((*field, n.to_string())),
+                _ => None,
+            }
+        });
+        match password {
+            Some((field, password)) => {
+                if record_key_env && recorded_at.is_none() {
+                    updated.push_str(&format!("{}secret_key_env: {}\n", indent, key_env));
+                    recorded_at = Some(indent.len());
+                }
+                let newline = if line.ends_with('\n') { "\n" } else { "" };
+                updated.push_str(&format!(
+                    "{}{}_encrypted: \"{}\"{}",
+                    indent,
+                    field,
+                    encrypt(secret, &password)?,
+                    newline
+                ));
+                fields.push(field.to_string());
+            }
+            None => updated.push_str(line),
+        }
+    }
+    Ok((updated, fields))
+}
+
+/// The signing config with its `*_encrypted` passwords decrypted with the
+/// key in `secret_key_env` (`ROMMER_SECRET_KEY` by default). Unchanged when
+/// nothing is encrypted.
+pub fn decrypt_signing(signing: &SigningConfig) -> anyhow::Result<SigningConfig> {
+    let mut signing = signing.clone();
+    let keystore_password = signing.keystore_password_encrypted.take();
+    let key_password = signing.key_password_encrypted.take();
+    if keystore_password.is_none() && key_password.is_none() {
+        return Ok(signing);
+    }
+    let key_env = signing.secret_key_env.as_deref().unwrap_or(SECRET_KEY_ENV);
+    let secret = std::env::var(key_env).with_context(|| {
+        format!(
+            "ROMMER.yaml has encrypted passwords, set {} to decrypt them",
+            key_env
+        )
+    })?;
+    if let Some(value) = keystore_password {
+        signing.keystore_password = decrypt(&secret, &value)
+            .context("Failed to decrypt signing.keystore_password_encrypted")?;
+    }
+    if let Some(value) = key_password {
+        signing.key_password =
+            decrypt(&secret, &value).context("Failed to decrypt signing.key_password_encrypted")?;
+    }
+    Ok(signing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_lines_round_trip() -> anyhow::Result<()> {
+        let content = "signing:\n  method: apksigner\n  keystore_password: \"hunter2\" # old\n  key_password: ''\n";
+        let (updated, fields) = encrypt_lines(content, "passphrase", SECRET_KEY_ENV)?;
+        assert_eq!(fields, ["keystore_password"]);
+        assert!(updated.contains("  key_password: ''\n"));
+        assert!(!updated.contains("secret_key_env"));
+        let value: serde_yaml::Value = serde_yaml::from_str(&updated)?;
+        let encrypted = value["signing"]["keystore_password_encrypted"]
+            .as_str()
+            .unwrap();
+        assert_eq!(decrypt("passphrase", encrypted)?, "hunter2");
+        assert!(decrypt("wrong", encrypted).is_err());
+
+        let content = "signing:\n  keystore_password: a\n  key_password: b\nprofiles:\n  release:\n    signing:\n      key_password: c\n";
+        let (updated, _) = encrypt_lines(content, "passphrase", "MY_ROM_KEY")?;
+        let value: serde_yaml::Value = serde_yaml::from_str(&updated)?;
+        assert_eq!(value["signing"]["secret_key_env"], "MY_ROM_KEY");
+        assert_eq!(
+            value["profiles"]["release"]["signing"]["secret_key_env"],
+            "MY_ROM_KEY"
+        );
+        assert_eq!(updated.matches("secret_key_env").count(), 2);
+        Ok(())
+    }
+}
@@ -1,18 +1,23 @@
-use crate::args::Args;
 use crate::config::{Config, SigningConfig};
 use anyhow::Context;
-use clap::Parser;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-pub async fn sign_rom(zip_path: &Path, config: &Config, dry_run: bool) -> anyhow::Result<()> {
-    let args = Args::parse();
+pub async fn sign_rom(
+    zip_path: &Path,
+    config: &Config,
+    skip_signing: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
     crate::utils::print_section("✍️  SIGNING ROM");
-    if args.skip_signing {
+    if !skip_signing {
         if let Some(signing_config) = &config.signing {
+            let signing_config = &crate::secrets::decrypt_signing(signing_config)?;
             match signing_config.method.as_str() {
                 "apksigner" => sign_with_apksigner(zip_path, signing_config, dry_run).await,
                 "jarsigner" => sign_with_jarsigner(zip_path, signing_config, dry_run).await,
+                "pkcs11" => sign_with_pkcs11(zip_path, signing_config, dry_run).await,
                 "custom" => sign_with_custom_command(zip_path, signing_config, dry_run).await,
                 _ => {
                     crate::utils::print_warning("Unknown signing method, skipping signature");
@@ -28,6 +33,111 @@ pub async fn sign_rom(zip_path: &Path, config: &Config, dry_run: bool) -> anyhow
     }
 }
 
+/// Signature schemes and signer found on a verified ZIP
+#[derive(Debug, Default, PartialEq)]
+pub struct SignatureReport {
+    pub schemes: Vec<String>,
+    pub subject: Option<String>,
+}
+
+/// Verifies the signature of a signed ZIP with `apksigner` or `jarsigner`
+/// and prints its signature schemes and signing certificate subject
+pub fn verify_signature(zip_path: &Path, method: &str) -> anyhow::Result<()> {
+    crate::utils::print_section("🔏 VERIFYING SIGNATURE");
+    if !zip_path.is_file() {
+        anyhow::bail!("'{}' does not exist", zip_path.display());
+    }
+    let mut command = match method {
+        "apksigner" => {
+            let mut command = Command::new("apksigner");
+            command.args(["verify", "--verbose", "--print-certs"]);
+            command
+        }
+        "jarsigner" => {
+            let mut command = Command::new("jarsigner");
+            command.args(["-verify", "-verbose", "-certs"]);
+            command
+        }
+        other => anyhow::bail!(
+            "Unknown verification method '{}' (expected apksigner or jarsigner)",
+            other
+        ),
+    };
+    command.arg(zip_path);
+    crate::utils::print_debug(&format!("Running: {}", redacted_command(&command)));
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run {}", method))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report = if method == "apksigner" {
+        parse_apksigner_output(&stdout)
+    } else {
+        parse_jarsigner_output(&stdout)
+    };
+    let verified = output.status.success()
+        && !report.schemes.is_empty()
+        && (method == "apksigner" || stdout.contains("jar verified"));
+    if !verified {
+        anyhow::bail!(
+            "Signature verification of '{}' failed:\n{}\n{}",
+            zip_path.display(),
+            stdout.trim_end(),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    crate::utils::print_success(&format!(
+        "✅ Signature verified ({})",
+        report.schemes.join(", ")
+    ));
+    if let Some(subject) = &report.subject {
+        crate::utils::print_info(&format!("📜 Signer: {}", subject));
+    }
+    Ok(())
+}
+
+/// Reads `Verified using v<N> scheme (...): true` lines and the first
+/// `certificate DN` from `apksigner verify --verbose --print-certs`
+fn parse_apksigner_output(output: &str) -> SignatureReport {
+    let mut report = SignatureReport::default();
+    for line in output.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("Verified using ")
+            && let Some((scheme, result)) = rest.split_once(':')
+            && result.trim() == "true"
+        {
+            let version = scheme.split_whitespace().next().unwrap_or(scheme);
+            report.schemes.push(version.to_uppercase());
+        } else if let Some((_, dn)) = line.split_once("certificate DN: ")
+            && report.subject.is_none()
+        {
+            report.subject = Some(dn.trim().to_string());
+        }
+    }
+    report
+}
+
+/// jarsigner only produces JAR (v1) signatures; the subject is read from the
+/// `Signed by "..."` or `X.509, ...` line of `jarsigner -verify -verbose -certs`
+fn parse_jarsigner_output(output: &str) -> SignatureReport {
+    let mut report = SignatureReport::default();
+    for line in output.lines().map(str::trim) {
+        let subject = if let Some(rest) = line.strip_prefix("Signed by ") {
+            Some(rest.trim_matches('"'))
+        } else {
+            line.strip_prefix("X.509, ")
+                .or_else(|| line.strip_prefix("- X.509, "))
+        };
+        if let Some(subject) = subject
+            && report.subject.is_none()
+        {
+            report.subject = Some(subject.to_string());
+        }
+    }
+    if output.contains("jar verified") {
+        report.schemes.push("V1".to_string());
+    }
+    report
+}
+
 async fn sign_with_apksigner(
     zip_path: &Path,
     signing_config: &SigningConfig,
@@ -46,35 +156,45 @@ async fn sign_with_apksigner(
         return Ok(());
     }
 
-    let output = Command::new("apksigner")
+    let mut command = Command::new("apksigner");
+    command
         .arg("sign")
         .arg("--ks")
         .arg(&signing_config.keystore_path)
         .arg("--ks-key-alias")
         .arg(&signing_config.key_alias)
         .arg("--ks-pass")
-        .arg(&format!("pass:{}", signing_config.keystore_password))
+        .arg(format!("pass:{}", signing_config.keystore_password))
         .arg("--key-pass")
-        .arg(&format!("pass:{}", signing_config.key_password))
-        .arg("--out")
-        .arg(&format!(
-            "{}_signed.zip",
-            zip_path.file_stem().unwrap().to_string_lossy()
-        ))
-        .arg(zip_path)
-        .output()
-        .context("Failed to execute apksigner")?;
+        .arg(format!("pass:{}", signing_config.key_password));
+    sign_to_copy(zip_path, command, signing_config, "apksigner").await?;
+    crate::utils::print_success("✍️  ROM signed successfully with apksigner");
+    Ok(())
+}
 
-    if output.status.success() {
-        crate::utils::print_success("✍️  ROM signed successfully with apksigner");
-    } else {
-        return Err(anyhow::anyhow!(
-            "apksigner failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+/// Runs a signer that writes the signed ZIP to `--out`, then moves it over
+/// `zip_path`, so the ROM that is split, uploaded and reported is the signed
+/// one, as with the methods that sign in place
+async fn sign_to_copy(
+    zip_path: &Path,
+    mut command: Command,
+    signing_config: &SigningConfig,
+    tool: &str,
+) -> anyhow::Result<()> {
+    let signed_path = PathBuf::from(format!("{}.signed", zip_path.display()));
+    command.arg("--out").arg(&signed_path).arg(zip_path);
+    crate::utils::print_debug(&format!("Running: {}", redacted_command(&command)));
+    if let Err(e) = run_with_retries(&mut command, signing_config, tool).await {
+        let _ = std::fs::remove_file(&signed_path);
+        return Err(e);
     }
-
-    Ok(())
+    std::fs::rename(&signed_path, zip_path).with_context(|| {
+        format!(
+            "Failed to move '{}' to '{}'",
+            signed_path.display(),
+            zip_path.display()
+        )
+    })
 }
 
 async fn sign_with_jarsigner(
@@ -95,7 +215,8 @@ async fn sign_with_jarsigner(
         return Ok(());
     }
 
-    let output = Command::new("jarsigner")
+    let mut command = Command::new("jarsigner");
+    command
         .arg("-verbose")
         .arg("-sigalg")
         .arg("SHA256withRSA")
@@ -108,22 +229,84 @@ async fn sign_with_jarsigner(
         .arg("-keypass")
         .arg(&signing_config.key_password)
         .arg(zip_path)
-        .arg(&signing_config.key_alias)
-        .output()
-        .context("Failed to execute jarsigner")?;
+        .arg(&signing_config.key_alias);
+    crate::utils::print_debug(&format!("Running: {}", redacted_command(&command)));
+    run_with_retries(&mut command, signing_config, "jarsigner").await?;
+    crate::utils::print_success("✍️  ROM signed successfully with jarsigner");
+    Ok(())
+}
 
-    if output.status.success() {
-        crate::utils::print_success("✍️  ROM signed successfully with jarsigner");
-    } else {
-        return Err(anyhow::anyhow!(
-            "jarsigner failed: {}",
-            String::from_utf8_lossy(&output.stderr)
+/// Signs with a key that never leaves a PKCS#11 token (HSM, smart card),
+/// through jarsigner's SunPKCS11 provider. The PIN is read by jarsigner from
+/// the environment variable named in `pkcs11_pin_env`.
+async fn sign_with_pkcs11(
+    zip_path: &Path,
+    signing_config: &SigningConfig,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let module = signing_config
+        .pkcs11_module
+        .as_ref()
+        .context("signing.pkcs11_module is required for the pkcs11 method")?;
+    let pin_env = signing_config
+        .pkcs11_pin_env
+        .as_ref()
+        .context("signing.pkcs11_pin_env is required for the pkcs11 method")?;
+    if dry_run {
+        crate::utils::print_info("🔍 DRY RUN: Would sign ROM with a PKCS#11 token");
+        crate::utils::print_info(&format!(
+            "🔍 DRY RUN: PKCS#11 URI: {}",
+            pkcs11_uri(signing_config, module)
         ));
+        crate::utils::print_info(&format!("🔍 DRY RUN: PIN from ${}", pin_env));
+        return Ok(());
+    }
+    if std::env::var_os(pin_env).is_none() {
+        anyhow::bail!(
+            "Environment variable '{}' (signing.pkcs11_pin_env) is not set",
+            pin_env
+        );
     }
 
+    let mut provider_config =
+        tempfile::NamedTempFile::new().context("Failed to create PKCS#11 provider config")?;
+    write!(provider_config, "name = ROMMER\nlibrary = {}\n", module)?;
+    if let Some(slot) = signing_config.pkcs11_slot {
+        writeln!(provider_config, "slot = {}", slot)?;
+    }
+    let mut command = Command::new("jarsigner");
+    command
+        .arg("-keystore")
+        .arg("NONE")
+        .arg("-storetype")
+        .arg("PKCS11")
+        .arg("-addprovider")
+        .arg("SunPKCS11")
+        .arg("-providerArg")
+        .arg(provider_config.path())
+        .arg("-storepass:env")
+        .arg(pin_env)
+        .arg("-sigalg")
+        .arg("SHA256withRSA")
+        .arg("-digestalg")
+        .arg("SHA-256")
+        .arg(zip_path)
+        .arg(&signing_config.key_alias);
+    crate::utils::print_debug(&format!("Running: {}", redacted_command(&command)));
+    run_with_retries(&mut command, signing_config, "jarsigner (PKCS#11)").await?;
+    crate::utils::print_success("✍️  ROM signed successfully with PKCS#11 token");
     Ok(())
 }
 
+/// RFC 7512 URI of the signing key, for display
+fn pkcs11_uri(signing_config: &SigningConfig, module: &str) -> String {
+    let mut uri = format!("pkcs11:object={};type=private", signing_config.key_alias);
+    if let Some(slot) = signing_config.pkcs11_slot {
+        uri.push_str(&format!(";slot-id={}", slot));
+    }
+    format!("{}?module-path={}", uri, module)
+}
+
 async fn sign_with_custom_command(
     zip_path: &Path,
     signing_config: &SigningConfig,
@@ -139,25 +322,61 @@ async fn sign_with_custom_command(
         }
 
         let command_with_path = custom_command.replace("{zip_path}", &zip_path.to_string_lossy());
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(&command_with_path)
-            .output()
-            .context("Failed to execute custom signing command")?;
-
-        if output.status.success() {
-            crate::utils::print_success("✍️  ROM signed successfully with custom command");
-        } else {
-            return Err(anyhow::anyhow!(
-                "Custom signing command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
+        crate::utils::print_debug(&format!("Running: sh -c {}", command_with_path));
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&command_with_path);
+        run_with_retries(&mut command, signing_config, "Custom signing command").await?;
+        crate::utils::print_success("✍️  ROM signed successfully with custom command");
     }
 
     Ok(())
 }
 
+/// Runs a signing command, retrying a failed attempt up to
+/// `signing_max_retries` times in total. The delay between attempts starts at
+/// `signing_retry_delay_ms` and doubles after each retry.
+async fn run_with_retries(
+    command: &mut Command,
+    signing_config: &SigningConfig,
+    tool: &str,
+) -> anyhow::Result<()> {
+    let max_retries = signing_config.signing_max_retries.unwrap_or(1).max(1);
+    let mut delay_ms = signing_config.signing_retry_delay_ms.unwrap_or(2000);
+    let mut last_error = None;
+    for attempt in 1..=max_retries {
+        if attempt > 1 {
+            crate::utils::print_info(&format!("Retry {}/{} signing...", attempt, max_retries));
+        }
+        let error = match command.output() {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => {
+                // jarsigner reports errors on stdout
+                let message = if output.stderr.is_empty() {
+                    &output.stdout
+                } else {
+                    &output.stderr
+                };
+                anyhow::anyhow!(
+                    "{} failed: {}",
+                    tool,
+                    String::from_utf8_lossy(message).trim()
+                )
+            }
+            Err(e) => anyhow::Error::new(e).context(format!("Failed to execute {}", tool)),
+        };
+        if attempt < max_retries {
+            crate::utils::print_warning(&format!(
+                "Attempt {}/{}: {}. Retrying in {}ms...",
+                attempt, max_retries, error, delay_ms
+            ));
+            tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms)).await;
+            delay_ms *= 2;
+        }
+        last_error = Some(error);
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("{} failed", tool)))
+}
+
 async fn create_test_signature(zip_path: &Path, dry_run: bool) -> anyhow::Result<()> {
     if dry_run {
         crate::utils::print_info("🔍 DRY RUN: Would create test signature");
@@ -174,7 +393,7 @@ async fn create_test_signature(zip_path: &Path, dry_run: bool) -> anyhow::Result
 
     let output = Command::new("python3")
         .arg("-c")
-        .arg(&format!(
+        .arg(format!(
             r#"
 import zipfile
 import hashlib
@@ -218,7 +437,7 @@ print('Test signature added')
 
 async fn generate_test_keys(key_path: &str, cert_path: &str) -> anyhow::Result<()> {
     let output = Command::new("openssl")
-        .args(&[
+        .args([
             "req",
             "-x509",
             "-newkey",
@@ -245,3 +464,60 @@ async fn generate_test_keys(key_path: &str, cert_path: &str) -> anyhow::Result<(
 
     Ok(())
 }
+
+/// Formats a command for debug output with password arguments masked
+fn redacted_command(command: &Command) -> String {
+    let mut parts = vec![command.get_program().to_string_lossy().to_string()];
+    let mut mask_next = false;
+    for arg in command.get_args() {
+        let arg = arg.to_string_lossy();
+        if mask_next || arg.starts_with("pass:") {
+            parts.push("****".to_string());
+        } else {
+            parts.push(arg.to_string());
+        }
+        mask_next = arg == "-storepass" || arg == "-keypass";
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sign_to_copy_replaces_the_rom() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let rom = dir.path().join("rom.zip");
+        std::fs::write(&rom, "unsigned")?;
+        // Stands in for apksigner: `--out <signed> <input>`
+        let mut command = Command::new("sh");
+        command.args(["-c", "cp \"$3\" \"$2\" && printf ' signed' >> \"$2\"", "sh"]);
+        let signing_config: SigningConfig =
+            serde_yaml::from_str("method: apksigner\nkey_alias: rommer")?;
+        sign_to_copy(&rom, command, &signing_config, "apksigner").await?;
+        assert_eq!(std::fs::read_to_string(&rom)?, "unsigned signed");
+        assert!(!dir.path().join("rom.zip.signed").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_verify_output() {
+        let apksigner = "Verifies\nVerified using v1 scheme (JAR signing): true\nVerified using v2 scheme (APK Signature Scheme v2): false\nVerified using v3 scheme (APK Signature Scheme v3): true\nNumber of signers: 1\nSigner #1 certificate DN: CN=ROMMER, O=TheROMMER\n";
+        assert_eq!(
+            parse_apksigner_output(apksigner),
+            SignatureReport {
+                schemes: vec!["V1".to_string(), "V3".to_string()],
+                subject: Some("CN=ROMMER, O=TheROMMER".to_string()),
+            }
+        );
+        let jarsigner = "sm  1234 Fri Oct 16 12:00:00 UTC 2026 system/build.prop\n\n      >>> Signer\n      X.509, CN=ROMMER, O=TheROMMER\n      [certificate is valid]\n\njar verified.\n";
+        assert_eq!(
+            parse_jarsigner_output(jarsigner),
+            SignatureReport {
+                schemes: vec!["V1".to_string()],
+                subject: Some("CN=ROMMER, O=TheROMMER".to_string()),
+            }
+        );
+    }
+}
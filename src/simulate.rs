@@ -0,0 +1,118 @@
+use crate::config::Config;
+use crate::rezip::{self, Source};
+use crate::utils;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// How an entry of the simulated output ZIP differs from the base ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulatedFile {
+    Unchanged,
+    New,
+    Modified,
+    Deleted,
+}
+
+impl SimulatedFile {
+    fn label(self) -> &'static str {
+        match self {
+            SimulatedFile::Unchanged => "",
+            SimulatedFile::New => "[NEW]",
+            SimulatedFile::Modified => "[MODIFIED]",
+            SimulatedFile::Deleted => "[DELETED]",
+        }
+    }
+}
+
+/// Predicts the entries of the output ZIP, including the base ROM entries
+/// the patches delete, sorted by path
+pub fn simulate<R: std::io::Read + std::io::Seek>(
+    archive: &ZipArchive<R>,
+    patches: &[PathBuf],
+    config: &Config,
+) -> anyhow::Result<BTreeMap<String, SimulatedFile>> {
+    let (_, entries) = rezip::resolve_entries(archive, patches, config)?;
+    let mut tree: BTreeMap<String, SimulatedFile> = archive
+        .file_names()
+        .filter(|name| !entries.contains_key(*name))
+        .map(|name| (name.to_string(), SimulatedFile::Deleted))
+        .collect();
+    for (name, source) in entries {
+        let in_base = archive.index_for_name(&name).is_some();
+        let file = match source {
+            Source::ZipEntry(_) => SimulatedFile::Unchanged,
+            Source::PatchFile(_) if in_base => SimulatedFile::Modified,
+            Source::PatchFile(_) | Source::PatchDir => SimulatedFile::New,
+        };
+        tree.insert(name, file);
+    }
+    Ok(tree)
+}
+
+/// Prints the predicted file listing of the output ZIP without building it
+pub fn print_simulation(
+    base_zip: &Path,
+    patches: &[PathBuf],
+    config: &Config,
+) -> anyhow::Result<()> {
+    utils::print_section("🔮 SIMULATED OUTPUT");
+    let file = File::open(base_zip)
+        .with_context(|| format!("Failed to open zip file '{}'", base_zip.display()))?;
+    let archive = ZipArchive::new(file).context("Failed to read zip archive")?;
+    let tree = simulate(&archive, patches, config)?;
+    for (name, file) in &tree {
+        println!("{:<11}{}", file.label(), name);
+    }
+    let count = |kind| tree.values().filter(|f| **f == kind).count();
+    utils::print_info(&format!(
+        "🔮 {} entries: {} new, {} modified, {} deleted",
+        tree.len() - count(SimulatedFile::Deleted),
+        count(SimulatedFile::New),
+        count(SimulatedFile::Modified),
+        count(SimulatedFile::Deleted)
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use tempfile::tempdir;
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_simulate_annotates_changes() -> anyhow::Result<()> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in ["system/build.prop", "system/app/Old.apk", "boot.img"] {
+            zip.start_file(name, FileOptions::<()>::default())?;
+            zip.write_all(b"base")?;
+        }
+        let archive = ZipArchive::new(zip.finish()?)?;
+
+        let patch = tempdir()?;
+        fs::create_dir_all(patch.path().join("system").join("media"))?;
+        fs::write(patch.path().join("system").join("build.prop"), "patched")?;
+        fs::write(
+            patch.path().join("system").join("media").join("boot.zip"),
+            "new",
+        )?;
+        fs::write(patch.path().join(".rommerfdel"), "system/app/Old.apk\n")?;
+        let tree = simulate(
+            &archive,
+            &[patch.path().to_path_buf()],
+            &crate::config::test_config(),
+        )?;
+
+        assert_eq!(tree["boot.img"], SimulatedFile::Unchanged);
+        assert_eq!(tree["system/build.prop"], SimulatedFile::Modified);
+        assert_eq!(tree["system/media/boot.zip"], SimulatedFile::New);
+        assert_eq!(tree["system/app/Old.apk"], SimulatedFile::Deleted);
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::utils;
+use anyhow::Context;
+use indicatif::HumanBytes;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Name of split part `index` (1-based) of `output_path`: `rom.zip.001`,
+/// `rom.zip.002`, ...
+fn part_path(output_path: &Path, index: usize) -> PathBuf {
+    let mut name = output_path.as_os_str().to_owned();
+    name.push(format!(".{:03}", index));
+    PathBuf::from(name)
+}
+
+/// The parts of a split output in order. An output that was not split is a
+/// single part, `output_path` itself.
+pub fn split_parts(output_path: &Path) -> Vec<PathBuf> {
+    let parts: Vec<PathBuf> = (1..)
+        .map(|index| part_path(output_path, index))
+        .take_while(|part| part.exists())
+        .collect();
+    if parts.is_empty() {
+        vec![output_path.to_path_buf()]
+    } else {
+        parts
+    }
+}
+
+/// Removes the `.001`, `.002`, ... parts left by an earlier split of
+/// `output_path`
+pub fn remove_split_parts(output_path: &Path) -> anyhow::Result<()> {
+    for part in split_parts(output_path)
+        .iter()
+        .filter(|p| *p != output_path)
+    {
+        fs::remove_file(part).with_context(|| format!("Failed to remove '{}'", part.display()))?;
+    }
+    Ok(())
+}
+
+/// Splits `output_path` into parts of at most `split_size_mb` MiB when it is
+/// larger than that, written to `rom.zip.001`, `rom.zip.002`, ... next to it.
+/// The ZIP itself is left alone; concatenating the parts in order gives it
+/// back. Returns the parts in order.
+pub fn split_output(output_path: &Path, split_size_mb: u64) -> anyhow::Result<Vec<PathBuf>> {
+    anyhow::ensure!(split_size_mb > 0, "output.split_size_mb must be at least 1");
+    remove_split_parts(output_path)?;
+    let part_size = split_size_mb * 1024 * 1024;
+    let total_size = fs::metadata(output_path)
+        .with_context(|| format!("Failed to read '{}'", output_path.display()))?
+        .len();
+    if total_size <= part_size {
+        return Ok(vec![output_path.to_path_buf()]);
+    }
+    utils::print_section("✂️  SPLITTING OUTPUT");
+    let part_count = total_size.div_ceil(part_size) as usize;
+    let mut input = File::open(output_path)
+        .with_context(|| format!("Failed to open '{}'", output_path.display()))?;
+    let mut parts = Vec::new();
+    for index in 1..=part_count {
+        let part = part_path(output_path, index);
+        let mut file = File::create(&part)
+            .with_context(|| format!("Failed to create '{}'", part.display()))?;
+        std::io::copy(&mut (&mut input).take(part_size), &mut file)?;
+        parts.push(part);
+    }
+    for part in &parts {
+        utils::print_info(&format!(
+            "✂️  {} ({})",
+            part.display(),
+            HumanBytes(fs::metadata(part)?.len())
+        ));
+    }
+    utils::print_success(&format!(
+        "✂️  Split {} into {} parts",
+        output_path.display(),
+        parts.len()
+    ));
+    Ok(parts)
+}
+
+/// SHA-256 of the parts reassembled in order, which is the checksum of the
+/// original ZIP
+pub fn parts_checksum(parts: &[PathBuf]) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        let mut file =
+            File::open(part).with_context(|| format!("Failed to open '{}'", part.display()))?;
+        std::io::copy(&mut file, &mut hasher)?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_split_output_reassembles() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let output = dir.path().join("rom.zip");
+        let content: Vec<u8> = (0..(2 * 1024 * 1024 + 10))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        fs::write(&output, &content)?;
+        let expected = crate::checksum::calculate_file_checksum(&output)?;
+
+        let parts = split_output(&output, 1)?;
+        assert_eq!(
+            parts,
+            vec![
+                dir.path().join("rom.zip.001"),
+                dir.path().join("rom.zip.002"),
+                dir.path().join("rom.zip.003")
+            ]
+        );
+        assert_eq!(fs::metadata(&parts[2])?.len(), 10);
+        assert_eq!(fs::read(&output)?, content);
+        assert_eq!(split_parts(&output), parts);
+        assert_eq!(parts_checksum(&parts)?, expected);
+
+        // A later build below the limit drops the stale parts
+        fs::write(&output, b"small")?;
+        assert_eq!(split_output(&output, 1)?, vec![output.clone()]);
+        assert_eq!(split_parts(&output), vec![output]);
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Directory inside the work directory holding one marker file per completed step
+pub const MARKER_DIR: &str = ".rommer-step-complete";
+/// File in the work directory listing the patches applied so far, one per line
+pub const APPLIED_PATCHES_FILE: &str = ".rommer-applied-patches";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Step {
+    Download,
+    Extract,
+    Patch,
+    Rezip,
+    Sign,
+}
+
+impl Step {
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::Download => "download",
+            Step::Extract => "extract",
+            Step::Patch => "patch",
+            Step::Rezip => "rezip",
+            Step::Sign => "sign",
+        }
+    }
+
+    fn all() -> [Step; 5] {
+        [
+            Step::Download,
+            Step::Extract,
+            Step::Patch,
+            Step::Rezip,
+            Step::Sign,
+        ]
+    }
+}
+
+/// Records that `step` completed in `work_dir`, with optional content (the ROM
+/// path for the download step, the output ZIP for the rezip step)
+pub fn mark_complete(
+    work_dir: &Path,
+    step: Step,
+    content: &str,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    if dry_run {
+        return Ok(());
+    }
+    let marker_dir = work_dir.join(MARKER_DIR);
+    fs::create_dir_all(&marker_dir).context("Failed to create step marker directory")?;
+    fs::write(marker_dir.join(step.name()), content)
+        .with_context(|| format!("Failed to write '{}' step marker", step.name()))
+}
+
+/// Ensures every step before `from_step` has completed in `work_dir`
+pub fn validate(work_dir: &Path, from_step: Step) -> anyhow::Result<()> {
+    if !work_dir.is_dir() {
+        anyhow::bail!("Work directory '{}' does not exist", work_dir.display());
+    }
+    let missing: Vec<&str> = Step::all()
+        .into_iter()
+        .filter(|step| *step < from_step)
+        .filter(|step| !work_dir.join(MARKER_DIR).join(step.name()).exists())
+        .map(Step::name)
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "Cannot resume from '{}': step(s) {} did not complete in '{}'",
+            from_step.name(),
+            missing.join(", "),
+            work_dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// The ROM ZIP recorded by the download step
+pub fn downloaded_rom(work_dir: &Path) -> anyhow::Result<PathBuf> {
+    let content = fs::read_to_string(work_dir.join(MARKER_DIR).join(Step::Download.name()))
+        .context("Failed to read 'download' step marker")?;
+    Ok(PathBuf::from(content.trim()))
+}
+
+/// The output ZIP recorded by the rezip step, if it recorded one
+pub fn rezipped_output(work_dir: &Path) -> Option<PathBuf> {
+    let content = fs::read_to_string(work_dir.join(MARKER_DIR).join(Step::Rezip.name())).ok()?;
+    let content = content.trim();
+    (!content.is_empty()).then(|| PathBuf::from(content))
+}
+
+/// Forgets the patches recorded by an earlier build in `work_dir`
+pub fn reset_applied_patches(work_dir: &Path, dry_run: bool) -> anyhow::Result<()> {
+    let path = work_dir.join(APPLIED_PATCHES_FILE);
+    if dry_run || !path.exists() {
+        return Ok(());
+    }
+    fs::remove_file(&path).with_context(|| format!("Failed to delete '{}'", path.display()))
+}
+
+/// Records that a patch was applied to `work_dir`
+pub fn record_applied_patch(work_dir: &Path, patch: &str, dry_run: bool) -> anyhow::Result<()> {
+    if dry_run {
+        return Ok(());
+    }
+    let path = work_dir.join(APPLIED_PATCHES_FILE);
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open '{}'", path.display()))?;
+    writeln!(file, "{}", patch).with_context(|| format!("Failed to write '{}'", path.display()))
+}
+
+/// The patches recorded by [`record_applied_patch`], empty if there are none
+pub fn applied_patches(work_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let path = work_dir.join(APPLIED_PATCHES_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_applied_patches() -> anyhow::Result<()> {
+        let work = tempdir()?;
+        assert!(applied_patches(work.path())?.is_empty());
+        record_applied_patch(work.path(), "patches/debloat", false)?;
+        record_applied_patch(work.path(), "patches/hosts", false)?;
+        record_applied_patch(work.path(), "patches/fonts", true)?;
+        assert_eq!(
+            applied_patches(work.path())?,
+            ["patches/debloat", "patches/hosts"]
+        );
+        reset_applied_patches(work.path(), false)?;
+        assert!(applied_patches(work.path())?.is_empty());
+        Ok(())
+    }
+}
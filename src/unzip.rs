@@ -1,12 +1,21 @@
 use crate::utils;
+use crate::verify::UPDATER_SCRIPT;
 use anyhow::Context;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::ProgressStyle;
+use std::collections::HashSet;
 use std::fs;
 use std::fs::File;
 use std::path::Path;
 use zip::ZipArchive;
 
-pub fn unzip_rom(zip_path: &Path, out_dir: &Path, dry_run: bool) -> anyhow::Result<()> {
+/// Extracts a ROM ZIP into `out_dir`. With `validate`, the extracted tree is
+/// checked afterwards (see [`validate_extraction`]).
+pub fn unzip_rom(
+    zip_path: &Path,
+    out_dir: &Path,
+    validate: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
     crate::utils::print_section("📦 EXTRACTING ROM");
     if dry_run {
         crate::utils::print_info(&format!(
@@ -19,7 +28,7 @@ pub fn unzip_rom(zip_path: &Path, out_dir: &Path, dry_run: bool) -> anyhow::Resu
     let file = File::open(zip_path)
         .with_context(|| format!("Failed to open zip file '{}'", zip_path.display()))?;
     let mut archive = ZipArchive::new(file).context("Failed to read zip archive")?;
-    let pb = ProgressBar::new(archive.len() as u64);
+    let pb = crate::utils::progress_bar(archive.len() as u64, "Extracting");
     pb.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -34,10 +43,10 @@ pub fn unzip_rom(zip_path: &Path, out_dir: &Path, dry_run: bool) -> anyhow::Resu
         if file.is_dir() {
             fs::create_dir_all(&outpath)?;
         } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(&p)?;
-                }
+            if let Some(p) = outpath.parent()
+                && !p.exists()
+            {
+                fs::create_dir_all(p)?;
             }
             let mut outfile = File::create(&outpath)?;
             std::io::copy(&mut file, &mut outfile)?;
@@ -46,5 +55,83 @@ pub fn unzip_rom(zip_path: &Path, out_dir: &Path, dry_run: bool) -> anyhow::Resu
     }
     pb.finish_with_message("Extraction complete!");
     utils::print_success(&format!("📂 Extracted to: {}", out_dir.display()));
+    if validate {
+        validate_extraction(&mut archive, out_dir)?;
+        utils::print_success("✅ Extracted ROM validated");
+    }
     Ok(())
 }
+
+/// Checks that an extracted ROM has an updater-script and a system partition,
+/// and that every file of the archive was extracted with its full size
+fn validate_extraction(archive: &mut ZipArchive<File>, out_dir: &Path) -> anyhow::Result<()> {
+    if !out_dir.join(UPDATER_SCRIPT).is_file() {
+        anyhow::bail!("Extracted ROM has no {}", UPDATER_SCRIPT);
+    }
+    if !out_dir.join("system").is_dir() && !out_dir.join("system.img").is_file() {
+        anyhow::bail!("Extracted ROM has neither system/ nor system.img");
+    }
+    let mut expected = 0;
+    let mut missing = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        expected += 1;
+        let path = out_dir.join(file.mangled_name());
+        if !fs::metadata(&path).is_ok_and(|m| m.len() == file.size()) {
+            missing.push(file.name().to_string());
+        }
+    }
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "Only {} of {} files were extracted intact, the ROM ZIP may be corrupt. First missing or truncated:\n  {}",
+            expected - missing.len(),
+            expected,
+            missing
+                .iter()
+                .take(10)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Lists the files and directories of a ROM ZIP like [`list_zip_entries`],
+/// failing for ROMs that ship their partitions as images, whose files can't
+/// be listed
+pub fn list_rom_files(rom_path: &Path) -> anyhow::Result<HashSet<String>> {
+    let entries = list_zip_entries(rom_path)?;
+    if let Some(image) = ["system.img", "payload.bin"]
+        .iter()
+        .find(|image| entries.contains(**image))
+    {
+        anyhow::bail!(
+            "'{}' ships its partitions in {}, its files can't be listed",
+            rom_path.display(),
+            image
+        );
+    }
+    Ok(entries)
+}
+
+/// Lists every file and directory in a ZIP, including implicit parent directories
+pub fn list_zip_entries(zip_path: &Path) -> anyhow::Result<HashSet<String>> {
+    let file = File::open(zip_path)
+        .with_context(|| format!("Failed to open zip file '{}'", zip_path.display()))?;
+    let archive = ZipArchive::new(file).context("Failed to read zip archive")?;
+    let mut entries = HashSet::new();
+    for name in archive.file_names() {
+        let name = name.trim_end_matches('/');
+        let mut current = name;
+        entries.insert(current.to_string());
+        while let Some((parent, _)) = current.rsplit_once('/') {
+            entries.insert(parent.to_string());
+            current = parent;
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,150 @@
+use crate::config::UploadConfig;
+use crate::utils;
+use anyhow::Context;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, ObjectStoreExt, WriteMultipart};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// Uploads the final ROM, or every part of a split ROM, with a `.sha256sum`
+/// of the whole ROM named after it
+pub async fn upload_rom(
+    rom_path: &Path,
+    parts: &[PathBuf],
+    upload_config: &UploadConfig,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    utils::print_section("☁️  UPLOADING ROM");
+    let file_name = rom_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .context("Output ROM has no file name")?;
+    let checksum_name = format!("{}.sha256sum", file_name);
+    let checksum_key = object_key(upload_config, &checksum_name);
+    let mut uploads = Vec::new();
+    for part in parts {
+        let name = part
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .context("Output ROM has no file name")?;
+        uploads.push((part.clone(), object_key(upload_config, &name)));
+    }
+    if dry_run {
+        for (part, key) in &uploads {
+            utils::print_info(&format!(
+                "🔍 DRY RUN: Would upload {} to {}://{}/{}",
+                part.display(),
+                upload_config.provider,
+                upload_config.bucket,
+                key
+            ));
+        }
+        utils::print_info(&format!(
+            "🔍 DRY RUN: Would upload {} to {}://{}/{}",
+            checksum_name, upload_config.provider, upload_config.bucket, checksum_key
+        ));
+        return Ok(());
+    }
+
+    let store = build_store(upload_config)?;
+    let hash = crate::split::parts_checksum(parts)?;
+    let checksum_path = rom_path.with_file_name(&checksum_name);
+    fs::write(&checksum_path, format!("{}  {}\n", hash, file_name))
+        .with_context(|| format!("Failed to write '{}'", checksum_path.display()))?;
+
+    uploads.push((checksum_path, checksum_key));
+    for (path, key) in &uploads {
+        upload_file(&store, path, key).await?;
+    }
+    for (_, key) in &uploads {
+        utils::print_success(&format!("☁️  Uploaded: {}", public_url(upload_config, key)));
+    }
+    Ok(())
+}
+
+fn build_store(upload_config: &UploadConfig) -> anyhow::Result<impl ObjectStore> {
+    let mut builder = AmazonS3Builder::from_env().with_bucket_name(&upload_config.bucket);
+    match upload_config.provider.as_str() {
+        "s3" | "r2" | "gcs" => {}
+        other => anyhow::bail!("Unsupported upload provider: {}", other),
+    }
+    if let Some(region) = &upload_config.region {
+        builder = builder.with_region(region);
+    } else if upload_config.provider != "s3" {
+        builder = builder.with_region("auto");
+    }
+    if let Some(endpoint) = endpoint_url(upload_config) {
+        builder = builder.with_endpoint(endpoint);
+    } else if upload_config.provider == "r2" {
+        anyhow::bail!("The 'r2' upload provider requires 'endpoint_url'");
+    }
+    if let Some(key_id) = upload_config.access_key_id()? {
+        builder = builder.with_access_key_id(key_id);
+    }
+    if let Some(secret) = upload_config.secret_access_key()? {
+        builder = builder.with_secret_access_key(secret);
+    }
+    builder
+        .build()
+        .context("Failed to configure upload storage client")
+}
+
+async fn upload_file(store: &impl ObjectStore, path: &Path, key: &str) -> anyhow::Result<()> {
+    utils::print_info(&format!("📤 Uploading {}...", path.display()));
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open '{}'", path.display()))?;
+    let upload = store
+        .put_multipart(&ObjectPath::from(key))
+        .await
+        .with_context(|| format!("Failed to start upload of '{}'", key))?;
+    let mut writer = WriteMultipart::new(upload);
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let bytes_read = file
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        if bytes_read == 0 {
+            break;
+        }
+        writer.wait_for_capacity(8).await?;
+        writer.write(&buffer[..bytes_read]);
+    }
+    writer
+        .finish()
+        .await
+        .with_context(|| format!("Failed to upload '{}'", key))?;
+    Ok(())
+}
+
+fn object_key(upload_config: &UploadConfig, file_name: &str) -> String {
+    match &upload_config.key_prefix {
+        Some(prefix) if !prefix.trim_matches('/').is_empty() => {
+            format!("{}/{}", prefix.trim_matches('/'), file_name)
+        }
+        _ => file_name.to_string(),
+    }
+}
+
+fn endpoint_url(upload_config: &UploadConfig) -> Option<String> {
+    match (&upload_config.endpoint_url, upload_config.provider.as_str()) {
+        (Some(url), _) => Some(url.trim_end_matches('/').to_string()),
+        (None, "gcs") => Some(GCS_ENDPOINT.to_string()),
+        _ => None,
+    }
+}
+
+fn public_url(upload_config: &UploadConfig, key: &str) -> String {
+    match endpoint_url(upload_config) {
+        Some(endpoint) => format!("{}/{}/{}", endpoint, upload_config.bucket, key),
+        None => format!(
+            "https://{}.s3.{}.amazonaws.com/{}",
+            upload_config.bucket,
+            upload_config.region.as_deref().unwrap_or("us-east-1"),
+            key
+        ),
+    }
+}
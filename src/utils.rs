@@ -1,16 +1,164 @@
-use crate::config::Hooks;
+use crate::config::{Config, Hooks};
 use anyhow::{Context, Result};
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
 use std::{fs, io};
+use tracing::level_filters::LevelFilter;
 use walkdir::WalkDir;
+static LOG_LEVEL: OnceLock<LogLevel> = OnceLock::new();
+static OUTPUT_LEVEL: OnceLock<OutputLevel> = OnceLock::new();
+static CI: OnceLock<bool> = OnceLock::new();
+/// Environment variables hook scripts run with, set once the config is loaded
+static HOOK_ENV: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+/// Files `copy_dir_all` copied into one work directory, by SHA-256, so later
+/// patches of the same build hard link identical files instead of copying
+/// them again. Cloning shares the same record.
+#[derive(Clone, Default)]
+pub struct CopiedFiles(Arc<Mutex<HashMap<String, CopiedFile>>>);
+
+/// A file copied by `copy_dir_all`, with the size and modification time it
+/// had then, to tell whether it was changed since
+struct CopiedFile {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// How much output `--quiet` and `--silent` suppress
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OutputLevel {
+    Normal,
+    /// Only warnings, errors and the final ROM
+    Quiet,
+    /// Only errors and the final ROM
+    Silent,
+}
+
+/// Minimum level of messages that are printed and logged, set with `--log-level`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Trace => LevelFilter::TRACE,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Error => LevelFilter::ERROR,
+        }
+    }
+}
+
+/// Sets the log level and, with `log_file`, writes every message at or above
+/// it to that file as JSON lines. Human output is unaffected by the file.
+pub fn init_logging(level: LogLevel, log_file: Option<&Path>) -> Result<()> {
+    let _ = LOG_LEVEL.set(level);
+    if let Some(path) = log_file {
+        let file = fs::File::create(path)
+            .with_context(|| format!("Failed to create log file '{}'", path.display()))?;
+        tracing_subscriber::fmt()
+            .json()
+            .with_ansi(false)
+            .with_max_level(level.filter())
+            .with_writer(Mutex::new(file))
+            .try_init()
+            .map_err(|e| anyhow::anyhow!("Failed to set up logging: {}", e))?;
+    }
+    Ok(())
+}
+
+fn log_level() -> LogLevel {
+    LOG_LEVEL.get().copied().unwrap_or(LogLevel::Info)
+}
+
+pub fn is_verbose() -> bool {
+    log_level() <= LogLevel::Debug
+}
+
+pub fn set_output_level(level: OutputLevel) {
+    let _ = OUTPUT_LEVEL.set(level);
+}
+
+pub fn output_level() -> OutputLevel {
+    OUTPUT_LEVEL.get().copied().unwrap_or(OutputLevel::Normal)
+}
+
+/// Whether informational messages and progress bars are printed, which
+/// `--quiet`, `--silent` and `--log-level warn` or above turn off
+pub fn shows_info() -> bool {
+    output_level() == OutputLevel::Normal && log_level() <= LogLevel::Info
+}
+
+pub fn set_ci(ci: bool) {
+    let _ = CI.set(ci);
+}
+
+/// Whether `--ci` is set, for plain output without progress bars or colors
+pub fn is_ci() -> bool {
+    CI.get().copied().unwrap_or(false)
+}
+
+/// A progress bar that is hidden with `--quiet`. With `--ci` it is hidden
+/// too, and `label` is printed with the percentage done every 10 seconds
+/// until the bar is finished or dropped.
+pub fn progress_bar(len: u64, label: &str) -> ProgressBar {
+    if !shows_info() {
+        ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::hidden())
+    } else if is_ci() {
+        let pb = ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::hidden());
+        let weak = pb.downgrade();
+        let label = label.to_string();
+        std::thread::spawn(move || {
+            print_info(&format!("{}... (0%)", label));
+            loop {
+                std::thread::sleep(std::time::Duration::from_secs(10));
+                let Some(pb) = weak.upgrade().filter(|pb| !pb.is_finished()) else {
+                    break;
+                };
+                let percent = match pb.length() {
+                    Some(len) if len > 0 => pb.position() * 100 / len,
+                    _ => 0,
+                };
+                print_info(&format!("{}... ({}%)", label, percent.min(100)));
+            }
+        });
+        pb
+    } else {
+        ProgressBar::new(len)
+    }
+}
+
+/// Sets the environment variables passed to every hook script run afterwards
+pub fn set_hook_env(vars: Vec<(&'static str, String)>) {
+    *HOOK_ENV.lock().unwrap() = vars;
+}
+
 pub fn run_hook(hooks: &Hooks, hook_name: &str) -> Result<()> {
     if let Some(script) = hooks.scripts.get(hook_name) {
         print_info(&("Running hook: ".to_owned() + hook_name));
+        print_debug(&format!("Running: sh {}", script));
+        let start = std::time::Instant::now();
         let status = Command::new("sh")
             .arg(script)
+            .envs(HOOK_ENV.lock().unwrap().iter().map(|(k, v)| (k, v)))
             .status()
             .with_context(|| format!("Failed to run hook script: {}", script))?;
+        crate::metrics::record(&format!("hook {}", hook_name), start.elapsed());
 
         if !status.success() {
             anyhow::bail!(
@@ -29,25 +177,29 @@ pub fn handle_deletions(
     tmp_dir: &Path,
     filename: &str,
     item_type: &str,
+    android_version: u32,
     dry_run: bool,
 ) -> anyhow::Result<()> {
     let del_path = patch_path.join(filename);
     if del_path.exists() {
-        let items_to_delete = read_paths(&del_path)?;
-        for item in items_to_delete {
-            let full_path = tmp_dir.join(&item);
-            if full_path.exists() && full_path.is_dir() {
-                if dry_run {
-                    print_info(&format!(
-                        "🔍 DRY RUN: Would delete {}: {}",
-                        item_type,
-                        item.display()
-                    ));
-                } else {
-                    fs::remove_dir_all(&full_path).with_context(|| {
-                        format!("Failed to delete {} '{}'", item_type, full_path.display())
-                    })?;
-                    print_info(&format!("🗑️  Deleted {}: {}", item_type, item.display()));
+        let items_to_delete = read_paths(&del_path, android_version)?;
+        for entry in items_to_delete {
+            for item in crate::globdel::expand_deletion(tmp_dir, &entry, true)? {
+                let full_path = tmp_dir.join(&item);
+                print_debug(&format!("Checking {}: {}", item_type, full_path.display()));
+                if full_path.exists() && full_path.is_dir() {
+                    if dry_run {
+                        print_info(&format!(
+                            "🔍 DRY RUN: Would delete {}: {}",
+                            item_type,
+                            item.display()
+                        ));
+                    } else {
+                        fs::remove_dir_all(&full_path).with_context(|| {
+                            format!("Failed to delete {} '{}'", item_type, full_path.display())
+                        })?;
+                        print_info(&format!("🗑️  Deleted {}: {}", item_type, item.display()));
+                    }
                 }
             }
         }
@@ -60,25 +212,29 @@ pub fn handle_file_deletions(
     tmp_dir: &Path,
     filename: &str,
     item_type: &str,
+    android_version: u32,
     dry_run: bool,
 ) -> anyhow::Result<()> {
     let del_path = patch_path.join(filename);
     if del_path.exists() {
-        let items_to_delete = read_paths(&del_path)?;
-        for item in items_to_delete {
-            let full_path = tmp_dir.join(&item);
-            if full_path.exists() && full_path.is_file() {
-                if dry_run {
-                    print_info(&format!(
-                        "🔍 DRY RUN: Would delete {}: {}",
-                        item_type,
-                        item.display()
-                    ));
-                } else {
-                    fs::remove_file(&full_path).with_context(|| {
-                        format!("Failed to delete {} '{}'", item_type, full_path.display())
-                    })?;
-                    print_info(&format!("🗑️  Deleted {}: {}", item_type, item.display()));
+        let items_to_delete = read_paths(&del_path, android_version)?;
+        for entry in items_to_delete {
+            for item in crate::globdel::expand_deletion(tmp_dir, &entry, false)? {
+                let full_path = tmp_dir.join(&item);
+                print_debug(&format!("Checking {}: {}", item_type, full_path.display()));
+                if full_path.exists() && full_path.is_file() {
+                    if dry_run {
+                        print_info(&format!(
+                            "🔍 DRY RUN: Would delete {}: {}",
+                            item_type,
+                            item.display()
+                        ));
+                    } else {
+                        fs::remove_file(&full_path).with_context(|| {
+                            format!("Failed to delete {} '{}'", item_type, full_path.display())
+                        })?;
+                        print_info(&format!("🗑️  Deleted {}: {}", item_type, item.display()));
+                    }
                 }
             }
         }
@@ -86,29 +242,65 @@ pub fn handle_file_deletions(
     Ok(())
 }
 
-fn read_paths(file_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+/// Reads the paths listed in a deletion file. Lines between `[[ android >= 15 ]]`
+/// and `[[ end ]]` are only kept when `android_version` satisfies the condition.
+pub fn read_paths(file_path: &Path, android_version: u32) -> anyhow::Result<Vec<PathBuf>> {
     let content = fs::read_to_string(file_path)?;
+    parse_paths(&content, android_version)
+        .with_context(|| format!("Failed to parse '{}'", file_path.display()))
+}
+
+fn parse_paths(content: &str, android_version: u32) -> anyhow::Result<Vec<PathBuf>> {
     let mut paths = Vec::new();
-    for line in content.lines() {
+    let mut conditions: Vec<bool> = Vec::new();
+    for (number, line) in content.lines().enumerate() {
         let trimmed = line.trim();
-        if !trimmed.is_empty() {
+        if let Some(directive) = trimmed
+            .strip_prefix("[[")
+            .and_then(|rest| rest.strip_suffix("]]"))
+        {
+            let directive = directive.trim();
+            if directive == "end" {
+                if conditions.pop().is_none() {
+                    anyhow::bail!("line {}: '[[ end ]]' without an open block", number + 1);
+                }
+            } else if let Some(requirement) = directive.strip_prefix("android") {
+                conditions.push(android_version_matches(requirement, android_version));
+            } else {
+                anyhow::bail!("line {}: unknown condition '{}'", number + 1, directive);
+            }
+            continue;
+        }
+        if !trimmed.is_empty() && conditions.iter().all(|c| *c) {
             paths.push(PathBuf::from(trimmed));
         }
     }
+    if !conditions.is_empty() {
+        anyhow::bail!("missing '[[ end ]]' for {} block(s)", conditions.len());
+    }
     Ok(paths)
 }
 
-pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>, dry_run: bool) -> io::Result<()> {
+/// Copies a patch folder over `dst`, leaving out the files in `skip`. Files
+/// that already exist in `dst` are resolved with `strategy` (see
+/// [`handle_file_conflict`]). With `copied`, new files identical to ones
+/// copied before are hard linked to them.
+pub fn copy_dir_all(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    strategy: Option<&str>,
+    skip: &[PathBuf],
+    copied: Option<&CopiedFiles>,
+    dry_run: bool,
+) -> io::Result<()> {
     if dry_run {
         let mut file_count = 0;
         let mut dir_count = 0;
-        for entry in WalkDir::new(&src) {
-            if let Ok(entry) = entry {
-                if entry.file_type().is_file() {
-                    file_count += 1;
-                } else if entry.file_type().is_dir() {
-                    dir_count += 1;
-                }
+        for entry in WalkDir::new(&src).into_iter().flatten() {
+            if entry.file_type().is_file() {
+                file_count += 1;
+            } else if entry.file_type().is_dir() {
+                dir_count += 1;
             }
         }
         println!(
@@ -126,36 +318,830 @@ pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>, dry_run: bool)
         let entry = entry?;
         let ty = entry.file_type()?;
         if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()), dry_run)?;
+            copy_dir_all(
+                entry.path(),
+                dst.as_ref().join(entry.file_name()),
+                strategy,
+                skip,
+                copied,
+                dry_run,
+            )?;
+        } else {
+            if entry.file_name() != "patch.yaml" && !skip.contains(&entry.path()) {
+                let target = dst.as_ref().join(entry.file_name());
+                print_debug(&format!(
+                    "Copying {} -> {}",
+                    entry.path().display(),
+                    target.display()
+                ));
+                if target.exists() {
+                    handle_file_conflict(strategy.unwrap_or("override"), &target, &entry.path())?;
+                } else if let Some(copied) = copied {
+                    copy_file_dedup(&entry.path(), &target, copied)?;
+                } else {
+                    fs::copy(entry.path(), &target)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copies a file, or hard links it to an earlier copy with the same SHA-256
+/// that is unchanged since, so a library shipped by several patches is only
+/// written once. Falls back to copying when linking fails, e.g. across
+/// filesystems.
+fn copy_file_dedup(src: &Path, target: &Path, copied: &CopiedFiles) -> io::Result<()> {
+    let hash = crate::checksum::calculate_file_checksum(src).map_err(io::Error::other)?;
+    let mut copied = copied.0.lock().unwrap();
+    if let Some(previous) = copied.get(&hash)
+        && fs::metadata(&previous.path)
+            .is_ok_and(|m| m.len() == previous.len && m.modified().ok() == previous.modified)
+        && fs::hard_link(&previous.path, target).is_ok()
+    {
+        print_debug(&format!(
+            "Linked {} -> {} (same content)",
+            target.display(),
+            previous.path.display()
+        ));
+        return Ok(());
+    }
+    fs::copy(src, target)?;
+    let metadata = fs::metadata(target)?;
+    copied.insert(
+        hash,
+        CopiedFile {
+            path: target.to_path_buf(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        },
+    );
+    Ok(())
+}
+
+/// Gives a file hard linked by `copy_dir_all` its own copy, so writing to it
+/// in place doesn't change the files it is linked with
+pub fn unshare_file(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    let shared = {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(path).is_ok_and(|m| m.nlink() > 1)
+    };
+    #[cfg(not(unix))]
+    let shared = path.is_file();
+    if !shared {
+        return Ok(());
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".rommer-unshare");
+    fs::copy(path, &tmp)?;
+    fs::rename(&tmp, path)
+}
+
+/// Applies a single patch folder to an extracted ROM: copies its files and
+/// `.rommercp` renames, leaving out files whose `.rommerconditional`
+/// condition fails, merges the files in `.rommermerge` and `.rommerprop`,
+/// sets the modes in `.rommerchmod`, then processes `.rommerdel` and
+/// `.rommerfdel`. `copied` records the files copied into `work_dir` by the
+/// patches applied to it before.
+pub fn apply_patch(
+    patch_path: &Path,
+    work_dir: &Path,
+    strategy: Option<&str>,
+    config: &Config,
+    copied: &CopiedFiles,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let android_version = config.android_version;
+    let renames = read_copy_renames(patch_path, android_version)?;
+    let mut skip: Vec<PathBuf> = renames
+        .iter()
+        .map(|(src, _)| patch_path.join(src))
+        .collect();
+    skip.extend(PATCH_CONTROL_FILES.iter().map(|file| patch_path.join(file)));
+    skip.extend(
+        read_merges(patch_path)?
+            .into_iter()
+            .map(|merge| patch_path.join(merge.file)),
+    );
+    skip.extend(
+        read_prop_files(patch_path, android_version)?
+            .into_iter()
+            .map(|file| patch_path.join(file)),
+    );
+    for file in conditional_skips(patch_path, config)? {
+        print_info(&format!(
+            "🚫 Skipping {} (.rommerconditional)",
+            file.display()
+        ));
+        skip.push(patch_path.join(file));
+    }
+    copy_dir_all(patch_path, work_dir, strategy, &skip, Some(copied), dry_run)
+        .with_context(|| format!("Failed to copy patch folder '{}'", patch_path.display()))?;
+    handle_merges(patch_path, work_dir, dry_run)?;
+    handle_prop_merges(patch_path, work_dir, android_version, dry_run)?;
+    handle_copy_renames(patch_path, work_dir, android_version, dry_run)?;
+    handle_modes(patch_path, work_dir, dry_run)?;
+    handle_deletions(
+        patch_path,
+        work_dir,
+        ".rommerdel",
+        "directory",
+        android_version,
+        dry_run,
+    )?;
+    handle_file_deletions(
+        patch_path,
+        work_dir,
+        ".rommerfdel",
+        "file",
+        android_version,
+        dry_run,
+    )
+}
+
+/// Sets the modes listed in a patch's `.rommerchmod` on the patched files.
+/// Only on Unix; the flashed files get the modes the updater-script sets.
+pub fn handle_modes(patch_path: &Path, work_dir: &Path, dry_run: bool) -> anyhow::Result<()> {
+    for (path, mode) in crate::audit::read_modes(patch_path)? {
+        let target = work_dir.join(&path);
+        if !target.exists() {
+            print_warning(&format!(
+                ".rommerchmod entry '{}' is not in the patched ROM",
+                path
+            ));
+            continue;
+        }
+        if dry_run {
+            print_info(&format!(
+                "🔍 DRY RUN: Would set mode {:04o} on {}",
+                mode, path
+            ));
+            continue;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if target.is_file() {
+                unshare_file(&target)?;
+            }
+            fs::set_permissions(&target, fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to set the mode of '{}'", target.display()))?;
+        }
+        print_debug(&format!("Set mode {:04o} on {}", mode, path));
+    }
+    Ok(())
+}
+
+/// Contents of a patch's `.rommermerge`
+#[derive(Debug, Deserialize)]
+struct MergeFile {
+    merges: Vec<MergeOp>,
+}
+
+/// A patch file merged into the ROM's file at the same path instead of
+/// replacing it
+#[derive(Debug, Deserialize)]
+pub struct MergeOp {
+    pub file: PathBuf,
+    pub strategy: String,
+}
+
+/// Reads the merges listed in a patch's `.rommermerge`
+pub fn read_merges(patch_path: &Path) -> anyhow::Result<Vec<MergeOp>> {
+    let merge_path = patch_path.join(".rommermerge");
+    if !merge_path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&merge_path)
+        .with_context(|| format!("Failed to read '{}'", merge_path.display()))?;
+    let merges: MergeFile = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse '{}'", merge_path.display()))?;
+    Ok(merges.merges)
+}
+
+/// Merges the files listed in a patch's `.rommermerge` into `tmp_dir`. A file
+/// that is not in the ROM yet is copied as is.
+pub fn handle_merges(patch_path: &Path, tmp_dir: &Path, dry_run: bool) -> anyhow::Result<()> {
+    for merge in read_merges(patch_path)? {
+        let source = patch_path.join(&merge.file);
+        let target = tmp_dir.join(&merge.file);
+        let new = fs::read_to_string(&source)
+            .with_context(|| format!("Failed to read merge file '{}'", source.display()))?;
+        if dry_run {
+            print_info(&format!(
+                "🔍 DRY RUN: Would merge {} into {} ({})",
+                source.display(),
+                target.display(),
+                merge.strategy
+            ));
+            continue;
+        }
+        let merged = if target.exists() {
+            let existing = fs::read_to_string(&target)
+                .with_context(|| format!("Failed to read '{}'", target.display()))?;
+            merge_text(&merge.strategy, &existing, &new)
+                .with_context(|| format!("Failed to merge '{}'", merge.file.display()))?
         } else {
-            if entry.file_name() != "patch.yaml" {
-                fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
             }
+            new
+        };
+        print_debug(&format!(
+            "Merging {} -> {} ({})",
+            source.display(),
+            target.display(),
+            merge.strategy
+        ));
+        unshare_file(&target)?;
+        fs::write(&target, merged)
+            .with_context(|| format!("Failed to write '{}'", target.display()))?;
+    }
+    Ok(())
+}
+
+/// Property files listed in a patch's `.rommerprop`, relative to the patch
+pub fn read_prop_files(patch_path: &Path, android_version: u32) -> anyhow::Result<Vec<PathBuf>> {
+    let prop_path = patch_path.join(".rommerprop");
+    if !prop_path.exists() {
+        return Ok(Vec::new());
+    }
+    read_paths(&prop_path, android_version)
+}
+
+/// Merges the property files listed in a patch's `.rommerprop` into the
+/// ROM's files at the same paths, key by key. Keys set by the patch replace
+/// the ROM's values in place, new keys are appended, and everything else in
+/// the ROM's file, comments included, is kept.
+pub fn handle_prop_merges(
+    patch_path: &Path,
+    tmp_dir: &Path,
+    android_version: u32,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    for file in read_prop_files(patch_path, android_version)? {
+        let source = patch_path.join(&file);
+        let target = tmp_dir.join(&file);
+        let new = fs::read_to_string(&source)
+            .with_context(|| format!("Failed to read property file '{}'", source.display()))?;
+        if dry_run {
+            print_info(&format!(
+                "🔍 DRY RUN: Would merge properties from {} into {}",
+                source.display(),
+                target.display()
+            ));
+            continue;
         }
+        let existing = if target.exists() {
+            fs::read_to_string(&target)
+                .with_context(|| format!("Failed to read '{}'", target.display()))?
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            String::new()
+        };
+        print_debug(&format!(
+            "Merging properties {} -> {}",
+            source.display(),
+            target.display()
+        ));
+        unshare_file(&target)?;
+        fs::write(&target, merge_text("properties", &existing, &new)?)
+            .with_context(|| format!("Failed to write '{}'", target.display()))?;
     }
     Ok(())
 }
 
+/// Merges `new` into `existing`: `append` and `prepend` concatenate them,
+/// `unique-lines` appends the new lines that are not already present, and
+/// `properties` sets `key=value` lines, replacing existing values in place
+fn merge_text(strategy: &str, existing: &str, new: &str) -> anyhow::Result<String> {
+    let with_newline = |text: &str| {
+        if text.is_empty() || text.ends_with('\n') {
+            text.to_string()
+        } else {
+            format!("{}\n", text)
+        }
+    };
+    Ok(match strategy {
+        "append" => with_newline(existing) + new,
+        "prepend" => with_newline(new) + existing,
+        "unique-lines" => {
+            let mut lines: Vec<&str> = Vec::new();
+            for line in existing.lines().chain(new.lines()) {
+                if !lines.contains(&line) {
+                    lines.push(line);
+                }
+            }
+            lines.into_iter().map(|l| format!("{}\n", l)).collect()
+        }
+        "properties" => {
+            let property = |line: &str| {
+                let line = line.trim();
+                if line.starts_with('#') {
+                    return None;
+                }
+                line.split_once('=').map(|(key, _)| key.trim().to_string())
+            };
+            let mut updates: Vec<(String, &str)> = new
+                .lines()
+                .filter_map(|line| property(line).map(|key| (key, line)))
+                .collect();
+            let mut merged = String::new();
+            for line in existing.lines() {
+                let update =
+                    property(line).and_then(|key| updates.iter().position(|(k, _)| *k == key));
+                match update {
+                    Some(i) => merged.push_str(updates.remove(i).1),
+                    None => merged.push_str(line),
+                }
+                merged.push('\n');
+            }
+            for (_, line) in updates {
+                merged.push_str(line);
+                merged.push('\n');
+            }
+            merged
+        }
+        other => anyhow::bail!(
+            "Unknown merge strategy '{}' (expected append, prepend, unique-lines or properties)",
+            other
+        ),
+    })
+}
+
+/// One entry of a patch's `.rommerconditional`
+#[derive(Debug, Deserialize)]
+struct ConditionalFile {
+    file: PathBuf,
+    condition: String,
+}
+
+/// Patch files, relative to the patch folder, whose `.rommerconditional`
+/// condition does not hold for `config`
+pub fn conditional_skips(patch_path: &Path, config: &Config) -> anyhow::Result<Vec<PathBuf>> {
+    let cond_path = patch_path.join(".rommerconditional");
+    if !cond_path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&cond_path)
+        .with_context(|| format!("Failed to read '{}'", cond_path.display()))?;
+    let entries: Vec<ConditionalFile> = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse '{}'", cond_path.display()))?;
+    let mut skipped = Vec::new();
+    for entry in entries {
+        let holds = evaluate_condition(&entry.condition, config)
+            .with_context(|| format!("Invalid condition in '{}'", cond_path.display()))?;
+        if !holds {
+            skipped.push(entry.file);
+        }
+    }
+    Ok(skipped)
+}
+
+/// Evaluates a condition such as `variant == nightly && android_version != 14`
+/// against `device`, `variant` and `android_version`. `&&` binds tighter than
+/// `||`; values may be quoted.
+pub fn evaluate_condition(expr: &str, config: &Config) -> anyhow::Result<bool> {
+    let mut any = false;
+    for alternative in expr.split("||") {
+        let mut all = true;
+        for comparison in alternative.split("&&") {
+            let (lhs, rhs, equal) = if let Some((lhs, rhs)) = comparison.split_once("!=") {
+                (lhs, rhs, false)
+            } else if let Some((lhs, rhs)) = comparison.split_once("==") {
+                (lhs, rhs, true)
+            } else {
+                anyhow::bail!("Expected '==' or '!=' in '{}'", comparison.trim());
+            };
+            let actual = match lhs.trim() {
+                "device" => config.device.clone(),
+                "variant" => config.variant.clone(),
+                "android_version" => config.android_version.to_string(),
+                other => anyhow::bail!(
+                    "Unknown variable '{}', expected device, variant or android_version",
+                    other
+                ),
+            };
+            let expected = rhs.trim().trim_matches(|c| c == '"' || c == '\'');
+            all &= (actual == expected) == equal;
+        }
+        any |= all;
+    }
+    Ok(any)
+}
+
+/// Reads the `<src> -> <dst>` lines of a patch's `.rommercp`. Sources are
+/// relative to the patch folder, destinations to the ROM root.
+pub fn read_copy_renames(
+    patch_path: &Path,
+    android_version: u32,
+) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let cp_path = patch_path.join(".rommercp");
+    if !cp_path.exists() {
+        return Ok(Vec::new());
+    }
+    read_paths(&cp_path, android_version)?
+        .into_iter()
+        .map(|line| {
+            let line = line.to_string_lossy().to_string();
+            let (src, dst) = line.split_once("->").with_context(|| {
+                format!(
+                    "Invalid line '{}' in '{}', expected '<src> -> <dst>'",
+                    line,
+                    cp_path.display()
+                )
+            })?;
+            Ok((PathBuf::from(src.trim()), PathBuf::from(dst.trim())))
+        })
+        .collect()
+}
+
+/// Copies the files listed in a patch's `.rommercp` to their new names
+pub fn handle_copy_renames(
+    patch_path: &Path,
+    tmp_dir: &Path,
+    android_version: u32,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    for (src, dst) in read_copy_renames(patch_path, android_version)? {
+        let src_path = patch_path.join(&src);
+        if !src_path.is_file() {
+            anyhow::bail!(
+                "'{}' listed in .rommercp does not exist",
+                src_path.display()
+            );
+        }
+        if dry_run {
+            print_info(&format!(
+                "🔍 DRY RUN: Would copy {} -> {}",
+                src.display(),
+                dst.display()
+            ));
+            continue;
+        }
+        let target = tmp_dir.join(&dst);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        unshare_file(&target)?;
+        fs::copy(&src_path, &target).with_context(|| {
+            format!("Failed to copy '{}' to '{}'", src.display(), dst.display())
+        })?;
+        print_info(&format!("📋 Copied {} -> {}", src.display(), dst.display()));
+    }
+    Ok(())
+}
+
+/// Resolves a patch file `new_path` that would replace `existing_path`:
+/// `override` replaces it, `append` appends to it, `merge-lines` keeps the
+/// sorted, deduplicated lines of both, and `error` aborts
+pub fn handle_file_conflict(
+    strategy: &str,
+    existing_path: &Path,
+    new_path: &Path,
+) -> io::Result<()> {
+    print_debug(&format!(
+        "Resolving conflict on {} ({})",
+        existing_path.display(),
+        strategy
+    ));
+    unshare_file(existing_path)?;
+    match strategy {
+        "override" => {
+            fs::copy(new_path, existing_path)?;
+        }
+        "append" => {
+            let content = fs::read(new_path)?;
+            let mut file = fs::OpenOptions::new().append(true).open(existing_path)?;
+            file.write_all(&content)?;
+        }
+        "merge-lines" => {
+            let existing = fs::read_to_string(existing_path)?;
+            let new = fs::read_to_string(new_path)?;
+            fs::write(existing_path, merge_lines(&existing, &new))?;
+        }
+        "error" => {
+            return Err(io::Error::other(format!(
+                "'{}' already exists and file_conflict_strategy is 'error'",
+                existing_path.display()
+            )));
+        }
+        other => {
+            return Err(io::Error::other(format!(
+                "Unknown file_conflict_strategy '{}' (expected override, append, merge-lines or error)",
+                other
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Sorted, deduplicated union of the lines of two text files
+fn merge_lines(existing: &str, new: &str) -> String {
+    let lines: std::collections::BTreeSet<&str> = existing.lines().chain(new.lines()).collect();
+    let mut merged = lines.into_iter().collect::<Vec<_>>().join("\n");
+    merged.push('\n');
+    merged
+}
+
+/// Files inside a patch folder that configure the patch rather than being copied into the ROM
+pub const PATCH_CONTROL_FILES: [&str; 9] = [
+    "patch.yaml",
+    ".rommerdel",
+    ".rommerfdel",
+    ".rommercp",
+    ".rommerconditional",
+    ".rommerchmod",
+    ".rommermerge",
+    ".rommerprop",
+    crate::patchsig::SIGNATURE_FILE,
+];
+
+/// Lists the files of a patch relative to its folder, skipping control files
+pub fn patch_files(patch_path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(patch_path).into_iter().flatten() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(patch_path) else {
+            continue;
+        };
+        if PATCH_CONTROL_FILES.iter().any(|f| rel == Path::new(f)) {
+            continue;
+        }
+        files.push(rel.to_path_buf());
+    }
+    files
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Today's UTC date as `YYYY-MM-DD`
+pub fn today() -> String {
+    let (year, month, day) = civil_date(unix_now() / 86_400);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC timestamp
+pub fn rfc3339(secs: u64) -> String {
+    let (year, month, day) = civil_date(secs / 86_400);
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Converts days since the Unix epoch to a `(year, month, day)` civil date
+pub fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's days-to-civil algorithm, with eras of 400 years
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Whether every file of a patch already exists in `work_dir` with the same
+/// content. Patches without files, and patches with control files (deletions,
+/// copies, merges...) whose effect can't be checked this way, never match.
+pub fn patch_files_match(patch_path: &Path, work_dir: &Path) -> Result<bool> {
+    let has_operations = PATCH_CONTROL_FILES
+        .iter()
+        .filter(|f| **f != "patch.yaml" && **f != crate::patchsig::SIGNATURE_FILE)
+        .any(|f| patch_path.join(f).exists());
+    let files = patch_files(patch_path);
+    if has_operations || files.is_empty() {
+        return Ok(false);
+    }
+    for rel in files {
+        let target = work_dir.join(&rel);
+        if !target.is_file()
+            || crate::checksum::calculate_file_checksum(&patch_path.join(&rel))?
+                != crate::checksum::calculate_file_checksum(&target)?
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether a configured patch folder is referred to by `name`, either by its
+/// full path or by its folder name
+pub fn patch_matches(patch_folder: &str, name: &str) -> bool {
+    let folder = patch_folder.trim_end_matches('/');
+    let name = name.trim_end_matches('/');
+    folder == name || Path::new(folder).file_name().is_some_and(|f| f == name)
+}
+
+/// Moves the patches named in `order` to the front, in that order, keeping
+/// the configured order for the rest
+pub fn apply_patch_order(patches: &[String], order: &[String]) -> Vec<String> {
+    let mut ordered = Vec::new();
+    for name in order {
+        match patches
+            .iter()
+            .find(|p| patch_matches(p, name) && !ordered.contains(*p))
+        {
+            Some(patch) => ordered.push(patch.clone()),
+            None => print_warning(&format!(
+                "--patch-order: '{}' does not match any configured patch",
+                name
+            )),
+        }
+    }
+    let rest: Vec<String> = patches
+        .iter()
+        .filter(|p| !ordered.contains(p))
+        .cloned()
+        .collect();
+    ordered.extend(rest);
+    ordered
+}
+
+/// Counts the files of a patch by extension, sorted by count descending
+pub fn extension_stats(src: impl AsRef<Path>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for entry in WalkDir::new(&src).into_iter().flatten() {
+        if !entry.file_type().is_file() || entry.file_name() == "patch.yaml" {
+            continue;
+        }
+        let key = entry
+            .path()
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+            .unwrap_or_else(|| "other".to_string());
+        *counts.entry(key).or_default() += 1;
+    }
+    let mut stats: Vec<(String, usize)> = counts.into_iter().collect();
+    stats.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    stats
+}
+
+/// Total size in bytes of the files under a directory
+pub fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// A size in decimal megabytes or gigabytes, e.g. `3.2 GB`
+pub fn format_size_mb(bytes: u64) -> String {
+    let mb = bytes as f64 / 1_000_000.0;
+    if mb >= 1000.0 {
+        format!("{:.1} GB", mb / 1000.0)
+    } else {
+        format!("{:.1} MB", mb)
+    }
+}
+
+pub fn format_extension_stats(stats: &[(String, usize)]) -> String {
+    stats
+        .iter()
+        .map(|(ext, count)| format!("{}: {}", ext, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Prints the banner on stderr, so commands that print data for other tools
+/// (`patch deps-graph`, `config merge`, ...) can be piped
 pub fn print_banner() {
-    print_section("🔧 ROMMER");
+    tracing::info!(section = true, "🔧 ROMMER");
+    if shows_info() {
+        eprintln!("{}", section("🔧 ROMMER"));
+    }
+}
+
+/// Whether `ROMMER_NO_COLOR` is set, to print without ANSI colors and emoji
+pub fn no_color() -> bool {
+    static NO_COLOR: OnceLock<bool> = OnceLock::new();
+    *NO_COLOR.get_or_init(|| std::env::var_os("ROMMER_NO_COLOR").is_some_and(|v| !v.is_empty()))
+}
+
+/// Width of section separators: `ROMMER_TERM_WIDTH`, else the terminal
+/// width, else 80 columns, minus padding
+fn section_width() -> usize {
+    let width = std::env::var("ROMMER_TERM_WIDTH")
+        .ok()
+        .and_then(|w| w.trim().parse::<usize>().ok())
+        .or_else(|| {
+            console::Term::stdout()
+                .size_checked()
+                .map(|(_, cols)| cols.into())
+        })
+        .unwrap_or(80);
+    width.saturating_sub(2).max(10)
+}
+
+/// `msg` as printed: unchanged, or without emoji when `ROMMER_NO_COLOR` is set
+fn styled(msg: &str) -> String {
+    if no_color() {
+        strip_emoji(msg)
+    } else {
+        msg.to_string()
+    }
+}
+
+/// Removes emoji and the spaces that follow them
+fn strip_emoji(msg: &str) -> String {
+    let is_emoji = |c: char| {
+        matches!(c as u32,
+            0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x23E9..=0x23FA
+            | 0x2139 | 0x200D | 0xFE0F)
+    };
+    let mut out = String::with_capacity(msg.len());
+    let mut after_emoji = false;
+    for c in msg.chars() {
+        if is_emoji(c) {
+            after_emoji = true;
+        } else if !(after_emoji && c == ' ') {
+            after_emoji = false;
+            out.push(c);
+        }
+    }
+    out.trim_start().to_string()
 }
 
 pub fn print_section(title: &str) {
-    println!("\n{}", "─".repeat(22));
-    println!(" {}", title);
-    println!("{}", "─".repeat(22));
+    tracing::info!(section = true, "{}", title);
+    if shows_info() {
+        println!("{}", section(title));
+    }
+}
+
+/// `title` between separator lines, as printed by `print_section`
+fn section(title: &str) -> String {
+    let line = if no_color() { "-" } else { "─" }.repeat(section_width());
+    format!("\n{}\n {}\n{}", line, styled(title), line)
 }
 
 pub fn print_success(msg: &str) {
-    println!("✅ {}", msg);
+    tracing::info!(success = true, "{}", msg);
+    if !shows_info() {
+        return;
+    }
+    println!("{}", styled(&format!("✅ {}", msg)));
 }
 
 pub fn print_info(msg: &str) {
-    println!("ℹ️  {}", msg);
+    tracing::info!("{}", msg);
+    if !shows_info() {
+        return;
+    }
+    println!("{}", styled(&format!("ℹ️  {}", msg)));
+}
+
+/// `print_info` on stderr, for notes printed by commands whose stdout is
+/// meant to be captured
+pub fn eprint_info(msg: &str) {
+    tracing::info!("{}", msg);
+    if !shows_info() {
+        return;
+    }
+    eprintln!("{}", styled(&format!("ℹ️  {}", msg)));
+}
+
+pub fn print_debug(msg: &str) {
+    tracing::debug!("{}", msg);
+    if is_verbose() {
+        println!("{}", styled(&format!("🐛 {}", msg)));
+    }
 }
 
 pub fn print_warning(msg: &str) {
-    println!("⚠️  {}", msg);
+    tracing::warn!("{}", msg);
+    if log_level() > LogLevel::Warn {
+        return;
+    }
+    match output_level() {
+        OutputLevel::Normal => println!("{}", styled(&format!("⚠️  {}", msg))),
+        OutputLevel::Quiet => eprintln!("{}", styled(&format!("⚠️  {}", msg))),
+        OutputLevel::Silent => {}
+    }
 }
 
 pub fn android_version_matches(requirement: &str, current: u32) -> bool {
@@ -176,3 +1162,225 @@ pub fn android_version_matches(requirement: &str, current: u32) -> bool {
         true // invalid
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_evaluate_condition() -> Result<()> {
+        let config = crate::config::test_config();
+        assert!(evaluate_condition("variant == nightly", &config)?);
+        assert!(evaluate_condition(
+            "device == \"bluejay\" && android_version != 14",
+            &config
+        )?);
+        assert!(!evaluate_condition(
+            "variant == stable && device == bluejay",
+            &config
+        )?);
+        assert!(evaluate_condition(
+            "variant == stable || android_version == 15",
+            &config
+        )?);
+        assert!(evaluate_condition("rom == lineageos", &config).is_err());
+        assert!(evaluate_condition("variant nightly", &config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_lines() {
+        assert_eq!(
+            merge_lines("ro.b=2\nro.a=1\n", "ro.c=3\nro.a=1\n"),
+            "ro.a=1\nro.b=2\nro.c=3\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_patch_order() {
+        let patches = vec![
+            "patches/a/".to_string(),
+            "patches/b/".to_string(),
+            "patches/c/".to_string(),
+        ];
+        let order = vec![
+            "c".to_string(),
+            "missing".to_string(),
+            "patches/b".to_string(),
+        ];
+        assert_eq!(
+            apply_patch_order(&patches, &order),
+            vec!["patches/c/", "patches/b/", "patches/a/"]
+        );
+    }
+
+    #[test]
+    fn test_parse_paths_conditions() -> Result<()> {
+        let content = "system/app/A\n[[ android >= 15 ]]\nsystem/app/B\n[[ android < 16 ]]\nsystem/app/C\n[[ end ]]\n[[ end ]]\n\nsystem/app/D\n";
+        assert_eq!(
+            parse_paths(content, 14)?,
+            vec![PathBuf::from("system/app/A"), PathBuf::from("system/app/D")]
+        );
+        assert_eq!(parse_paths(content, 16)?.len(), 3);
+        assert_eq!(parse_paths(content, 15)?.len(), 4);
+        assert!(parse_paths("[[ android > 1 ]]\nsystem/app/A\n", 15).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extension_stats() -> Result<()> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join("system/lib"))?;
+        fs::write(dir.path().join("system/lib/a.so"), "")?;
+        fs::write(dir.path().join("system/lib/b.so"), "")?;
+        fs::write(dir.path().join("system/build.XML"), "")?;
+        fs::write(dir.path().join("system/README"), "")?;
+        fs::write(dir.path().join("patch.yaml"), "")?;
+        let stats = extension_stats(dir.path());
+        assert_eq!(format_extension_stats(&stats), ".so: 2, .xml: 1, other: 1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_files_match() -> Result<()> {
+        let patch = tempdir()?;
+        let rom = tempdir()?;
+        assert!(!patch_files_match(patch.path(), rom.path())?);
+        fs::create_dir_all(patch.path().join("system/etc"))?;
+        fs::create_dir_all(rom.path().join("system/etc"))?;
+        fs::write(patch.path().join("system/etc/hosts"), "127.0.0.1 ads")?;
+        fs::write(patch.path().join("patch.yaml"), "name: hosts\n")?;
+        assert!(!patch_files_match(patch.path(), rom.path())?);
+        fs::write(rom.path().join("system/etc/hosts"), "127.0.0.1 localhost")?;
+        assert!(!patch_files_match(patch.path(), rom.path())?);
+        fs::write(rom.path().join("system/etc/hosts"), "127.0.0.1 ads")?;
+        assert!(patch_files_match(patch.path(), rom.path())?);
+        // Its deletions still have to run
+        fs::write(patch.path().join(".rommerdel"), "system/app/Foo\n")?;
+        assert!(!patch_files_match(patch.path(), rom.path())?);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_handle_modes() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let patch = tempdir()?;
+        let rom = tempdir()?;
+        fs::create_dir_all(patch.path().join("system/bin"))?;
+        fs::write(patch.path().join("system/bin/daemon"), "#!/system/bin/sh")?;
+        fs::write(
+            patch.path().join(".rommerchmod"),
+            "0750 system/bin/daemon\n0644 system/bin/missing\n",
+        )?;
+        apply_patch(
+            patch.path(),
+            rom.path(),
+            None,
+            &crate::config::test_config(),
+            &CopiedFiles::default(),
+            false,
+        )?;
+        let mode = fs::metadata(rom.path().join("system/bin/daemon"))?
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o750);
+        assert!(!rom.path().join(".rommerchmod").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_prop_merges() -> Result<()> {
+        let patch = tempdir()?;
+        let rom = tempdir()?;
+        fs::create_dir_all(patch.path().join("system"))?;
+        fs::create_dir_all(rom.path().join("system"))?;
+        fs::write(patch.path().join(".rommerprop"), "system/build.prop\n")?;
+        fs::write(
+            patch.path().join("system/build.prop"),
+            "# branding\nro.product.brand=rommer\n#ro.debuggable=1\nro.custom=1\n",
+        )?;
+        fs::write(
+            rom.path().join("system/build.prop"),
+            "# build properties\nro.product.brand=google\nro.debuggable=0\n",
+        )?;
+        handle_prop_merges(patch.path(), rom.path(), 15, false)?;
+        assert_eq!(
+            fs::read_to_string(rom.path().join("system/build.prop"))?,
+            "# build properties\nro.product.brand=rommer\nro.debuggable=0\nro.custom=1\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_size_mb() {
+        assert_eq!(format_size_mb(3_200_000_000), "3.2 GB");
+        assert_eq!(format_size_mb(1_000 * 1_000_000), "1.0 GB");
+        assert_eq!(format_size_mb(250_000), "0.2 MB");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_dir_all_links_identical_files() -> anyhow::Result<()> {
+        use std::os::unix::fs::MetadataExt;
+        let (first, second, rom) = (tempdir()?, tempdir()?, tempdir()?);
+        for patch in [&first, &second] {
+            fs::create_dir_all(patch.path().join("system/lib"))?;
+            fs::write(patch.path().join("system/lib/libshared.so"), "shared")?;
+        }
+        fs::create_dir_all(second.path().join("vendor/lib"))?;
+        fs::rename(
+            second.path().join("system/lib/libshared.so"),
+            second.path().join("vendor/lib/libshared.so"),
+        )?;
+        let copied = CopiedFiles::default();
+        copy_dir_all(first.path(), rom.path(), None, &[], Some(&copied), false)?;
+        copy_dir_all(second.path(), rom.path(), None, &[], Some(&copied), false)?;
+        let (system, vendor) = (
+            rom.path().join("system/lib/libshared.so"),
+            rom.path().join("vendor/lib/libshared.so"),
+        );
+        assert_eq!(fs::metadata(&vendor)?.nlink(), 2);
+
+        // Writing to one of the links must leave the other one alone
+        fs::write(first.path().join("system/lib/libshared.so"), "patched")?;
+        copy_dir_all(first.path(), rom.path(), None, &[], Some(&copied), false)?;
+        assert_eq!(fs::read_to_string(&system)?, "patched");
+        assert_eq!(fs::read_to_string(&vendor)?, "shared");
+        assert_eq!(fs::metadata(&vendor)?.nlink(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_emoji() {
+        assert_eq!(strip_emoji("✅ 📦 Created: out.zip"), "Created: out.zip");
+        assert_eq!(
+            strip_emoji("ℹ️  [1/3] ⏱️ Patch applied"),
+            "[1/3] Patch applied"
+        );
+        assert_eq!(strip_emoji("old  →  new"), "old  →  new");
+    }
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(20_743), (2026, 10, 17));
+    }
+
+    #[test]
+    fn test_merge_text() -> Result<()> {
+        let existing = "# build\nro.a=1\nro.b=2\n";
+        let new = "ro.b=3\nro.c=4\n";
+        assert_eq!(
+            merge_text("properties", existing, new)?,
+            "# build\nro.a=1\nro.b=3\nro.c=4\n"
+        );
+        assert_eq!(merge_text("unique-lines", "b\na\n", "a\nc")?, "b\na\nc\n");
+        assert_eq!(merge_text("append", "a", "b\n")?, "a\nb\n");
+        assert_eq!(merge_text("prepend", "a\n", "b")?, "b\na\n");
+        assert!(merge_text("sideways", "", "").is_err());
+        Ok(())
+    }
+}
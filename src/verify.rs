@@ -0,0 +1,113 @@
+use crate::config::Config;
+use crate::utils;
+use anyhow::Context;
+use std::fs::File;
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Edify script recovery runs to flash a non-A/B ROM
+pub const UPDATER_SCRIPT: &str = "META-INF/com/google/android/updater-script";
+const UPDATE_BINARY: &str = "META-INF/com/google/android/update-binary";
+const AB_PAYLOAD: &str = "payload.bin";
+const AB_PAYLOAD_PROPERTIES: &str = "payload_properties.txt";
+
+/// Checks that a ROM ZIP can still be flashed: it needs a non-empty
+/// `updater-script`, or for A/B OTA packages a non-empty `payload.bin` with
+/// `payload_properties.txt` (sideloaded) or `update-binary` (recovery)
+pub fn verify_flashable(zip_path: &Path) -> anyhow::Result<()> {
+    let file = File::open(zip_path)
+        .with_context(|| format!("Failed to open zip file '{}'", zip_path.display()))?;
+    let mut archive = ZipArchive::new(file).context("Failed to read zip archive")?;
+    let mut non_empty = |name: &str| archive.by_name(name).is_ok_and(|entry| entry.size() > 0);
+    if non_empty(UPDATER_SCRIPT)
+        || (non_empty(AB_PAYLOAD) && (non_empty(AB_PAYLOAD_PROPERTIES) || non_empty(UPDATE_BINARY)))
+    {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "'{}' has no non-empty {} (or {} with {} or {} for A/B OTA packages) and won't flash",
+        zip_path.display(),
+        UPDATER_SCRIPT,
+        AB_PAYLOAD,
+        AB_PAYLOAD_PROPERTIES,
+        UPDATE_BINARY
+    )
+}
+
+/// Patches whose `.rommerdel`/`.rommerfdel` delete something under `META-INF/`
+pub fn meta_inf_deletions(config: &Config) -> Vec<String> {
+    let mut found = Vec::new();
+    for patch in &config.patches {
+        for del_file in [".rommerdel", ".rommerfdel"] {
+            let del_path = config.resolve_patch_path(patch).join(del_file);
+            let Ok(items) = utils::read_paths(&del_path, config.android_version) else {
+                continue;
+            };
+            for item in items {
+                if item.starts_with("META-INF") {
+                    found.push(format!(
+                        "{}/{}: {}",
+                        patch.trim_end_matches('/'),
+                        del_file,
+                        item.display()
+                    ));
+                }
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            zip.start_file(*name, FileOptions::<()>::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_verify_flashable() {
+        let dir = tempdir().unwrap();
+        let ok = dir.path().join("ok.zip");
+        write_zip(&ok, &[(UPDATER_SCRIPT, b"ui_print(\"hi\");")]);
+        assert!(verify_flashable(&ok).is_ok());
+
+        let ab = dir.path().join("ab.zip");
+        write_zip(
+            &ab,
+            &[(AB_PAYLOAD, b"CrAU"), (UPDATE_BINARY, b"#!/sbin/sh")],
+        );
+        assert!(verify_flashable(&ab).is_ok());
+
+        let ota = dir.path().join("ota.zip");
+        write_zip(
+            &ota,
+            &[
+                (AB_PAYLOAD, b"CrAU"),
+                (AB_PAYLOAD_PROPERTIES, b"FILE_HASH=abc"),
+            ],
+        );
+        assert!(verify_flashable(&ota).is_ok());
+
+        let payload_only = dir.path().join("payload_only.zip");
+        write_zip(&payload_only, &[(AB_PAYLOAD, b"CrAU")]);
+        assert!(verify_flashable(&payload_only).is_err());
+
+        let empty = dir.path().join("empty.zip");
+        write_zip(
+            &empty,
+            &[(UPDATER_SCRIPT, b""), ("system/build.prop", b"x")],
+        );
+        assert!(verify_flashable(&empty).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::utils;
+use anyhow::Context;
+use semver::Version;
+use serde::Deserialize;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/TheROMMER/core/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+pub async fn print_version(check: bool, offline: bool) -> anyhow::Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    utils::print_info(&format!("rommer v{}", current));
+    if utils::is_verbose() {
+        print_build_info();
+    }
+    if !check {
+        return Ok(());
+    }
+    if offline {
+        utils::print_info("Skipping update check (--offline)");
+        return Ok(());
+    }
+    if std::env::var("ROMMER_NO_UPDATE_CHECK").is_ok_and(|v| v == "1") {
+        utils::print_info("Skipping update check (ROMMER_NO_UPDATE_CHECK=1)");
+        return Ok(());
+    }
+
+    let release: Release = reqwest::Client::new()
+        .get(LATEST_RELEASE_URL)
+        .header(reqwest::header::USER_AGENT, format!("rommer/{}", current))
+        .send()
+        .await
+        .context("Failed to fetch the latest release")?
+        .error_for_status()
+        .context("Failed to fetch the latest release")?
+        .json()
+        .await
+        .context("Failed to parse the latest release")?;
+    let latest = Version::parse(release.tag_name.trim_start_matches('v'))
+        .with_context(|| format!("Invalid release tag '{}'", release.tag_name))?;
+    let installed = Version::parse(current)?;
+    if latest > installed {
+        utils::print_warning(&format!(
+            "Update available: v{} ({})",
+            latest, release.html_url
+        ));
+    } else {
+        utils::print_success("rommer is up to date");
+    }
+    Ok(())
+}
+
+/// Build details embedded by build.rs, for bug reports
+fn print_build_info() {
+    let unknown = |value: Option<&'static str>| value.unwrap_or("unknown");
+    println!("build date:  {}", unknown(option_env!("VERGEN_BUILD_DATE")));
+    println!(
+        "commit:      {} ({})",
+        unknown(option_env!("VERGEN_GIT_SHA")),
+        unknown(option_env!("VERGEN_GIT_COMMIT_DATE"))
+    );
+    println!(
+        "rustc:       {}",
+        unknown(option_env!("VERGEN_RUSTC_SEMVER"))
+    );
+    println!(
+        "target:      {}",
+        unknown(option_env!("VERGEN_CARGO_TARGET_TRIPLE"))
+    );
+}